@@ -1,10 +1,16 @@
 use merkle_root::calc::depth_walk::DepthWalk;
 use merkle_root::calc::hash;
 use merkle_root::calc::width_walk::WidthWalk;
-use merkle_root::source::SourceReader;
+#[cfg(feature = "mmap")]
+use merkle_root::mmap::MmapSourceReader;
+use merkle_root::source::{RecordReader, SourceReader};
+use merkle_root::Hash;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
 
 #[allow(unused_imports)]
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 
 fn depth_walk(c: &mut Criterion) {
     c.bench_function("depth_walk", |b| {
@@ -12,6 +18,7 @@ fn depth_walk(c: &mut Criterion) {
             let source_file = String::from("input.txt");
             let mut source = SourceReader::new(source_file)
                 .expect("Expected input.txt to be present")
+                .map(|leaf| leaf.expect("Expected input.txt to be valid"))
                 .peekable();
             DepthWalk::calculate(&mut source, &hash)
         })
@@ -24,11 +31,294 @@ fn width_walk(c: &mut Criterion) {
             let source_file = String::from("input.txt");
             let mut source = SourceReader::new(source_file)
                 .expect("Expected input.txt to be present")
+                .map(|leaf| leaf.expect("Expected input.txt to be valid"))
                 .peekable();
             WidthWalk::calculate(&mut source, &hash)
         })
     });
 }
 
-criterion_group!(benches, depth_walk, width_walk);
+///
+/// Size of the generated binary input, in 32 bytes records (256 MiB).
+const BINARY_RECORDS: u64 = 8 * 1024 * 1024;
+
+///
+/// Generates the binary input in the temporary directory once, returning its
+/// path.
+fn binary_input() -> PathBuf {
+    let path = std::env::temp_dir().join("merkle_root_bench.bin");
+    let size = BINARY_RECORDS * 32;
+    if std::fs::metadata(&path).map(|meta| meta.len()).ok() != Some(size) {
+        let bytes: Vec<u8> = (0..size).map(|byte| (byte % 251) as u8).collect();
+        std::fs::write(&path, bytes).expect("Expected the binary input to be written");
+    }
+    path
+}
+
+fn binary_readers(c: &mut Criterion) {
+    let path = binary_input();
+    let mut group = c.benchmark_group("binary_readers");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(BINARY_RECORDS * 32));
+    group.bench_function("read_exact", |b| {
+        b.iter(|| {
+            let mut reader = File::open(&path).unwrap();
+            let mut record: Hash = [0u8; 32];
+            let mut count = 0u64;
+            while reader.read_exact(&mut record).is_ok() {
+                black_box(&record);
+                count += 1;
+            }
+            count
+        })
+    });
+    group.bench_function("buffered_read_exact", |b| {
+        b.iter(|| {
+            let mut reader = BufReader::new(File::open(&path).unwrap());
+            let mut record: Hash = [0u8; 32];
+            let mut count = 0u64;
+            while reader.read_exact(&mut record).is_ok() {
+                black_box(&record);
+                count += 1;
+            }
+            count
+        })
+    });
+    group.bench_function("record_reader", |b| {
+        b.iter(|| {
+            let mut count = 0u64;
+            for record in RecordReader::open(path.to_string_lossy().into_owned()).unwrap() {
+                black_box(&record.unwrap());
+                count += 1;
+            }
+            count
+        })
+    });
+    group.finish();
+}
+
+///
+/// Generates the hex input of the given number of leaves in the temporary
+/// directory once, returning its path.
+fn hex_input(leaves: u64) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("merkle_root_bench_{leaves}.hex"));
+    if std::fs::metadata(&path).map(|meta| meta.len()).ok() != Some(leaves * 65) {
+        let mut out = std::io::BufWriter::new(File::create(&path).unwrap());
+        for i in 0..leaves {
+            let mut buf = [0u8; 64];
+            let leaf = hash(&i.to_le_bytes().repeat(4).try_into().unwrap(), None);
+            let hex = base16ct::lower::encode_str(&leaf, &mut buf).unwrap();
+            writeln!(out, "{hex}").expect("Expected the hex input to be written");
+        }
+    }
+    path
+}
+
+///
+/// Compares the buffered reader against the memory-mapped one (with the
+/// `mmap` feature) over the hex input. The number of leaves is taken from the
+/// `MERKLE_ROOT_BENCH_LEAVES` environment variable, 4Mi (260 MiB) by default;
+/// set it to 32Mi or more for a multi-gigabyte file.
+fn hex_readers(c: &mut Criterion) {
+    let leaves = std::env::var("MERKLE_ROOT_BENCH_LEAVES")
+        .ok()
+        .and_then(|leaves| leaves.parse().ok())
+        .unwrap_or(4 * 1024 * 1024);
+    let path = hex_input(leaves).to_string_lossy().into_owned();
+    let mut group = c.benchmark_group("hex_readers");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(leaves * 65));
+    group.bench_function("source_reader", |b| {
+        b.iter(|| {
+            let mut count = 0u64;
+            for leaf in SourceReader::new(path.clone()).unwrap() {
+                black_box(&leaf.unwrap());
+                count += 1;
+            }
+            count
+        })
+    });
+    #[cfg(feature = "mmap")]
+    group.bench_function("mmap_source_reader", |b| {
+        b.iter(|| {
+            let mut count = 0u64;
+            for leaf in MmapSourceReader::new(path.clone()).unwrap() {
+                black_box(&leaf.unwrap());
+                count += 1;
+            }
+            count
+        })
+    });
+    group.finish();
+}
+
+///
+/// Number of leaves of the generated input of the ordering benchmarks.
+const ORDERING_LEAVES: u32 = 1 << 18;
+
+///
+/// The splitmix64 generator, advancing the state and returning the next
+/// number, so the same seed always gives the same sequence.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+///
+/// Shuffles the leaves in place with the Fisher-Yates algorithm, driven by the
+/// splitmix64 generator, so the same seed always gives the same order.
+fn shuffle(leaves: &mut [Hash], seed: u64) {
+    let mut state = seed;
+    for i in (1..leaves.len()).rev() {
+        leaves.swap(i, (splitmix64(&mut state) % (i as u64 + 1)) as usize);
+    }
+}
+
+///
+/// Compares the width-walk over the leaves in their original order against
+/// the leaves shuffled with the seed from the `MERKLE_ROOT_BENCH_SEED`
+/// environment variable (criterion owns the command line arguments). The
+/// shuffled order changes the root; the shuffled-and-sorted one does not
+/// depend on the seed, but still moves the leaves in memory.
+fn leaf_ordering(c: &mut Criterion) {
+    let seed = std::env::var("MERKLE_ROOT_BENCH_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or(42);
+    let sequential: Vec<Hash> = (0..ORDERING_LEAVES)
+        .map(|i| {
+            let mut leaf = [0u8; 32];
+            leaf[..4].copy_from_slice(&i.to_le_bytes());
+            hash(&leaf, None)
+        })
+        .collect();
+    let mut shuffled = sequential.clone();
+    shuffle(&mut shuffled, seed);
+    let mut sorted = shuffled.clone();
+    sorted.sort_unstable();
+
+    let mut group = c.benchmark_group("leaf_ordering");
+    for (name, leaves) in [
+        ("sequential", &sequential),
+        ("shuffled", &shuffled),
+        ("shuffled_sorted", &sorted),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut source = leaves.iter().copied().peekable();
+                WidthWalk::calculate(&mut source, &hash)
+            })
+        });
+    }
+    group.finish();
+}
+
+///
+/// Number of leaves of the generated input of the walk_layers benchmarks.
+const WALK_LAYERS_LEAVES: u32 = 1 << 20;
+
+///
+/// Compares the width-walk allocating every level against the one reusing two
+/// buffers for the levels, over the leaves already in memory.
+fn walk_layers(c: &mut Criterion) {
+    let leaves: Vec<Hash> = (0..WALK_LAYERS_LEAVES)
+        .map(|i| {
+            let mut leaf = [0u8; 32];
+            leaf[..4].copy_from_slice(&i.to_le_bytes());
+            leaf
+        })
+        .collect();
+    let mut group = c.benchmark_group("walk_layers");
+    group.sample_size(20);
+    group.bench_function("collect", |b| {
+        b.iter(|| {
+            let mut source = leaves.iter().copied().peekable();
+            WidthWalk::calculate(&mut source, &hash)
+        })
+    });
+    group.bench_function("buffered", |b| {
+        b.iter(|| {
+            let mut source = leaves.iter().copied().peekable();
+            WidthWalk::calculate_buffered(&mut source, &hash)
+        })
+    });
+    group.finish();
+}
+
+///
+/// Compares the width-walk handing the pairs to rayon one by one (a chunk of
+/// two nodes) against the coarser chunks, over the leaves already in memory.
+fn min_parallel_chunk(c: &mut Criterion) {
+    let leaves: Vec<Hash> = (0..WALK_LAYERS_LEAVES)
+        .map(|i| {
+            let mut leaf = [0u8; 32];
+            leaf[..4].copy_from_slice(&i.to_le_bytes());
+            leaf
+        })
+        .collect();
+    let mut group = c.benchmark_group("min_parallel_chunk");
+    group.sample_size(20);
+    for chunk in [2, 64, 1024, 16 * 1024] {
+        group.bench_function(chunk.to_string(), |b| {
+            b.iter(|| {
+                let mut source = leaves.iter().copied().peekable();
+                WidthWalk::try_calculate_chunked(&mut source, chunk, &hash)
+            })
+        });
+    }
+    group.finish();
+}
+
+///
+/// Generates the given number of pseudo-random leaves in memory.
+fn random_leaves(leaves: usize, seed: u64) -> Vec<Hash> {
+    let mut state = seed;
+    (0..leaves)
+        .map(|_| {
+            let mut leaf = [0u8; 32];
+            for word in leaf.chunks_exact_mut(8) {
+                word.copy_from_slice(&splitmix64(&mut state).to_le_bytes());
+            }
+            leaf
+        })
+        .collect()
+}
+
+///
+/// Runs both algorithms over 1Ki to 1Mi random leaves already in memory, so
+/// the point the width-walk overtakes the depth-walk shows up in one run.
+fn scaling(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scaling");
+    group.sample_size(10);
+    for leaves in [1 << 10, 10 << 10, 100 << 10, 1 << 20] {
+        let input = random_leaves(leaves, 42);
+        group.throughput(Throughput::Elements(leaves as u64));
+        group.bench_with_input(
+            BenchmarkId::new("depth_walk", leaves),
+            &input,
+            |b, input| b.iter(|| DepthWalk::calculate_from_slice(input, &hash)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("width_walk", leaves),
+            &input,
+            |b, input| b.iter(|| WidthWalk::calculate_from_slice(input, &hash)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    depth_walk,
+    width_walk,
+    binary_readers,
+    hex_readers,
+    leaf_ordering,
+    walk_layers,
+    min_parallel_chunk,
+    scaling
+);
 criterion_main!(benches);
@@ -0,0 +1,255 @@
+//!
+//! Implements the asynchronous reader of the hashes over tokio, available with
+//! the `tokio` feature.
+//!
+//! The lines are framed and decoded the same way
+//! [`SourceReader`](crate::source::SourceReader) does, only the reads yield to
+//! the runtime instead of blocking the thread, so a socket or a pipe is read
+//! without `spawn_blocking`.
+//!
+//! Pros: no blocking thread per stream, any `AsyncBufRead` source.
+//!
+//! Cons: no algorithm consumes the stream directly, the leaves are collected
+//! (or pushed to the
+//! [`MerkleAccumulator`](crate::calc::accumulator::MerkleAccumulator)) as they
+//! are read.
+//!
+//! Use-cases: services already running on tokio, reading the hashes off the
+//! network.
+
+use crate::error::MerkleError;
+use crate::source::{is_comment, Encoding, DEFAULT_MAX_LINE_BYTES};
+use crate::Hash;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+///
+/// The asynchronous reader of the source with one hash per line, yielding the
+/// same items as [`SourceReader`](crate::source::SourceReader).
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::async_source::AsyncSourceReader;
+/// use merkle_root::calc::accumulator::MerkleAccumulator;
+/// use merkle_root::calc::hash;
+///
+/// let hashes = format!("{}\n{}\n", "00".repeat(32), "01".repeat(32));
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// let root = runtime.block_on(async {
+///     let mut reader = AsyncSourceReader::new(hashes.as_bytes());
+///     let mut accumulator = MerkleAccumulator::new();
+///     while let Some(leaf) = reader.next_hash().await {
+///         accumulator.push(leaf.unwrap());
+///     }
+///     accumulator.root()
+/// });
+///
+/// assert_eq!(Some(hash(&[0u8; 32], Some(&[1u8; 32]))), root);
+/// ```
+#[derive(Debug)]
+pub struct AsyncSourceReader<R> {
+    reader: R,
+    encoding: Encoding,
+    max_line_bytes: usize,
+    skip_comments: bool,
+    keep_going: bool,
+    // set once the iteration ended on an error
+    fused: bool,
+    // the line being read, reused across the lines
+    buf: Vec<u8>,
+    // number of lines read so far
+    line: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncSourceReader<R> {
+    ///
+    /// Creates a new reader of the buffered asynchronous source, e.g. a
+    /// `tokio::io::BufReader` over a socket.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            encoding: Encoding::default(),
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            skip_comments: false,
+            keep_going: false,
+            fused: false,
+            buf: Vec::with_capacity(Encoding::Base16.width() + 2),
+            line: 0,
+        }
+    }
+
+    ///
+    /// Sets the encoding of the hashes.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    ///
+    /// Sets the maximum length of a line in bytes, see
+    /// [`SourceReader::with_max_line_bytes`](crate::source::SourceReader::with_max_line_bytes).
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = max_line_bytes;
+        self
+    }
+
+    ///
+    /// Sets whether the blank lines and the comment lines are skipped, see
+    /// [`SourceReader::with_skip_comments`](crate::source::SourceReader::with_skip_comments).
+    pub fn with_skip_comments(mut self, skip_comments: bool) -> Self {
+        self.skip_comments = skip_comments;
+        self
+    }
+
+    ///
+    /// Sets whether the reader moves on past the lines that fail to decode,
+    /// see [`SourceReader::with_keep_going`](crate::source::SourceReader::with_keep_going).
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    ///
+    /// Reads the next hash, `None` once the source ends, or after the first
+    /// error unless asked to keep going.
+    pub async fn next_hash(&mut self) -> Option<Result<Hash, MerkleError>> {
+        if self.fused {
+            return None;
+        }
+        let leaf = self.read_leaf().await?;
+        if let Err(error) = &leaf {
+            let framing = matches!(error, MerkleError::Io(_) | MerkleError::LineTooLong { .. });
+            self.fused = framing || !self.keep_going;
+        }
+        Some(leaf)
+    }
+
+    ///
+    /// Reads and decodes the next line, skipping the comments if asked to.
+    async fn read_leaf(&mut self) -> Option<Result<Hash, MerkleError>> {
+        loop {
+            self.buf.clear();
+            self.line += 1;
+            match read_line_bounded(
+                &mut self.reader,
+                &mut self.buf,
+                self.max_line_bytes,
+                self.line,
+            )
+            .await
+            {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(error) => return Some(Err(error)),
+            }
+            if !(self.skip_comments && is_comment(&self.buf)) {
+                break;
+            }
+        }
+        Some(self.encoding.decode_line(&self.buf, self.line))
+    }
+}
+
+///
+/// Reads the line the same way [`crate::source::read_line_bounded`] does,
+/// yielding to the runtime while the source has no data.
+async fn read_line_bounded<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+    line: usize,
+) -> Result<usize, MerkleError> {
+    let mut read = 0;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(read);
+        }
+        let (chunk, done) = match available.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => (&available[..=newline], true),
+            None => (available, false),
+        };
+        let len = chunk.len();
+        if read + len > max {
+            return Err(MerkleError::LineTooLong { line });
+        }
+        buf.extend_from_slice(chunk);
+        reader.consume(len);
+        read += len;
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SourceReader;
+    use std::io::Cursor;
+
+    const LEAVES: &str = "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053\n\
+        915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723\n\
+        7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646\n";
+
+    async fn read_all<R: AsyncBufRead + Unpin>(
+        mut reader: AsyncSourceReader<R>,
+    ) -> Vec<Result<Hash, MerkleError>> {
+        let mut leaves = Vec::new();
+        while let Some(leaf) = reader.next_hash().await {
+            leaves.push(leaf);
+        }
+        leaves
+    }
+
+    #[tokio::test]
+    async fn same_as_source_reader() {
+        let crlf = LEAVES.replace('\n', "\r\n");
+        let short = LEAVES.replacen("77d5", "77d", 1);
+        let comments = format!("# leaves\n\n{LEAVES}");
+        for contents in [
+            LEAVES,
+            &crlf,
+            &LEAVES[..LEAVES.len() - 1],
+            &short,
+            &comments,
+        ] {
+            for (skip_comments, keep_going) in [(false, false), (true, true)] {
+                let reader = AsyncSourceReader::new(contents.as_bytes())
+                    .with_skip_comments(skip_comments)
+                    .with_keep_going(keep_going);
+                let sync = SourceReader::from_reader(Cursor::new(contents.to_string()))
+                    .with_skip_comments(skip_comments)
+                    .with_keep_going(keep_going);
+                let leaves = read_all(reader).await;
+                let expected: Vec<_> = sync.collect();
+                assert_eq!(expected.len(), leaves.len());
+                for (leaf, expected) in leaves.iter().zip(&expected) {
+                    assert_eq!(expected.as_ref().ok(), leaf.as_ref().ok());
+                    assert_eq!(
+                        expected.as_ref().err().map(ToString::to_string),
+                        leaf.as_ref().err().map(ToString::to_string)
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn line_too_long() {
+        let reader = AsyncSourceReader::new(LEAVES.as_bytes()).with_max_line_bytes(64);
+        let leaves = read_all(reader.with_keep_going(true)).await;
+        // the rest of the line is left unread, so the reader ends
+        assert!(matches!(
+            leaves.as_slice(),
+            [Err(MerkleError::LineTooLong { line: 1 })]
+        ));
+    }
+
+    #[tokio::test]
+    async fn base64() {
+        let contents = format!("{}=\n", "A".repeat(43));
+        let reader = AsyncSourceReader::new(contents.as_bytes()).with_encoding(Encoding::Base64);
+        assert_eq!([0u8; 32], read_all(reader).await.pop().unwrap().unwrap());
+    }
+}
@@ -0,0 +1,526 @@
+//!
+//! Implements an incremental merkle tree root calculation.
+//!
+//! Time complexity: O(n) total, amortized O(1) per leaf, O(log(n)) per root
+//! Space complexity: O(log(n))
+//!
+//! The accumulator keeps the peaks: the roots of the complete subtrees the
+//! leaves pushed so far are split into, from the highest to the lowest
+//!
+//! lvl2       abcd
+//!           /   |
+//! lvl1     ab  cd    ef
+//!         / | / |   / |
+//! lvl0    a b c d   e f  g
+//!
+//! peaks: (2, abcd), (1, ef), (0, g)
+//!
+//! Pushing a leaf appends it as a peak of height 0, merging the two last peaks
+//! while they have the same height. The root is calculated by folding the
+//! peaks from right to left, lifting the running node up to the height of the
+//! next peak by hashing it with itself, the same way the full tree would, e.g.
+//! the root of the peaks above is abcd + (ef + gg) = abcdefgg.
+//!
+//! The peaks are the peaks of the Merkle Mountain Range (MMR) over the leaves,
+//! so the accumulator also calculates the MMR root, bagging the peaks from
+//! right to left without any lifting, e.g. abcd + (ef + g) = abcdefg, see
+//! [`MerkleAccumulator::bagged_root`].
+//!
+//! The peaks can be saved and loaded, so the calculation can be resumed. The
+//! saved peaks of the first M leaves also serve as a frontier checkpoint: an
+//! append-only log extending these leaves must reproduce the same peaks after
+//! M leaves, see [`VerifyFrontier`].
+//!
+//! The root after each pushed leaf is the root of every prefix of the leaves,
+//! e.g. the tree heads a transparency log signs, see [`PrefixRoots`]. Each
+//! root costs O(log(n)) hashes on top of the amortized O(1) push.
+//!
+//! Pros: streaming, low space complexity, the root is available at any time.
+//!
+//! Cons: impossible to calculate parts of the tree in parallel.
+//!
+//! Use-cases: append-only logs, resumable calculations over large files.
+
+#[cfg(feature = "std")]
+use super::decode_hex;
+use super::hash;
+use crate::error::MerkleError;
+#[cfg(feature = "std")]
+use crate::hex::HexHash;
+use crate::Hash;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Write};
+
+///
+/// The incremental merkle tree root calculator.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{accumulator::MerkleAccumulator, hash};
+///
+/// let mut accumulator = MerkleAccumulator::new();
+/// accumulator.push([0u8; 32]);
+/// accumulator.push([1u8; 32]);
+///
+/// assert_eq!(Some(hash(&[0u8; 32], Some(&[1u8; 32]))), accumulator.root());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MerkleAccumulator<F = fn(&Hash, Option<&Hash>) -> Hash> {
+    peaks: Vec<(u64, Hash)>,
+    hash_fn: F,
+}
+
+impl MerkleAccumulator {
+    ///
+    /// Creates an empty accumulator with the default [`hash`] function.
+    pub fn new() -> Self {
+        Self {
+            peaks: Vec::new(),
+            hash_fn: hash,
+        }
+    }
+
+    ///
+    /// Creates an accumulator from the previously saved peaks. The heights of
+    /// the peaks must be strictly decreasing, and the number of leaves they
+    /// cover must fit in `u64`.
+    pub fn from_peaks(peaks: Vec<(u64, Hash)>) -> Result<Self, MerkleError> {
+        if peaks.windows(2).any(|pair| pair[0].0 <= pair[1].0) {
+            return Err(MerkleError::BadState {
+                reason: "peak heights must be strictly decreasing".to_string(),
+            });
+        }
+        if let Some((height, _)) = peaks.iter().find(|(height, _)| *height >= u64::BITS as u64) {
+            return Err(MerkleError::BadState {
+                reason: format!("peak height {height} is out of range"),
+            });
+        }
+        if checked_leaf_count(&peaks).is_none() {
+            return Err(MerkleError::BadState {
+                reason: "number of leaves is out of range".to_string(),
+            });
+        }
+        Ok(Self {
+            peaks,
+            hash_fn: hash,
+        })
+    }
+
+    ///
+    /// Loads the accumulator saved with [`MerkleAccumulator::save`].
+    #[cfg(feature = "std")]
+    pub fn load<R: BufRead>(input: R) -> Result<Self, MerkleError> {
+        let mut peaks = Vec::new();
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            let (height, hex) = line.split_once(' ').ok_or(MerkleError::BadState {
+                reason: format!("line {}: expected height and hash", index + 1),
+            })?;
+            let height = height.parse().map_err(|_| MerkleError::BadState {
+                reason: format!("line {}: invalid height {height:?}", index + 1),
+            })?;
+            peaks.push((height, decode_hex(hex, index + 1)?));
+        }
+        Self::from_peaks(peaks)
+    }
+}
+
+impl Default for MerkleAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> MerkleAccumulator<F>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    ///
+    /// Replaces the hash function of the accumulator, keeping its peaks.
+    pub fn with_hash_fn<G>(self, hash_fn: G) -> MerkleAccumulator<G>
+    where
+        G: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        MerkleAccumulator {
+            peaks: self.peaks,
+            hash_fn,
+        }
+    }
+
+    ///
+    /// Appends the leaf to the tree.
+    pub fn push(&mut self, leaf: Hash) {
+        let mut node = (0, leaf);
+        while let Some(&(height, peak)) = self.peaks.last() {
+            if height != node.0 {
+                break;
+            }
+            self.peaks.pop();
+            node = (height + 1, (self.hash_fn)(&peak, Some(&node.1)));
+        }
+        self.peaks.push(node);
+    }
+
+    ///
+    /// Calculates the root of the leaves pushed so far, `None` if there are
+    /// none.
+    pub fn root(&self) -> Option<Hash> {
+        let mut peaks = self.peaks.iter().rev();
+        let (mut height, mut root) = *peaks.next()?;
+        for (peak_height, peak) in peaks {
+            while height < *peak_height {
+                root = (self.hash_fn)(&root, None);
+                height += 1;
+            }
+            root = (self.hash_fn)(peak, Some(&root));
+            height += 1;
+        }
+        Some(root)
+    }
+
+    ///
+    /// Calculates the MMR root of the leaves pushed so far, `None` if there
+    /// are none. The peaks are bagged from right to left, each peak being the
+    /// left branch of the running node, i.e. `hash(p0, hash(p1, p2))`.
+    ///
+    /// Unlike [`MerkleAccumulator::root`], the root differs from the one of
+    /// the full tree, unless the number of leaves is a power of two.
+    pub fn bagged_root(&self) -> Option<Hash> {
+        let mut peaks = self.peaks.iter().rev();
+        let (_, mut root) = *peaks.next()?;
+        for (_, peak) in peaks {
+            root = (self.hash_fn)(peak, Some(&root));
+        }
+        Some(root)
+    }
+
+    ///
+    /// Returns the peaks as pairs of height and hash, highest first.
+    pub fn peaks(&self) -> &[(u64, Hash)] {
+        &self.peaks
+    }
+
+    ///
+    /// Returns the number of leaves pushed so far.
+    pub fn leaf_count(&self) -> u64 {
+        // the peaks are checked on loading, saturated just in case
+        checked_leaf_count(&self.peaks).unwrap_or(u64::MAX)
+    }
+
+    ///
+    /// Saves the peaks, one per line, as the height and the base16 hash
+    /// separated by a space.
+    #[cfg(feature = "std")]
+    pub fn save<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        for (height, peak) in &self.peaks {
+            writeln!(out, "{height} {}", HexHash(*peak))?;
+        }
+        Ok(())
+    }
+}
+
+impl<F> Extend<Hash> for MerkleAccumulator<F>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    fn extend<T: IntoIterator<Item = Hash>>(&mut self, iter: T) {
+        for leaf in iter {
+            self.push(leaf);
+        }
+    }
+}
+
+///
+/// Returns the number of leaves covered by the peaks, `None` on overflow.
+fn checked_leaf_count(peaks: &[(u64, Hash)]) -> Option<u64> {
+    peaks.iter().try_fold(0u64, |count, (height, _)| {
+        let leaves = u32::try_from(*height)
+            .ok()
+            .and_then(|height| 1u64.checked_shl(height))?;
+        count.checked_add(leaves)
+    })
+}
+
+///
+/// The iterator adapter, verifying the leaves of the wrapped fallible iterator
+/// extend the frontier: the peaks of the first M leaves must be equal to the
+/// peaks of the frontier covering M leaves. Yields
+/// [`MerkleError::FrontierMismatch`] if they diverge, and
+/// [`MerkleError::LeafCountMismatch`] if the source has less than M leaves.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::accumulator::{MerkleAccumulator, VerifyFrontier};
+///
+/// let mut frontier = MerkleAccumulator::new();
+/// frontier.extend([[0u8; 32], [1u8; 32]]);
+/// let leaves = [[0u8; 32], [1u8; 32], [2u8; 32]].into_iter().map(Ok);
+///
+/// assert!(VerifyFrontier::new(leaves, frontier).all(|leaf| leaf.is_ok()));
+/// ```
+pub struct VerifyFrontier<I, F = fn(&Hash, Option<&Hash>) -> Hash> {
+    inner: I,
+    frontier: Vec<(u64, Hash)>,
+    size: u64,
+    running: Option<MerkleAccumulator<F>>,
+}
+
+impl<I, F> VerifyFrontier<I, F>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash + Clone,
+{
+    ///
+    /// Creates the adapter, calculating the running peaks with the hash
+    /// function of the frontier.
+    pub fn new(inner: I, frontier: MerkleAccumulator<F>) -> Self {
+        let size = frontier.leaf_count();
+        let running = MerkleAccumulator {
+            peaks: Vec::new(),
+            hash_fn: frontier.hash_fn.clone(),
+        };
+        Self {
+            inner,
+            frontier: frontier.peaks,
+            size,
+            running: (size > 0).then_some(running),
+        }
+    }
+}
+
+impl<I, F> Iterator for VerifyFrontier<I, F>
+where
+    I: Iterator<Item = Result<Hash, MerkleError>>,
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next();
+        let Some(running) = &mut self.running else {
+            return item;
+        };
+        match item {
+            Some(Ok(leaf)) => {
+                running.push(leaf);
+                if running.leaf_count() < self.size {
+                    return Some(Ok(leaf));
+                }
+                let consistent = running.peaks == self.frontier;
+                self.running = None;
+                match consistent {
+                    true => Some(Ok(leaf)),
+                    false => Some(Err(MerkleError::FrontierMismatch { leaves: self.size })),
+                }
+            }
+            None => {
+                let got = running.leaf_count() as usize;
+                self.running = None;
+                Some(Err(MerkleError::LeafCountMismatch {
+                    expected: self.size as usize,
+                    got,
+                }))
+            }
+            error => error,
+        }
+    }
+}
+
+///
+/// The iterator adapter, pushing the leaves of the wrapped iterator to the
+/// accumulator and yielding the root after each of them, i.e. the roots of the
+/// prefixes of 1, 2, ..., n leaves. The accumulator may already hold the
+/// leaves preceding the wrapped ones.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::accumulator::{MerkleAccumulator, PrefixRoots};
+/// use merkle_root::calc::hash;
+///
+/// let leaves = [[0u8; 32], [1u8; 32]];
+/// let roots: Vec<_> = PrefixRoots::new(leaves.into_iter(), MerkleAccumulator::new()).collect();
+///
+/// assert_eq!(vec![[0u8; 32], hash(&[0u8; 32], Some(&[1u8; 32]))], roots);
+/// ```
+pub struct PrefixRoots<I, F = fn(&Hash, Option<&Hash>) -> Hash> {
+    inner: I,
+    accumulator: MerkleAccumulator<F>,
+}
+
+impl<I, F> PrefixRoots<I, F> {
+    pub fn new(inner: I, accumulator: MerkleAccumulator<F>) -> Self {
+        Self { inner, accumulator }
+    }
+
+    ///
+    /// Returns the accumulator holding the leaves pushed so far.
+    pub fn accumulator(&self) -> &MerkleAccumulator<F> {
+        &self.accumulator
+    }
+}
+
+impl<I, F> Iterator for PrefixRoots<I, F>
+where
+    I: Iterator<Item = Hash>,
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    type Item = Hash;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.accumulator.push(self.inner.next()?);
+        self.accumulator.root()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::depth_walk::DepthWalk;
+
+    fn leaves(n: u8) -> Vec<Hash> {
+        (0..n).map(|byte| [byte; 32]).collect()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(None, MerkleAccumulator::new().root());
+    }
+
+    #[test]
+    fn same_as_depth_walk() {
+        // the running root after each push matches the root of the prefix
+        let mut accumulator = MerkleAccumulator::new();
+        for n in 1..=16 {
+            accumulator.push([n - 1; 32]);
+            let mut source = leaves(n).into_iter().peekable();
+            assert_eq!(
+                Some(DepthWalk::calculate(&mut source, &hash)),
+                accumulator.root()
+            );
+            assert_eq!(n as u64, accumulator.leaf_count());
+        }
+    }
+
+    #[test]
+    fn prefix_roots() {
+        let roots: Vec<Hash> =
+            PrefixRoots::new(leaves(16).into_iter(), MerkleAccumulator::new()).collect();
+        assert_eq!(16, roots.len());
+        for (n, root) in (1..=16).zip(roots) {
+            let mut source = leaves(n).into_iter().peekable();
+            assert_eq!(DepthWalk::calculate(&mut source, &hash), root);
+        }
+
+        // resumed after the first 5 leaves, the prefixes still start at leaf 0
+        let mut accumulator = MerkleAccumulator::new();
+        accumulator.extend(leaves(5));
+        let mut prefix_roots = PrefixRoots::new(leaves(7).into_iter().skip(5), accumulator);
+        let mut source = leaves(6).into_iter().peekable();
+        assert_eq!(
+            Some(DepthWalk::calculate(&mut source, &hash)),
+            prefix_roots.next()
+        );
+        assert!(prefix_roots.next().is_some());
+        assert!(prefix_roots.next().is_none());
+        assert_eq!(7, prefix_roots.accumulator().leaf_count());
+    }
+
+    #[test]
+    fn split_and_resume() {
+        let mut whole = MerkleAccumulator::new();
+        whole.extend(leaves(13));
+        for split in 0..=13 {
+            let mut first = MerkleAccumulator::new();
+            first.extend(leaves(13).into_iter().take(split));
+            let mut resumed = MerkleAccumulator::from_peaks(first.peaks().to_vec()).unwrap();
+            resumed.extend(leaves(13).into_iter().skip(split));
+            assert_eq!(whole.root(), resumed.root());
+            assert_eq!(whole.bagged_root(), resumed.bagged_root());
+            assert_eq!(whole.peaks(), resumed.peaks());
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut accumulator = MerkleAccumulator::new();
+        accumulator.extend(leaves(7));
+        assert_eq!(3, accumulator.peaks().len());
+
+        let mut saved = Vec::new();
+        accumulator.save(&mut saved).unwrap();
+        let mut loaded = MerkleAccumulator::load(saved.as_slice()).unwrap();
+        assert_eq!(accumulator.peaks(), loaded.peaks());
+
+        loaded.push([7u8; 32]);
+        accumulator.push([7u8; 32]);
+        assert_eq!(accumulator.root(), loaded.root());
+
+        assert!(MerkleAccumulator::from_peaks(vec![(0, [0u8; 32]), (1, [1u8; 32])]).is_err());
+        assert!(MerkleAccumulator::load("x 00".as_bytes()).is_err());
+
+        // the heights of 64 and above would overflow the leaf count
+        let peak = "00".repeat(32);
+        for height in [64, 70, u64::MAX] {
+            let state = format!("{height} {peak}\n");
+            assert!(matches!(
+                MerkleAccumulator::load(state.as_bytes()),
+                Err(MerkleError::BadState { .. })
+            ));
+        }
+        let state = format!("63 {peak}\n62 {peak}\n");
+        let loaded = MerkleAccumulator::load(state.as_bytes()).unwrap();
+        assert_eq!(3 << 62, loaded.leaf_count());
+    }
+
+    #[test]
+    fn bagged_root() {
+        let leaves = leaves(3);
+        let mut accumulator = MerkleAccumulator::new();
+        accumulator.extend(leaves.clone());
+        let ab = hash(&leaves[0], Some(&leaves[1]));
+        assert_eq!(&[(1, ab), (0, leaves[2])], accumulator.peaks());
+        assert_eq!(Some(hash(&ab, Some(&leaves[2]))), accumulator.bagged_root());
+
+        accumulator.push([3u8; 32]);
+        assert_eq!(accumulator.root(), accumulator.bagged_root());
+        assert_eq!(None, MerkleAccumulator::new().bagged_root());
+    }
+
+    #[test]
+    fn verify_frontier() {
+        let verify = |frontier: &MerkleAccumulator, leaves: Vec<Hash>| {
+            VerifyFrontier::new(leaves.into_iter().map(Ok), frontier.clone())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let mut frontier = MerkleAccumulator::new();
+        frontier.extend(leaves(5));
+
+        assert_eq!(leaves(9), verify(&frontier, leaves(9)).unwrap());
+        assert_eq!(leaves(5), verify(&frontier, leaves(5)).unwrap());
+
+        let mut tampered = leaves(9);
+        tampered[2] = [42u8; 32];
+        assert!(matches!(
+            verify(&frontier, tampered),
+            Err(MerkleError::FrontierMismatch { leaves: 5 })
+        ));
+        assert!(matches!(
+            verify(&frontier, leaves(3)),
+            Err(MerkleError::LeafCountMismatch {
+                expected: 5,
+                got: 3
+            })
+        ));
+        assert_eq!(
+            leaves(2),
+            verify(&MerkleAccumulator::new(), leaves(2)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//!
+//! Implements the configurable merkle tree root calculation.
+//!
+//! [`MerkleConfig`] bundles the algorithm with the node combination rule. The
+//! built-in rules are the [`Combiner`] presets, any other rule may be injected
+//! as a boxed closure, without forking the crate.
+
+use super::{Combiner, Mode, OddPolicy};
+use crate::error::MerkleError;
+use crate::Hash;
+use alloc::boxed::Box;
+
+///
+/// The boxed node combination rule, given the left and right branch hashes.
+pub type BoxedCombiner = Box<dyn Fn(&Hash, Option<&Hash>) -> Hash + Send + Sync>;
+
+///
+/// The configuration of the merkle tree root calculation.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{hash, MerkleConfig, Mode};
+///
+/// let leaves = vec![[0u8; 32], [1u8; 32]];
+/// let root = MerkleConfig::new()
+///     .with_mode(Mode::WidthWalk)
+///     .calculate(leaves)
+///     .unwrap();
+///
+/// assert_eq!(hash(&[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+pub struct MerkleConfig {
+    mode: Mode,
+    combiner: BoxedCombiner,
+    strict: bool,
+}
+
+impl MerkleConfig {
+    ///
+    /// Creates the configuration with the default algorithm and the default
+    /// [`Combiner`] preset.
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::default(),
+            combiner: Box::new(|left, right| Combiner::default().hash(left, right)),
+            strict: false,
+        }
+    }
+
+    ///
+    /// Sets the algorithm of the calculation.
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    ///
+    /// Sets the built-in node combination preset. The preset with
+    /// [`OddPolicy::Strict`] makes the calculation fail on the levels with the
+    /// odd number of nodes.
+    pub fn with_preset(mut self, preset: Combiner) -> Self {
+        self.strict = preset.odd == OddPolicy::Strict;
+        self.with_combiner(Box::new(move |left, right| preset.hash(left, right)))
+    }
+
+    ///
+    /// Sets the custom node combination rule. The rule receives `None` as the
+    /// right branch of the last node of the level with the odd number of
+    /// nodes.
+    pub fn with_combiner(mut self, combiner: BoxedCombiner) -> Self {
+        self.combiner = combiner;
+        self
+    }
+
+    ///
+    /// Calculates the root of the leaves, failing with
+    /// [`MerkleError::EmptySource`] if there are none.
+    pub fn calculate<I>(&self, leaves: I) -> Result<Hash, MerkleError>
+    where
+        I: IntoIterator<Item = Hash>,
+    {
+        let combiner = &self.combiner;
+        let hash_fn = |left: &Hash, right: Option<&Hash>| combiner(left, right);
+        let mut leaves = leaves.into_iter().peekable();
+        match self.strict {
+            true => self.mode.try_calculate_strict(&mut leaves, &hash_fn),
+            false => self.mode.try_calculate(&mut leaves, &hash_fn),
+        }
+    }
+}
+
+impl Default for MerkleConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::{hash, hash_length_prefixed, ConcatOrder};
+
+    fn xor(left: &Hash, right: Option<&Hash>) -> Hash {
+        let right = right.unwrap_or(left);
+        std::array::from_fn(|i| left[i] ^ right[i])
+    }
+
+    #[test]
+    fn custom_combiner() {
+        let leaves = vec![[1u8; 32], [2u8; 32], [4u8; 32], [8u8; 32], [16u8; 32]];
+        for mode in [
+            Mode::DepthWalk,
+            Mode::WidthWalk,
+            Mode::ParallelShards,
+            Mode::Auto,
+        ] {
+            let config = MerkleConfig::new()
+                .with_mode(mode)
+                .with_combiner(Box::new(xor));
+            assert_eq!([15u8; 32], config.calculate(leaves[..4].to_vec()).unwrap());
+            // the odd leaf is combined with itself, cancelling out
+            assert_eq!([15u8; 32], config.calculate(leaves.clone()).unwrap());
+        }
+        assert!(matches!(
+            MerkleConfig::new().calculate(Vec::new()),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn preset() {
+        let leaves = vec![[1u8; 32], [2u8; 32]];
+        let config = MerkleConfig::new().with_preset(Combiner {
+            length_prefixed: true,
+            order: ConcatOrder::LeftRight,
+            ..Default::default()
+        });
+        assert_eq!(
+            hash_length_prefixed(&leaves[0], Some(&leaves[1])),
+            config.calculate(leaves).unwrap()
+        );
+    }
+
+    #[test]
+    fn strict_preset() {
+        let leaves = vec![[1u8; 32], [2u8; 32], [4u8; 32]];
+        let config = MerkleConfig::new().with_preset(Combiner {
+            odd: OddPolicy::Strict,
+            ..Default::default()
+        });
+        assert_eq!(
+            hash(&leaves[0], Some(&leaves[1])),
+            config.calculate(leaves[..2].to_vec()).unwrap()
+        );
+        assert!(matches!(
+            config.calculate(leaves),
+            Err(MerkleError::OddLayer { level: 0, size: 3 })
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+//!
+//! Implements the consistency proofs: the proofs that the tree of M leaves is
+//! a prefix of the tree of N leaves, as the heads of an append-only log.
+//!
+//! Time complexity: O(N) to build the proof, O(log(N)) to verify it
+//! Space complexity: O(log(N))
+//!
+//! The first M leaves split into the peaks, the complete subtrees the
+//! [`MerkleAccumulator`] keeps. The peaks are nodes of both trees: the old
+//! root is folded from the peaks alone, and the new root is folded from the
+//! peaks along with the roots of the subtrees to the right of them
+//!
+//! lvl3              abcdefgg
+//!               /            |
+//! lvl2       abcd            (efgg)
+//!           /    |          /    |
+//! lvl1    ab*    cd       ef      gg
+//!                / |     / |     /
+//! lvl0          c* (d)  e   f   g
+//!
+//! old size 3 (the peaks marked *), new size 7, proof: [ab, c, d, efgg]
+//!
+//! The proof is the peaks of the old tree, highest first, followed by the
+//! siblings of the running node on the path from the lowest peak up to the
+//! new root, lowest first. The right-edge node hashed with itself needs no
+//! sibling, and if the old size is a power of two, the single peak is the old
+//! root, so it is left out.
+//!
+//! Pros: the verifier needs neither of the leaves, only both roots and sizes.
+//!
+//! Cons: the proof is built from all the leaves of the new tree. The right
+//! edge hashed with itself does not bind the new size, e.g. the tree of 7
+//! leaves has the root of the tree of 8 leaves repeating the last one, so the
+//! sizes must come from a trusted source, e.g. the signed tree heads.
+//!
+//! Use-cases: append-only logs, proving the new tree head extends the old one.
+
+use super::accumulator::MerkleAccumulator;
+use super::{ct_eq, hash, tree_height};
+use crate::error::MerkleError;
+use crate::Hash;
+use alloc::vec::Vec;
+
+///
+/// Calculates the consistency proof of the tree of the first `old_size`
+/// leaves of the source against the tree of its first `new_size` leaves.
+///
+/// Fails with [`MerkleError::BadTreeSizes`] unless `0 < old_size <=
+/// new_size`, and with [`MerkleError::LeafCountMismatch`] if the source has
+/// fewer than `new_size` leaves.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::consistency::{consistency_proof, verify_consistency};
+/// use merkle_root::calc::{depth_walk::DepthWalk, hash};
+///
+/// let leaves: Vec<[u8; 32]> = (0..7u8).map(|byte| [byte; 32]).collect();
+/// let root = |size: usize| DepthWalk::calculate(&mut leaves[..size].iter().copied().peekable(), &hash);
+/// let proof = consistency_proof(3, 7, &mut leaves.iter().copied(), &hash).unwrap();
+///
+/// assert!(verify_consistency(&root(3), &root(7), 3, 7, &proof));
+/// ```
+pub fn consistency_proof<I, F>(
+    old_size: usize,
+    new_size: usize,
+    source: &mut I,
+    hash_fn: &F,
+) -> Result<Vec<Hash>, MerkleError>
+where
+    I: Iterator<Item = Hash>,
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    if old_size == 0 || old_size > new_size {
+        return Err(MerkleError::BadTreeSizes { old_size, new_size });
+    }
+    let mut old = MerkleAccumulator::new().with_hash_fn(hash_fn);
+    old.extend(source.take(old_size));
+    let mut read = old.leaf_count() as usize;
+    if read != old_size {
+        return Err(MerkleError::LeafCountMismatch {
+            expected: new_size,
+            got: read,
+        });
+    }
+    let mut proof: Vec<Hash> = match old.peaks() {
+        // the single peak is the old root
+        [_] => Vec::new(),
+        peaks => peaks.iter().map(|(_, peak)| *peak).collect(),
+    };
+    let mut height = old_size.trailing_zeros() as usize;
+    let mut position = (old_size - 1) >> height;
+    while (new_size - 1) >> height > 0 {
+        let last = (new_size - 1) >> height;
+        if position.is_multiple_of(2) && position != last {
+            // the sibling covers the next 2^height leaves, fewer on the edge
+            let mut subtree = MerkleAccumulator::new().with_hash_fn(hash_fn);
+            subtree.extend(source.take((new_size - read).min(1 << height)));
+            let leaves = subtree.leaf_count() as usize;
+            read += leaves;
+            let Some(mut sibling) = subtree.root() else {
+                break;
+            };
+            for _ in tree_height(leaves)..height {
+                sibling = hash_fn(&sibling, None);
+            }
+            proof.push(sibling);
+        }
+        position /= 2;
+        height += 1;
+    }
+    if read != new_size {
+        return Err(MerkleError::LeafCountMismatch {
+            expected: new_size,
+            got: read,
+        });
+    }
+    Ok(proof)
+}
+
+///
+/// Verifies the consistency proof of the old root against the new root,
+/// calculated with the default [`hash`] function.
+pub fn verify_consistency(
+    old_root: &Hash,
+    new_root: &Hash,
+    old_size: usize,
+    new_size: usize,
+    proof: &[Hash],
+) -> bool {
+    verify_consistency_with(old_root, new_root, old_size, new_size, proof, &hash)
+}
+
+///
+/// Verifies the consistency proof the same way [`verify_consistency`] does,
+/// with the given hash function. Every hash of the proof must be used.
+pub fn verify_consistency_with<F>(
+    old_root: &Hash,
+    new_root: &Hash,
+    old_size: usize,
+    new_size: usize,
+    proof: &[Hash],
+    hash_fn: &F,
+) -> bool
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    if old_size == 0 || old_size > new_size {
+        return false;
+    }
+    let (peaks, siblings) = match old_size.count_ones() as usize {
+        1 => (core::slice::from_ref(old_root), proof),
+        count if count <= proof.len() => proof.split_at(count),
+        _ => return false,
+    };
+    let old = fold(peaks, &[], old_size, old_size, hash_fn);
+    let new = fold(peaks, siblings, old_size, new_size, hash_fn);
+    match (old, new) {
+        (Some(old), Some(new)) => ct_eq(&old, old_root) & ct_eq(&new, new_root),
+        _ => false,
+    }
+}
+
+///
+/// Folds the peaks of the first `old_size` leaves into the root of the tree
+/// of `size` leaves, taking the siblings to the right of the peaks from the
+/// proof. `None` unless all the peaks and the siblings are used.
+fn fold<F>(
+    peaks: &[Hash],
+    siblings: &[Hash],
+    old_size: usize,
+    size: usize,
+    hash_fn: &F,
+) -> Option<Hash>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    let mut peaks = peaks.iter().rev();
+    let mut siblings = siblings.iter();
+    let mut node = *peaks.next()?;
+    let mut height = old_size.trailing_zeros() as usize;
+    let mut position = (old_size - 1) >> height;
+    while (size - 1) >> height > 0 {
+        node = match position % 2 {
+            // the left sibling of the path is the next higher peak
+            1 => hash_fn(peaks.next()?, Some(&node)),
+            _ if position == (size - 1) >> height => hash_fn(&node, None),
+            _ => hash_fn(&node, Some(siblings.next()?)),
+        };
+        position /= 2;
+        height += 1;
+    }
+    (peaks.next().is_none() && siblings.next().is_none()).then_some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::depth_walk::DepthWalk;
+    use crate::calc::OddPolicy;
+
+    fn leaves(n: u8) -> Vec<Hash> {
+        (0..n).map(|byte| [byte; 32]).collect()
+    }
+
+    fn root<F>(leaves: &[Hash], hash_fn: &F) -> Hash
+    where
+        F: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        DepthWalk::calculate(&mut leaves.iter().copied().peekable(), hash_fn)
+    }
+
+    #[test]
+    fn hand_computed() {
+        let leaves = leaves(7);
+        let [a, b, c, d, e, f, g] = leaves[..] else {
+            unreachable!()
+        };
+        let ab = hash(&a, Some(&b));
+        let efgg = hash(&hash(&e, Some(&f)), Some(&hash(&g, None)));
+        let old_root = hash(&ab, Some(&hash(&c, None)));
+        let new_root = hash(&hash(&ab, Some(&hash(&c, Some(&d)))), Some(&efgg));
+        assert_eq!(old_root, root(&leaves[..3], &hash));
+        assert_eq!(new_root, root(&leaves, &hash));
+
+        let proof = consistency_proof(3, 7, &mut leaves.iter().copied(), &hash).unwrap();
+        assert_eq!(vec![ab, c, d, efgg], proof);
+        assert!(verify_consistency(&old_root, &new_root, 3, 7, &proof));
+
+        // tamper with the roots, the sizes and the proof
+        assert!(!verify_consistency(&new_root, &new_root, 3, 7, &proof));
+        assert!(!verify_consistency(&old_root, &old_root, 3, 7, &proof));
+        assert!(!verify_consistency(&old_root, &new_root, 3, 9, &proof));
+        assert!(!verify_consistency(&old_root, &new_root, 2, 7, &proof));
+        assert!(!verify_consistency(&old_root, &new_root, 3, 7, &proof[..3]));
+        let mut tampered = proof.clone();
+        tampered[2] = e;
+        assert!(!verify_consistency(&old_root, &new_root, 3, 7, &tampered));
+        tampered.truncate(3);
+        tampered.extend([d, efgg]);
+        assert!(!verify_consistency(&old_root, &new_root, 3, 7, &tampered));
+    }
+
+    #[test]
+    fn all_sizes() {
+        let leaves = leaves(17);
+        let promote = OddPolicy::Promote.apply(hash);
+        for new_size in 1..=leaves.len() {
+            for old_size in 1..=new_size {
+                let mut source = leaves.iter().copied();
+                let proof = consistency_proof(old_size, new_size, &mut source, &hash).unwrap();
+                let old_root = root(&leaves[..old_size], &hash);
+                let new_root = root(&leaves[..new_size], &hash);
+                assert!(verify_consistency(
+                    &old_root, &new_root, old_size, new_size, &proof
+                ));
+
+                let mut source = leaves.iter().copied();
+                let proof = consistency_proof(old_size, new_size, &mut source, &promote).unwrap();
+                let old_root = root(&leaves[..old_size], &promote);
+                let new_root = root(&leaves[..new_size], &promote);
+                assert!(verify_consistency_with(
+                    &old_root, &new_root, old_size, new_size, &proof, &promote
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn bad_sizes() {
+        let leaves = leaves(4);
+        for (old_size, new_size) in [(0, 4), (5, 4)] {
+            let mut source = leaves.iter().copied();
+            assert!(matches!(
+                consistency_proof(old_size, new_size, &mut source, &hash),
+                Err(MerkleError::BadTreeSizes { .. })
+            ));
+        }
+        for (old_size, new_size) in [(2, 5), (5, 6)] {
+            let mut source = leaves.iter().copied();
+            assert!(matches!(
+                consistency_proof(old_size, new_size, &mut source, &hash),
+                Err(MerkleError::LeafCountMismatch { got: 4, .. })
+            ));
+        }
+    }
+}
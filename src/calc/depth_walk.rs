@@ -14,11 +14,14 @@
 //!         / | / | / |
 //! lvl0    a b c d e f
 //!
-//! The idea is to walk up and down the tree, starting from the lvl1,
-//! calculating the right branch (walk_down) and left branch (walk_up)
-//! recursively, reading the lvl0 hashes on-demand. Algorithm terminates when:
-//! - all branches converged into single hash;
-//! - there are no more source hashes left.
+//! The idea is to walk the tree depth-first, reading the lvl0 hashes
+//! on-demand and keeping the stack of the roots of the complete subtrees
+//! calculated so far, e.g. (abcd, ef) after reading f. Each hash read is
+//! pushed onto the stack, merging the two topmost roots while they have the
+//! same height. Once the source is exhausted, the stack is folded from the
+//! top, lifting the running node up to the height of the next root by hashing
+//! it with itself, e.g. ef => efef => abcdefef. The walk is iterative, so the
+//! call stack does not grow with the height of the tree.
 //!
 //! Pros: low disk usage, low space complexity.
 //!
@@ -26,52 +29,389 @@
 //!
 //! Use-cases: single-thread environments, embedded systems.
 
-use std::iter::Peekable;
+use super::proof::Side;
+use crate::error::MerkleError;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::iter::Peekable;
+
+///
+/// Number of leaves pulled from the source between the checks of the
+/// cancellation, see [`DepthWalk::try_calculate_cancellable`].
+pub const CANCEL_STEP: usize = 1024;
 
 pub struct DepthWalk;
 
 impl DepthWalk {
+    ///
+    /// Calculates the root, panicking if the source is empty; see
+    /// [`DepthWalk::try_calculate`].
     pub fn calculate<I, H, F>(source: &mut Peekable<I>, hash_fn: &F) -> H
     where
         I: Iterator<Item = H>,
         F: Fn(&H, Option<&H>) -> H,
     {
-        let left = source.next().expect("Expected source not to be empty");
-        match source.peek() {
-            None => left,
-            Some(_) => Self::walk_up(1, left, source, hash_fn),
+        Self::try_calculate(source, hash_fn).expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root, failing with [`MerkleError::EmptySource`] if the
+    /// source is empty.
+    pub fn try_calculate<I, H, F>(source: &mut Peekable<I>, hash_fn: &F) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        Self::try_calculate_from_level(source, 0, &|_, left, right| hash_fn(left, right))
+    }
+
+    ///
+    /// Calculates the root the same way [`DepthWalk::try_calculate`] does,
+    /// returning it along with the number of leaves read from the source.
+    pub fn try_calculate_counted<I, H, F>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<(H, usize), MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        let mut leaves = 0;
+        let mut counted = source.inspect(|_| leaves += 1).peekable();
+        let root = Self::try_calculate(&mut counted, hash_fn)?;
+        Ok((root, leaves))
+    }
+
+    ///
+    /// Calculates the root the same way [`DepthWalk::try_calculate`] does,
+    /// failing with [`MerkleError::OddLayer`] if any level of the tree, other
+    /// than the root, has the odd number of nodes. The node missing its right
+    /// branch only shows up once the source is exhausted, so the walk is
+    /// complete by then.
+    pub fn try_calculate_strict<I, H, F>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        // the lower levels are hashed first, so the first node missing its
+        // right branch is the parent of the last node of the lowest odd level
+        let odd = Cell::new(None);
+        let mut leaves: usize = 0;
+        let mut counted = source.inspect(|_| leaves += 1).peekable();
+        let root = Self::try_calculate_from_level(&mut counted, 0, &|height, left, right| {
+            if right.is_none() && odd.get().is_none() {
+                odd.set(Some(height - 1));
+            }
+            hash_fn(left, right)
+        })?;
+        drop(counted);
+        match odd.get() {
+            Some(level) => Err(MerkleError::OddLayer {
+                level,
+                size: leaves.div_ceil(1 << level),
+            }),
+            None => Ok(root),
         }
     }
 
-    fn walk_up<I, H, F>(height: usize, left: H, source: &mut Peekable<I>, hash_fn: &F) -> H
+    ///
+    /// Calculates the root of the leaves already in memory, panicking if there
+    /// are none; see [`DepthWalk::try_calculate_from_slice`].
+    pub fn calculate_from_slice<H, F>(leaves: &[H], hash_fn: &F) -> H
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        H: Clone,
+    {
+        Self::try_calculate_from_slice(leaves, hash_fn).expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root of the leaves already in memory, failing with
+    /// [`MerkleError::EmptySource`] if there are none. The slice is split into
+    /// the subtrees directly, no leaf is cloned except the root of the single
+    /// leaf tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{depth_walk::DepthWalk, hash};
+    ///
+    /// let leaves = [[0u8; 32], [1u8; 32], [2u8; 32]];
+    /// let root = DepthWalk::try_calculate_from_slice(&leaves, &hash).unwrap();
+    ///
+    /// assert_eq!(DepthWalk::calculate(&mut leaves.into_iter().peekable(), &hash), root);
+    /// ```
+    pub fn try_calculate_from_slice<H, F>(leaves: &[H], hash_fn: &F) -> Result<H, MerkleError>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        H: Clone,
+    {
+        match leaves {
+            [] => Err(MerkleError::EmptySource),
+            [leaf] => Ok(leaf.clone()),
+            leaves => {
+                let height = leaves.len().next_power_of_two().trailing_zeros();
+                Ok(Self::walk_slice(height, leaves, hash_fn))
+            }
+        }
+    }
+
+    ///
+    /// Calculates the root the same way [`DepthWalk::try_calculate`] does,
+    /// calling `should_cancel` once every [`CANCEL_STEP`] leaves pulled from
+    /// the source, and failing with [`MerkleError::Cancelled`] as soon as it
+    /// returns `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{depth_walk::DepthWalk, hash};
+    /// use merkle_root::error::MerkleError;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// let cancelled = AtomicBool::new(true);
+    /// let mut source = vec![[0u8; 32]; 5].into_iter().peekable();
+    /// let root = DepthWalk::try_calculate_cancellable(&mut source, &hash, &|| {
+    ///     cancelled.load(Ordering::Relaxed)
+    /// });
+    ///
+    /// assert!(matches!(root, Err(MerkleError::Cancelled)));
+    /// ```
+    pub fn try_calculate_cancellable<I, H, F, C>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+        should_cancel: &C,
+    ) -> Result<H, MerkleError>
     where
         I: Iterator<Item = H>,
         F: Fn(&H, Option<&H>) -> H,
+        C: Fn() -> bool,
+    {
+        Self::walk_stack(
+            source,
+            0,
+            &|_, left, right| hash_fn(left, right),
+            should_cancel,
+        )
+    }
+
+    ///
+    /// Calculates the root, treating the source as the level `level` of a
+    /// larger tree (level 0 being the leaves). The hash function receives the
+    /// level of the node being calculated along with its branches. Panics if
+    /// the source is empty.
+    pub fn calculate_from_level<I, H, F>(source: &mut Peekable<I>, level: usize, hash_fn: &F) -> H
+    where
+        I: Iterator<Item = H>,
+        F: Fn(usize, &H, Option<&H>) -> H,
     {
-        let right = Self::walk_down(height - 1, source, hash_fn);
-        let hash = hash_fn(&left, right.as_ref());
-        match source.peek() {
-            // source still contains hash to continue
-            Some(_) => Self::walk_up(height + 1, hash, source, hash_fn),
-            // no hashes left in the source, return the root
-            None => hash,
+        Self::try_calculate_from_level(source, level, hash_fn)
+            .expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root the same way [`DepthWalk::calculate_from_level`]
+    /// does, failing with [`MerkleError::EmptySource`] if the source is empty.
+    pub fn try_calculate_from_level<I, H, F>(
+        source: &mut Peekable<I>,
+        level: usize,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(usize, &H, Option<&H>) -> H,
+    {
+        Self::walk_stack(source, level, hash_fn, &|| false)
+    }
+
+    ///
+    /// Calculates the root of the source as the level `level`, checking the
+    /// cancellation once every [`CANCEL_STEP`] leaves.
+    fn walk_stack<I, H, F, C>(
+        source: &mut Peekable<I>,
+        level: usize,
+        hash_fn: &F,
+        should_cancel: &C,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(usize, &H, Option<&H>) -> H,
+        C: Fn() -> bool,
+    {
+        // the roots of the complete subtrees along with their heights, the
+        // lowest on top
+        let mut stack: Vec<(usize, H)> = Vec::new();
+        for (index, leaf) in source.enumerate() {
+            if index.is_multiple_of(CANCEL_STEP) && should_cancel() {
+                return Err(MerkleError::Cancelled);
+            }
+            let mut node = (0, leaf);
+            while let Some((height, left)) = stack.pop_if(|(height, _)| *height == node.0) {
+                node = (
+                    height + 1,
+                    hash_fn(level + height + 1, &left, Some(&node.1)),
+                );
+            }
+            stack.push(node);
+        }
+        let (mut height, mut root) = stack.pop().ok_or(MerkleError::EmptySource)?;
+        while let Some((left_height, left)) = stack.pop() {
+            // the subtree without the right branch is hashed with itself
+            while height < left_height {
+                height += 1;
+                root = hash_fn(level + height, &root, None);
+            }
+            height += 1;
+            root = hash_fn(level + height, &left, Some(&root));
         }
+        Ok(root)
     }
 
-    fn walk_down<I, H, F>(height: usize, source: &mut Peekable<I>, hash_fn: &F) -> Option<H>
+    ///
+    /// Calculates the root along with the inclusion proof of the leaf with the
+    /// given index, in the same single walk. The proof is the list of the
+    /// siblings from the leaf up to the root; the node hashed with itself gets
+    /// its own hash as the sibling on the [`Side::Right`], the node carried up
+    /// as is (e.g. by [`OddPolicy::Promote`](super::OddPolicy::Promote)) gets
+    /// no sibling at all.
+    ///
+    /// Fails with [`MerkleError::EmptySource`] if the source is empty, and
+    /// with [`MerkleError::IndexOutOfRange`] if the source has no leaf with
+    /// the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{depth_walk::DepthWalk, hash, verify_proof, Proof};
+    ///
+    /// let leaves = [[0u8; 32], [1u8; 32], [2u8; 32]];
+    /// let mut source = leaves.into_iter().peekable();
+    /// let (root, siblings) = DepthWalk::calculate_with_proof(&mut source, 2, &hash).unwrap();
+    ///
+    /// assert!(verify_proof(&leaves[2], &Proof { index: 2, siblings }, &root));
+    /// ```
+    pub fn calculate_with_proof<I, H, F>(
+        source: &mut Peekable<I>,
+        index: usize,
+        hash_fn: &F,
+    ) -> Result<(H, Vec<(H, Side)>), MerkleError>
     where
         I: Iterator<Item = H>,
         F: Fn(&H, Option<&H>) -> H,
+        H: Clone + PartialEq,
+    {
+        let mut walk = ProofWalk {
+            next: 0,
+            index,
+            siblings: Vec::new(),
+        };
+        let left = walk
+            .walk_down(0, source, hash_fn)
+            .ok_or(MerkleError::EmptySource)?;
+        let mut root = left;
+        let mut height = 1;
+        while source.peek().is_some() {
+            let right = walk.walk_down(height - 1, source, hash_fn);
+            root = walk.parent(root, right, hash_fn);
+            height += 1;
+        }
+        match root.on_path {
+            true => Ok((root.hash, walk.siblings)),
+            false => Err(MerkleError::IndexOutOfRange {
+                index,
+                leaves: walk.next,
+            }),
+        }
+    }
+
+    fn walk_slice<H, F>(height: u32, leaves: &[H], hash_fn: &F) -> H
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        H: Clone,
     {
         if height == 0 {
-            // we're at the very bottom of the tree, collect the hash from the source
-            source.next()
-        } else {
-            // recurse down once again
-            Some(hash_fn(
-                &Self::walk_down(height - 1, source, hash_fn)?,
-                Self::walk_down(height - 1, source, hash_fn).as_ref(),
-            ))
+            return leaves[0].clone();
+        }
+        // the left subtree is complete unless it is the last one, the right
+        // one is missing if the left one took all the leaves
+        let half = 1 << (height - 1);
+        match leaves.split_at_checked(half) {
+            Some((left, right)) if !right.is_empty() => hash_fn(
+                &Self::walk_slice(height - 1, left, hash_fn),
+                Some(&Self::walk_slice(height - 1, right, hash_fn)),
+            ),
+            _ => hash_fn(&Self::walk_slice(height - 1, leaves, hash_fn), None),
+        }
+    }
+}
+
+///
+/// The node of the proof walk, marked if the leaf being proven is below it.
+struct PathNode<H> {
+    hash: H,
+    on_path: bool,
+}
+
+///
+/// The state of [`DepthWalk::calculate_with_proof`]: the index of the next
+/// leaf read, the index of the leaf being proven and its siblings found so
+/// far. The subtrees are completed bottom-up, so the siblings are found in
+/// the order from the leaf up to the root.
+struct ProofWalk<H> {
+    next: usize,
+    index: usize,
+    siblings: Vec<(H, Side)>,
+}
+
+impl<H: Clone + PartialEq> ProofWalk<H> {
+    fn walk_down<I, F>(
+        &mut self,
+        height: usize,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Option<PathNode<H>>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        if height == 0 {
+            let hash = source.next()?;
+            let on_path = self.next == self.index;
+            self.next += 1;
+            return Some(PathNode { hash, on_path });
+        }
+        let left = self.walk_down(height - 1, source, hash_fn)?;
+        let right = self.walk_down(height - 1, source, hash_fn);
+        Some(self.parent(left, right, hash_fn))
+    }
+
+    fn parent<F>(
+        &mut self,
+        left: PathNode<H>,
+        right: Option<PathNode<H>>,
+        hash_fn: &F,
+    ) -> PathNode<H>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        let right_on_path = right.as_ref().is_some_and(|right| right.on_path);
+        let hash = hash_fn(&left.hash, right.as_ref().map(|right| &right.hash));
+        match &right {
+            Some(right) if left.on_path => self.siblings.push((right.hash.clone(), Side::Right)),
+            Some(_) if right_on_path => self.siblings.push((left.hash.clone(), Side::Left)),
+            // the node without the right branch is either hashed with itself
+            // or carried up as is, the latter needs no sibling
+            None if left.on_path && hash != left.hash => {
+                self.siblings.push((left.hash.clone(), Side::Right))
+            }
+            _ => {}
+        }
+        PathNode {
+            hash,
+            on_path: left.on_path || right_on_path,
         }
     }
 }
@@ -103,6 +443,84 @@ mod tests {
         DepthWalk::calculate(&mut source, &hash);
     }
 
+    #[test]
+    fn try_empty_source() {
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            DepthWalk::try_calculate(&mut source, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+        let mut source = vec![vec!['a'], vec!['b']].into_iter().peekable();
+        assert_eq!(
+            vec!['a', 'b'],
+            DepthWalk::try_calculate(&mut source, &hash).unwrap()
+        );
+    }
+
+    #[test]
+    fn counted() {
+        let mut source = vec![vec!['a'], vec!['b'], vec!['c']].into_iter().peekable();
+        assert_eq!(
+            (vec!['a', 'b', 'c', 'c'], 3),
+            DepthWalk::try_calculate_counted(&mut source, &hash).unwrap()
+        );
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            DepthWalk::try_calculate_counted(&mut source, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn strict() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let root = DepthWalk::try_calculate_strict(&mut source, &hash);
+            match count.is_power_of_two() {
+                true => assert_eq!(
+                    DepthWalk::calculate(&mut leaves[..count].iter().cloned().peekable(), &hash),
+                    root.unwrap()
+                ),
+                false => {
+                    let level = count.trailing_zeros() as usize;
+                    assert!(matches!(
+                        root,
+                        Err(MerkleError::OddLayer { level: l, size })
+                            if l == level && size == count >> level
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cancellable() {
+        use core::cell::Cell;
+
+        let leaves: Vec<Vec<char>> = (0..3 * CANCEL_STEP).map(|_| vec!['a']).collect();
+        let checks = Cell::new(0);
+        let count = || {
+            checks.set(checks.get() + 1);
+            false
+        };
+        let mut source = leaves.clone().into_iter().peekable();
+        let root = DepthWalk::try_calculate_cancellable(&mut source, &hash, &count).unwrap();
+        assert_eq!(
+            DepthWalk::calculate(&mut leaves.clone().into_iter().peekable(), &hash),
+            root
+        );
+        assert_eq!(3, checks.get());
+
+        // the leaves past the check are not pulled
+        let mut source = leaves.into_iter().peekable();
+        assert!(matches!(
+            DepthWalk::try_calculate_cancellable(&mut source, &hash, &|| true),
+            Err(MerkleError::Cancelled)
+        ));
+        assert_eq!(3 * CANCEL_STEP - 1, source.count());
+    }
+
     #[test]
     fn full_tree() {
         let mut source = vec![vec!['a'], vec!['b']].into_iter().peekable();
@@ -128,4 +546,129 @@ mod tests {
             DepthWalk::calculate(&mut source, &hash)
         );
     }
+
+    #[test]
+    fn from_slice() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            assert_eq!(
+                DepthWalk::calculate(&mut source, &hash),
+                DepthWalk::calculate_from_slice(&leaves[..count], &hash)
+            );
+        }
+        assert!(matches!(
+            DepthWalk::try_calculate_from_slice(&[] as &[Vec<char>], &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn with_proof() {
+        use crate::calc::{hash, verify_proof, Proof};
+
+        for n in 1..=9u8 {
+            let leaves: Vec<[u8; 32]> = (0..n).map(|byte| [byte; 32]).collect();
+            let root = DepthWalk::calculate(&mut leaves.clone().into_iter().peekable(), &hash);
+            for index in 0..n as usize {
+                let mut source = leaves.clone().into_iter().peekable();
+                let (proven, siblings) =
+                    DepthWalk::calculate_with_proof(&mut source, index, &hash).unwrap();
+                assert_eq!(root, proven);
+                assert!(verify_proof(
+                    &leaves[index],
+                    &Proof { index, siblings },
+                    &root
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn with_proof_duplicated() {
+        let mut source = vec![vec!['a'], vec!['b'], vec!['c']].into_iter().peekable();
+        let (root, siblings) = DepthWalk::calculate_with_proof(&mut source, 2, &hash).unwrap();
+        assert_eq!(vec!['a', 'b', 'c', 'c'], root);
+        assert_eq!(
+            vec![(vec!['c'], Side::Right), (vec!['a', 'b'], Side::Left)],
+            siblings
+        );
+
+        let mut source = vec![vec!['a'], vec!['b']].into_iter().peekable();
+        assert!(matches!(
+            DepthWalk::calculate_with_proof(&mut source, 2, &hash),
+            Err(MerkleError::IndexOutOfRange {
+                index: 2,
+                leaves: 2
+            })
+        ));
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            DepthWalk::calculate_with_proof(&mut source, 0, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    ///
+    /// The recursive depth-walk the iterative one replaced, walking up the
+    /// left branches and down the right ones.
+    mod recursive {
+        use core::iter::Peekable;
+
+        pub fn walk_up<I, H, F>(height: usize, left: H, source: &mut Peekable<I>, hash_fn: &F) -> H
+        where
+            I: Iterator<Item = H>,
+            F: Fn(usize, &H, Option<&H>) -> H,
+        {
+            let right = walk_down(height - 1, source, hash_fn);
+            let hash = hash_fn(height, &left, right.as_ref());
+            match source.peek() {
+                Some(_) => walk_up(height + 1, hash, source, hash_fn),
+                None => hash,
+            }
+        }
+
+        fn walk_down<I, H, F>(height: usize, source: &mut Peekable<I>, hash_fn: &F) -> Option<H>
+        where
+            I: Iterator<Item = H>,
+            F: Fn(usize, &H, Option<&H>) -> H,
+        {
+            if height == 0 {
+                return source.next();
+            }
+            Some(hash_fn(
+                height,
+                &walk_down(height - 1, source, hash_fn)?,
+                walk_down(height - 1, source, hash_fn).as_ref(),
+            ))
+        }
+    }
+
+    #[test]
+    fn same_as_recursive() {
+        use core::cell::RefCell;
+
+        let leaves: Vec<Vec<char>> = ('a'..='z')
+            .chain('A'..='Z')
+            .map(|leaf| vec![leaf])
+            .collect();
+        for count in 2..=leaves.len() {
+            // the nodes are hashed in the same order, at the same heights
+            let calls = RefCell::new(Vec::new());
+            let record = |height, left: &Vec<char>, right: Option<&Vec<char>>| {
+                calls
+                    .borrow_mut()
+                    .push((height, left.clone(), right.cloned()));
+                hash(left, right)
+            };
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let root = DepthWalk::calculate_from_level(&mut source, 0, &record);
+            let iterative = calls.take();
+
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let left = source.next().unwrap();
+            assert_eq!(root, recursive::walk_up(1, left, &mut source, &record));
+            assert_eq!(iterative, calls.take());
+        }
+    }
 }
@@ -0,0 +1,249 @@
+//!
+//! Implements the merkle root of the directory tree (merkle of merkles).
+//!
+//! Time complexity: O(n) of the total size of the files
+//! Space complexity: O(e) of the number of entries of the directories on the
+//! path from the top to the deepest one
+//!
+//! Every file and directory of the tree is hashed into the node hash, the
+//! leading type byte keeping the files and the directories apart:
+//!
+//! file        sha256(0x00 || contents)
+//! directory   sha256(0x01 || root(entries))
+//! entry       sha256(len(name) || name || node)
+//!
+//! The entries of the directory are sorted by their names, compared bytewise,
+//! and the entry hashes are combined into `root(entries)` as the leaves of the
+//! merkle tree; the root of the empty directory is the zero hash. The name
+//! length is the little-endian u64, so the name can not run into the node.
+//!
+//! top           dir(top)
+//!                  |
+//!          root(entries of top)
+//!             /            |
+//!      entry(a.txt)     entry(sub)
+//!            |              |
+//! sub   file(a.txt)      dir(sub)
+//!                           |
+//!                  root(entries of sub)
+//!                     /            |
+//!              entry(b.txt)    entry(c.txt)
+//!                    |              |
+//!               file(b.txt)    file(c.txt)
+//!
+//! The names of the entries are bound into the root, the name of the top
+//! directory is not, so the root only depends on the contents of the tree.
+//! Symlinks and special files are skipped, the walk never leaves the tree.
+//!
+//! Pros: the root of the subdirectory is reused as is, renames and moves are
+//! detected.
+//!
+//! Cons: every file is read whole, the files are not split into chunks.
+//!
+//! Use-cases: content-addressed directories, snapshots of the file trees.
+//!
+//! The flat variant takes the plain sha256 of the contents of each file as the
+//! leaves, see [`leaves_from_paths`], e.g. the files of a single directory in
+//! the order of [`sorted_files`]. Neither the names nor the subdirectories are
+//! bound into its root.
+
+use super::{calculate_from_level0, ZERO_HASH};
+use crate::error::MerkleError;
+use crate::Hash;
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+const FILE: u8 = 0x00;
+const DIRECTORY: u8 = 0x01;
+
+///
+/// Calculates the root of the directory tree, the node hash of the top
+/// directory. The entry hashes of each directory are combined with `hash_fn`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use merkle_root::calc::{dir::dir_root, hash};
+/// use std::path::Path;
+///
+/// let root = dir_root(Path::new("snapshot"), &hash).unwrap();
+/// ```
+pub fn dir_root<F>(path: &Path, hash_fn: &F) -> Result<Hash, MerkleError>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    let entries = sorted_entries(path)?;
+    let mut leaves = Vec::with_capacity(entries.len());
+    for (name, is_dir) in entries {
+        let child = path.join(&name);
+        let node = match is_dir {
+            true => dir_root(&child, hash_fn)?,
+            false => file_node(&child)?,
+        };
+        leaves.push(entry_hash(name.as_encoded_bytes(), &node));
+    }
+    let root = match leaves.is_empty() {
+        true => ZERO_HASH,
+        false => calculate_from_level0(leaves, hash_fn)?,
+    };
+    let mut hasher = Sha256::new();
+    hasher.update([DIRECTORY]);
+    hasher.update(root);
+    Ok(hasher.finalize().into())
+}
+
+///
+/// Lists the regular files of the directory, not descending into the
+/// subdirectories, sorted by their names compared bytewise, so the order does
+/// not depend on the file system.
+pub fn sorted_files(path: &Path) -> Result<Vec<PathBuf>, MerkleError> {
+    Ok(sorted_entries(path)?
+        .into_iter()
+        .filter(|(_, is_dir)| !is_dir)
+        .map(|(name, _)| path.join(name))
+        .collect())
+}
+
+///
+/// Calculates the leaves of the files in the given order: the sha256 of the
+/// contents of each file, streamed rather than read whole.
+///
+/// # Examples
+///
+/// ```no_run
+/// use merkle_root::calc::{dir::{leaves_from_paths, sorted_files}, root_from_source, Mode};
+/// use std::path::Path;
+///
+/// let leaves = leaves_from_paths(&sorted_files(Path::new("snapshot")).unwrap()).unwrap();
+/// let root = root_from_source(leaves.into_iter().map(Ok), Mode::DepthWalk).unwrap();
+/// ```
+pub fn leaves_from_paths(paths: &[PathBuf]) -> Result<Vec<Hash>, MerkleError> {
+    paths
+        .iter()
+        .map(|path| {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut File::open(path)?, &mut hasher)?;
+            Ok(hasher.finalize().into())
+        })
+        .collect()
+}
+
+///
+/// Lists the files and the directories of the directory, with the flag of
+/// the directory, sorted by their names compared bytewise. Symlinks and
+/// special files are skipped.
+fn sorted_entries(path: &Path) -> Result<Vec<(OsString, bool)>, MerkleError> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        if kind.is_file() || kind.is_dir() {
+            entries.push((entry.file_name(), kind.is_dir()));
+        }
+    }
+    entries.sort_unstable_by(|(left, _), (right, _)| {
+        left.as_encoded_bytes().cmp(right.as_encoded_bytes())
+    });
+    Ok(entries)
+}
+
+///
+/// Calculates the node hash of the file, streaming its contents.
+pub fn file_node(path: &Path) -> Result<Hash, MerkleError> {
+    let mut hasher = Sha256::new();
+    hasher.update([FILE]);
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+fn entry_hash(name: &[u8], node: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update((name.len() as u64).to_le_bytes());
+    hasher.update(name);
+    hasher.update(node);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::hash;
+    use crate::test_fixtures::{fixture, fixture_dir};
+    use std::path::PathBuf;
+
+    ///
+    /// Creates the fixture directory tree, returning its path.
+    fn tree_fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = fixture_dir("dir").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (path, contents) in files {
+            fixture("dir", &format!("{name}/{path}"), contents);
+        }
+        dir
+    }
+
+    fn node(kind: u8, contents: &[u8]) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update([kind]);
+        hasher.update(contents);
+        hasher.finalize().into()
+    }
+
+    #[test]
+    fn known_root() {
+        let dir = tree_fixture(
+            "known_root",
+            &[("a.txt", "a"), ("sub/b.txt", "b"), ("sub/c.txt", "c")],
+        );
+        let sub = node(
+            DIRECTORY,
+            &hash(
+                &entry_hash(b"b.txt", &node(FILE, b"b")),
+                Some(&entry_hash(b"c.txt", &node(FILE, b"c"))),
+            ),
+        );
+        let top = node(
+            DIRECTORY,
+            &hash(
+                &entry_hash(b"a.txt", &node(FILE, b"a")),
+                Some(&entry_hash(b"sub", &sub)),
+            ),
+        );
+        let root = dir_root(&dir, &hash).unwrap();
+        assert_eq!(top, root);
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            "6d1aadec68a0d9271bb85fa86d345d455c9b349a5f5693f235ecc5851dc01203",
+            base16ct::lower::encode_str(&root, &mut buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn ambiguity() {
+        let root = |name, files| dir_root(&tree_fixture(name, files), &hash).unwrap();
+        let flat = root("flat", &[("a", "x"), ("b", "y")]);
+        // same contents, different names
+        assert_ne!(flat, root("renamed", &[("a", "x"), ("c", "y")]));
+        // same names, contents moved into the subdirectory
+        assert_ne!(flat, root("nested", &[("a", "x"), ("b/b", "y")]));
+        // the order of the entries does not depend on the creation order
+        assert_eq!(flat, root("reversed", &[("b", "y"), ("a", "x")]));
+        assert_eq!(node(DIRECTORY, &ZERO_HASH), root("empty", &[]));
+    }
+
+    #[test]
+    fn flat() {
+        let dir = tree_fixture("flat_files", &[("b", "y"), ("a", "x"), ("sub/c", "z")]);
+        let files = sorted_files(&dir).unwrap();
+        assert_eq!(vec![dir.join("a"), dir.join("b")], files);
+        let digest = |contents: &[u8]| -> Hash { Sha256::digest(contents).into() };
+        assert_eq!(
+            vec![digest(b"x"), digest(b"y")],
+            leaves_from_paths(&files).unwrap()
+        );
+        assert!(leaves_from_paths(&[dir.join("missing")]).is_err());
+    }
+}
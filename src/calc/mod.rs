@@ -1,8 +1,521 @@
+use crate::error::MerkleError;
+#[cfg(feature = "std")]
+use crate::progress::DEFAULT_STEP;
+#[cfg(feature = "std")]
+use crate::source::{hex_lines_to_hashes, LeafSource, UntilError};
 use crate::Hash;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use depth_walk::DepthWalk;
+use parallel_shards::ParallelShards;
+use sha2::digest::Output;
 use sha2::{Digest, Sha256};
+use width_walk::WidthWalk;
 
+// the RFC 6962 domain separation prefixes
+const RFC6962_LEAF: u8 = 0x00;
+const RFC6962_NODE: u8 = 0x01;
+
+pub mod accumulator;
+pub mod config;
+pub mod consistency;
 pub mod depth_walk;
+#[cfg(feature = "std")]
+pub mod dir;
+pub mod parallel_shards;
+pub mod proof;
+#[cfg(feature = "std")]
+pub mod range;
 pub mod width_walk;
+pub mod window;
+
+pub use config::{BoxedCombiner, MerkleConfig};
+#[cfg(feature = "parallel")]
+pub use proof::verify_proofs_par;
+pub use proof::{calculate_batch_proof, verify_batch_proof, verify_batch_proof_with, BatchProof};
+pub use proof::{verify_path, verify_path_with, verify_proof, verify_proof_with, Proof, Side};
+
+///
+/// The algorithm of the merkle tree root calculation.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// See [`depth_walk`]
+    #[default]
+    DepthWalk,
+    /// See [`width_walk`]
+    WidthWalk,
+    /// See [`parallel_shards`]; the source is collected into memory first
+    ParallelShards,
+    /// The depth-walk for the sources of less than [`AUTO_THRESHOLD`] leaves,
+    /// the width-walk otherwise; up to the threshold leaves are buffered to
+    /// decide, see [`Mode::resolve`]
+    Auto,
+}
+
+///
+/// Number of leaves from which [`Mode::Auto`] picks the width-walk: below it,
+/// the overhead of rayon outweighs the parallel hashing.
+pub const AUTO_THRESHOLD: usize = 4096;
+
+impl Mode {
+    ///
+    /// Picks the algorithm of [`Mode::Auto`], pulling up to [`AUTO_THRESHOLD`]
+    /// leaves from the source: the depth-walk if the source ends before the
+    /// threshold, the width-walk otherwise. Returns the algorithm along with
+    /// the leaves pulled, to be read before the rest of the source. Any other
+    /// mode is returned as is, pulling nothing.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::Mode;
+    ///
+    /// let mut source = vec![[0u8; 32]; 5].into_iter();
+    /// let (mode, head) = Mode::Auto.resolve(&mut source);
+    ///
+    /// assert_eq!(Mode::DepthWalk, mode);
+    /// assert_eq!(5, head.len());
+    /// ```
+    pub fn resolve<I, H>(&self, source: &mut I) -> (Mode, Vec<H>)
+    where
+        I: Iterator<Item = H>,
+    {
+        if *self != Mode::Auto {
+            return (*self, Vec::new());
+        }
+        let head: Vec<H> = source.take(AUTO_THRESHOLD).collect();
+        match head.len() < AUTO_THRESHOLD {
+            true => (Mode::DepthWalk, head),
+            false => (Mode::WidthWalk, head),
+        }
+    }
+
+    ///
+    /// Calculates the root of the source with the algorithm, panicking if the
+    /// source is empty; see [`Mode::try_calculate`].
+    pub fn calculate<I, H, F>(&self, source: &mut Peekable<I>, hash_fn: &F) -> H
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        self.try_calculate(source, hash_fn)
+            .expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root of the source with the algorithm, failing with
+    /// [`MerkleError::EmptySource`] if the source is empty.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, Mode};
+    /// use merkle_root::error::MerkleError;
+    ///
+    /// let mut source = Vec::new().into_iter().peekable();
+    /// let root = Mode::WidthWalk.try_calculate(&mut source, &hash);
+    ///
+    /// assert!(matches!(root, Err(MerkleError::EmptySource)));
+    /// ```
+    pub fn try_calculate<I, H, F>(
+        &self,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        match self {
+            Mode::DepthWalk => DepthWalk::try_calculate(source, hash_fn),
+            Mode::WidthWalk => WidthWalk::try_calculate(source, hash_fn),
+            Mode::ParallelShards => {
+                let leaves: Vec<H> = source.collect();
+                if leaves.is_empty() {
+                    return Err(MerkleError::EmptySource);
+                }
+                let open = |start: usize| leaves[start..].iter().cloned();
+                let shards = parallel_shards::default_shards();
+                Ok(ParallelShards::calculate(
+                    leaves.len(),
+                    shards,
+                    &open,
+                    hash_fn,
+                ))
+            }
+            Mode::Auto => {
+                let (mode, head) = self.resolve(source);
+                let mut source = head.into_iter().chain(source).peekable();
+                match mode {
+                    Mode::DepthWalk => DepthWalk::try_calculate(&mut source, hash_fn),
+                    _ => WidthWalk::try_calculate(&mut source, hash_fn),
+                }
+            }
+        }
+    }
+
+    ///
+    /// Calculates the root the same way [`Mode::try_calculate`] does, failing
+    /// with [`MerkleError::OddLayer`] on the first level with the odd number
+    /// of nodes (other than the root), see [`OddPolicy::Strict`].
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, Mode};
+    /// use merkle_root::error::MerkleError;
+    ///
+    /// let mut source = vec![[0u8; 32]; 6].into_iter().peekable();
+    /// let root = Mode::DepthWalk.try_calculate_strict(&mut source, &hash);
+    ///
+    /// assert!(matches!(root, Err(MerkleError::OddLayer { level: 1, size: 3 })));
+    /// ```
+    pub fn try_calculate_strict<I, H, F>(
+        &self,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        let (mode, head) = self.resolve(source);
+        let mut source = head.into_iter().chain(source).peekable();
+        match mode {
+            Mode::DepthWalk => DepthWalk::try_calculate_strict(&mut source, hash_fn),
+            Mode::WidthWalk => WidthWalk::try_calculate_strict(&mut source, hash_fn),
+            Mode::ParallelShards => {
+                // the shards are complete subtrees, only the leaf count matters
+                let leaves: Vec<H> = source.collect();
+                ensure_balanced(leaves.len())?;
+                mode.try_calculate(&mut leaves.into_iter().peekable(), hash_fn)
+            }
+            Mode::Auto => unreachable!("Expected the auto mode to be resolved"),
+        }
+    }
+
+    ///
+    /// Calculates the root the same way [`Mode::try_calculate`] does, failing
+    /// with [`MerkleError::Cancelled`] once `should_cancel` returns `true`,
+    /// e.g. after the deadline of the request. The depth-walk checks it once
+    /// every [`CANCEL_STEP`](depth_walk::CANCEL_STEP) leaves, the width-walk
+    /// once per level. The parallel-shards mode only checks it once the
+    /// source is collected, the shards run to completion.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, Mode};
+    /// use merkle_root::error::MerkleError;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// let cancelled = AtomicBool::new(false);
+    /// let should_cancel = || cancelled.load(Ordering::Relaxed);
+    /// let mut source = vec![[0u8; 32]; 5].into_iter().peekable();
+    /// assert!(Mode::WidthWalk.try_calculate_cancellable(&mut source, &hash, &should_cancel).is_ok());
+    ///
+    /// cancelled.store(true, Ordering::Relaxed);
+    /// let mut source = vec![[0u8; 32]; 5].into_iter().peekable();
+    /// let root = Mode::WidthWalk.try_calculate_cancellable(&mut source, &hash, &should_cancel);
+    /// assert!(matches!(root, Err(MerkleError::Cancelled)));
+    /// ```
+    pub fn try_calculate_cancellable<I, H, F, C>(
+        &self,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+        should_cancel: &C,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+        C: Fn() -> bool,
+    {
+        let (mode, head) = self.resolve(source);
+        let mut source = head.into_iter().chain(source).peekable();
+        match mode {
+            Mode::DepthWalk => {
+                DepthWalk::try_calculate_cancellable(&mut source, hash_fn, should_cancel)
+            }
+            Mode::WidthWalk => {
+                WidthWalk::try_calculate_cancellable(&mut source, hash_fn, should_cancel)
+            }
+            Mode::ParallelShards => {
+                let leaves: Vec<H> = source.collect();
+                if should_cancel() {
+                    return Err(MerkleError::Cancelled);
+                }
+                mode.try_calculate(&mut leaves.into_iter().peekable(), hash_fn)
+            }
+            Mode::Auto => unreachable!("Expected the auto mode to be resolved"),
+        }
+    }
+
+    ///
+    /// Calculates the root the same way [`Mode::try_calculate`] does,
+    /// returning it along with the number of leaves read from the source.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, Mode};
+    ///
+    /// let mut source = vec![[0u8; 32]; 5].into_iter().peekable();
+    /// let (_, leaves) = Mode::DepthWalk.try_calculate_counted(&mut source, &hash).unwrap();
+    ///
+    /// assert_eq!(5, leaves);
+    /// ```
+    pub fn try_calculate_counted<I, H, F>(
+        &self,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<(H, usize), MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        match self {
+            Mode::DepthWalk => DepthWalk::try_calculate_counted(source, hash_fn),
+            Mode::WidthWalk => WidthWalk::try_calculate_counted(source, hash_fn),
+            Mode::ParallelShards => {
+                let leaves: Vec<H> = source.collect();
+                let count = leaves.len();
+                let root = self.try_calculate(&mut leaves.into_iter().peekable(), hash_fn)?;
+                Ok((root, count))
+            }
+            Mode::Auto => {
+                let (mode, head) = self.resolve(source);
+                let mut source = head.into_iter().chain(source).peekable();
+                match mode {
+                    Mode::DepthWalk => DepthWalk::try_calculate_counted(&mut source, hash_fn),
+                    _ => WidthWalk::try_calculate_counted(&mut source, hash_fn),
+                }
+            }
+        }
+    }
+
+    ///
+    /// Calculates the root the same way [`Mode::try_calculate`] does, calling
+    /// `progress` with the number of leaves pulled from the source once every
+    /// [`DEFAULT_STEP`] leaves, and once the source is exhausted. The
+    /// width-walk then calls it once each level is reduced, with the
+    /// cumulative number of the leaves and the nodes calculated so far.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, Mode};
+    ///
+    /// let mut source = vec![[0u8; 32]; 5].into_iter().peekable();
+    /// let mut reported = Vec::new();
+    /// Mode::WidthWalk
+    ///     .try_calculate_with_progress(&mut source, &hash, &mut |done| reported.push(done))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(vec![5, 8, 10, 11], reported);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn try_calculate_with_progress<I, H, F, P>(
+        &self,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+        progress: &mut P,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+        P: FnMut(usize),
+    {
+        let mut leaves = 0;
+        let mut pulled = source.inspect(|_| {
+            leaves += 1;
+            if leaves % DEFAULT_STEP == 0 {
+                progress(leaves);
+            }
+        });
+        let (mode, head) = self.resolve(&mut pulled);
+        let pulled = head.into_iter().chain(pulled);
+        match mode {
+            Mode::DepthWalk => {
+                let root = DepthWalk::try_calculate(&mut pulled.peekable(), hash_fn)?;
+                progress(leaves);
+                Ok(root)
+            }
+            Mode::WidthWalk => {
+                let layer: Vec<H> = pulled.collect();
+                progress(leaves);
+                // the level sizes follow from the number of leaves
+                let mut size = leaves;
+                let mut done = leaves;
+                WidthWalk::try_calculate_observed(
+                    &mut layer.into_iter().peekable(),
+                    0,
+                    &|_, left: &H, right: Option<&H>| hash_fn(left, right),
+                    &mut |level| {
+                        if level > 0 {
+                            size = size.div_ceil(2);
+                            done += size;
+                            progress(done);
+                        }
+                    },
+                )
+            }
+            Mode::ParallelShards => {
+                let collected: Vec<H> = pulled.collect();
+                progress(leaves);
+                mode.try_calculate(&mut collected.into_iter().peekable(), hash_fn)
+            }
+            Mode::Auto => unreachable!("Expected the auto mode to be resolved"),
+        }
+    }
+}
+
+///
+/// Calculates the root of the base16 hashes with the given algorithm. The
+/// hashes are decoded with [`hex_lines_to_hashes`], so they may be mixed-case
+/// and `0x`-prefixed.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, root_from_hex_iter, Mode};
+///
+/// let hashes = vec![
+///     "0000000000000000000000000000000000000000000000000000000000000000",
+///     "0x0101010101010101010101010101010101010101010101010101010101010101",
+/// ];
+/// let root = root_from_hex_iter(hashes.into_iter(), Mode::DepthWalk).unwrap();
+///
+/// assert_eq!(hash(&[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+#[cfg(feature = "std")]
+pub fn root_from_hex_iter<I, S>(iter: I, mode: Mode) -> Result<Hash, MerkleError>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    root_from_source(hex_lines_to_hashes(iter), mode)
+}
+
+///
+/// Calculates the root of the leaves of the [`LeafSource`] with the given
+/// algorithm, failing with the first error of the source, or with
+/// [`MerkleError::EmptySource`] if there are no leaves.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, root_from_source, Mode};
+///
+/// let leaves = vec![Ok([0u8; 32]), Ok([1u8; 32])];
+/// let root = root_from_source(leaves.into_iter(), Mode::DepthWalk).unwrap();
+///
+/// assert_eq!(hash(&[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+#[cfg(feature = "std")]
+pub fn root_from_source<S: LeafSource>(source: S, mode: Mode) -> Result<Hash, MerkleError> {
+    let mut leaves = UntilError::new(source.leaves());
+    let root = mode.try_calculate(&mut (&mut leaves).peekable(), &hash);
+    match leaves.error() {
+        Some(error) => Err(error),
+        None => root,
+    }
+}
+
+///
+/// Calculates the root of the level 0 hashes, i.e. the leaves that are already
+/// hashed by the caller (e.g. with the RFC 6962 leaf hash). The hashes are
+/// only combined into nodes with `hash_fn`, no leaf hashing is applied.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{calculate_from_level0, hash};
+///
+/// let hashes = vec![[0u8; 32], [1u8; 32]];
+/// let root = calculate_from_level0(hashes, &hash).unwrap();
+///
+/// assert_eq!(hash(&[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+pub fn calculate_from_level0<I, F>(hashes: I, hash_fn: &F) -> Result<Hash, MerkleError>
+where
+    I: IntoIterator<Item = Hash>,
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    DepthWalk::try_calculate(&mut hashes.into_iter().peekable(), hash_fn)
+}
+
+///
+/// Decodes the base16 hash, found on the given line (1-based) of the source.
+///
+/// Surrounding whitespace and the `0x` prefix are ignored, the hash may be
+/// lowercase, uppercase or mixed-case.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::decode_hex;
+///
+/// let hex = "0x77D519A56A3BB197BCA02ED25F880A122487914556D587588E633C8368D13053";
+/// assert_eq!(0x77, decode_hex(hex, 1).unwrap()[0]);
+/// assert!(decode_hex("77d5", 1).is_err());
+/// ```
+pub fn decode_hex(hex: &str, line: usize) -> Result<Hash, MerkleError> {
+    let hex = hex.trim();
+    let hex = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+    if hex.len() != 64 {
+        return Err(MerkleError::BadLength {
+            line,
+            got: hex.len(),
+            want: 64,
+        });
+    }
+    let mut byte_buf = [0u8; 32];
+    base16ct::mixed::decode(hex, &mut byte_buf).map_err(|_| MerkleError::BadHex {
+        line,
+        content: hex.to_string(),
+    })?;
+    Ok(byte_buf)
+}
+
+///
+/// The hash of 32 zero bytes, used as a placeholder where no hash is present.
+pub const ZERO_HASH: Hash = [0u8; 32];
+
+///
+/// Returns the sha256 hash of the empty input.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::empty_sha256;
+///
+/// assert_eq!(0xe3, empty_sha256()[0]);
+/// ```
+pub fn empty_sha256() -> Hash {
+    EMPTY_SHA256
+}
+
+// sha256 of the empty input, checked against the digest in the tests
+const EMPTY_SHA256: Hash = [
+    0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24,
+    0x27, 0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52, 0xb8, 0x55,
+];
 
 ///
 /// Calculates the hash of node, given the left and right branch hashes.
@@ -10,6 +523,8 @@ pub mod width_walk;
 /// Left branch must be present. If the right branch hash is `None`, then the
 /// left branch hash is copied over and hashed with itself.
 ///
+/// The sha256 instance of [`hash_with`].
+///
 /// # Examples:
 ///
 /// ```
@@ -23,18 +538,1098 @@ pub mod width_walk;
 /// let result = hash(&left, Some(&left));  // same result
 /// ```
 pub fn hash(left: &Hash, right: Option<&Hash>) -> Hash {
-    let mut input = [0u8; 64];
-
-    input[..32].copy_from_slice(left);
-    if let Some(hash) = right {
-        // right branch has a hash, proceed
-        input[32..].copy_from_slice(hash);
-    } else {
-        // right branch is empty, copy the left hash and proceed
-        input[32..].copy_from_slice(left);
+    hash_with::<Sha256, 32>(left, right)
+}
+
+///
+/// Calculates the hash of node the same way [`hash`] does, with any digest of
+/// the `N` bytes output, e.g. sha512/256 or blake2s256 for 32 bytes, sha512
+/// for 64 bytes.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, hash_with};
+/// use sha2::{Sha512, Sha512_256};
+///
+/// let left = [0u8; 32];
+///
+/// assert_ne!(hash(&left, None), hash_with::<Sha512_256, 32>(&left, None));
+/// assert_eq!(64, hash_with::<Sha512, 64>(&[0u8; 64], None).len());
+/// ```
+pub fn hash_with<D, const N: usize>(left: &[u8; N], right: Option<&[u8; N]>) -> [u8; N]
+where
+    D: Digest,
+    Output<D>: Into<[u8; N]>,
+{
+    let mut hasher = D::new();
+    hasher.update(left);
+    // the right branch is empty, the left one is hashed with itself
+    hasher.update(right.unwrap_or(left));
+    hasher.finalize().into()
+}
+
+///
+/// Calculates the hash of node the same way [`hash`] does, but prefixes each
+/// branch hash with its byte length (little-endian u64), i.e.
+/// `sha256(len(left) || left || len(right) || right)`.
+///
+/// The branches are always 32 bytes long, so the prefix is the constant
+/// `32u64`: it changes the root, but gives no domain separation, e.g. of the
+/// leaves from the nodes; see [`hash_rfc6962`] or [`hash_level_domain`] for
+/// that.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, hash_length_prefixed};
+///
+/// let left = [0u8; 32];
+/// let right = [1u8; 32];
+///
+/// assert_ne!(hash(&left, Some(&right)), hash_length_prefixed(&left, Some(&right)));
+/// assert_eq!(hash_length_prefixed(&left, None), hash_length_prefixed(&left, Some(&left)));
+/// ```
+pub fn hash_length_prefixed(left: &Hash, right: Option<&Hash>) -> Hash {
+    let right = right.unwrap_or(left);
+    let mut hasher = Sha256::new();
+    hasher.update((left.len() as u64).to_le_bytes());
+    hasher.update(left);
+    hasher.update((right.len() as u64).to_le_bytes());
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+///
+/// Calculates the hash of node the Bitcoin way, i.e. the double
+/// `sha256(sha256(left || right))`, the node without the right branch being
+/// hashed with itself the same way [`hash`] does.
+///
+/// Bitcoin displays the transaction and block hashes byte-reversed, so the
+/// displayed transaction ids are reversed into leaves, and the root is
+/// reversed back for display.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, hash_sha256d};
+/// use sha2::{Digest, Sha256};
+///
+/// let left = [0u8; 32];
+/// let once = hash(&left, None);
+///
+/// assert_eq!(<[u8; 32]>::from(Sha256::digest(once)), hash_sha256d(&left, None));
+/// ```
+pub fn hash_sha256d(left: &Hash, right: Option<&Hash>) -> Hash {
+    Sha256::digest(hash(left, right)).into()
+}
+
+///
+/// Calculates the hash of node the RFC 6962 (Certificate Transparency) way,
+/// i.e. `sha256(0x01 || left || right)`. The `0x01` prefix keeps the nodes
+/// apart from the leaves, hashed with [`rfc6962_leaf_hash`].
+///
+/// The node without the right branch is carried up unchanged rather than
+/// hashed with itself, which builds the left-balanced tree of the RFC: the
+/// root of `n` leaves combines the full subtree of the largest power of two
+/// below `n` leaves with the root of the rest.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{calculate_from_level0, hash_rfc6962, rfc6962_leaf_hash};
+///
+/// let leaves = [b"".as_slice(), b"\x00"].map(rfc6962_leaf_hash);
+/// let root = calculate_from_level0(leaves, &hash_rfc6962).unwrap();
+///
+/// assert_eq!(0xfa, root[0]);
+/// ```
+pub fn hash_rfc6962(left: &Hash, right: Option<&Hash>) -> Hash {
+    let Some(right) = right else {
+        return *left;
     };
+    let mut hasher = Sha256::new();
+    hasher.update([RFC6962_NODE]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+///
+/// Calculates the leaf hash of the data, i.e. `sha256(data)`, to build the
+/// tree over the raw records rather than over the precomputed digests.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{empty_sha256, leaf_hash, rfc6962_leaf_hash};
+///
+/// assert_eq!(empty_sha256(), leaf_hash(b""));
+/// assert_ne!(leaf_hash(b"record"), rfc6962_leaf_hash(b"record"));
+/// ```
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    Sha256::digest(data).into()
+}
+
+///
+/// Creates the hash of node salted with the caller's context, i.e.
+/// `sha256(salt || left || right)`, so the trees of different salts share no
+/// subtrees and the proofs of one tree can not be replayed against another.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, hash_with_salt};
+///
+/// let left = [0u8; 32];
+/// let salted = hash_with_salt(b"tree-a");
+///
+/// assert_ne!(hash(&left, None), salted(&left, None));
+/// assert_ne!(hash_with_salt(b"tree-b")(&left, None), salted(&left, None));
+/// assert_eq!(hash(&left, None), hash_with_salt(b"")(&left, None));
+/// ```
+pub fn hash_with_salt(
+    salt: &[u8],
+) -> impl Fn(&Hash, Option<&Hash>) -> Hash + Copy + Sync + Send + '_ {
+    move |left, right| {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(left);
+        hasher.update(right.unwrap_or(left));
+        hasher.finalize().into()
+    }
+}
+
+///
+/// Calculates the RFC 6962 leaf hash of the data, i.e. `sha256(0x00 || data)`.
+pub fn rfc6962_leaf_hash(data: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([RFC6962_LEAF]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+///
+/// The iterator adapter, hashing each byte slice of the wrapped iterator into
+/// the leaf with [`leaf_hash`], or with [`rfc6962_leaf_hash`] if asked to.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, leaf_hash, HashedLeaves, Mode};
+///
+/// let records = ["alice", "bob"];
+/// let mut leaves = HashedLeaves::new(records.iter().map(|record| record.as_bytes()));
+/// let root = Mode::DepthWalk.calculate(&mut leaves.by_ref().peekable(), &hash);
+///
+/// assert_eq!(hash(&leaf_hash(b"alice"), Some(&leaf_hash(b"bob"))), root);
+/// ```
+pub struct HashedLeaves<I> {
+    inner: I,
+    leaf_fn: fn(&[u8]) -> Hash,
+}
+
+impl<I> HashedLeaves<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            leaf_fn: leaf_hash,
+        }
+    }
+
+    ///
+    /// Hashes the data the RFC 6962 way, i.e. `sha256(0x00 || data)`, to
+    /// combine the leaves with [`hash_rfc6962`].
+    pub fn with_rfc6962(mut self, rfc6962: bool) -> Self {
+        self.leaf_fn = if rfc6962 {
+            rfc6962_leaf_hash
+        } else {
+            leaf_hash
+        };
+        self
+    }
+}
+
+impl<I> Iterator for HashedLeaves<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Hash;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|data| (self.leaf_fn)(data.as_ref()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+///
+/// Calculates the synthetic leaf binding the root to the source file, i.e.
+/// `sha256(file_name || file_size)`, the size being little-endian u64.
+/// Prepended to the real leaves, it changes the root and adds one leaf to
+/// the tree.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::source_tag;
+///
+/// assert_ne!(source_tag("a.txt", 65), source_tag("b.txt", 65));
+/// assert_ne!(source_tag("a.txt", 65), source_tag("a.txt", 130));
+/// ```
+pub fn source_tag(file_name: &str, file_size: u64) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(file_name.as_bytes());
+    hasher.update(file_size.to_le_bytes());
+    hasher.finalize().into()
+}
+
+///
+/// The order the branch hashes are concatenated in before hashing.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ConcatOrder {
+    /// `left || right`
+    #[default]
+    LeftRight,
+    /// `right || left`
+    RightLeft,
+}
+
+///
+/// The handling of the node without the right sibling, i.e. the last node of
+/// the level with the odd number of nodes
+///
+/// lvl2       abcc                 abc
+///           /    |               /   |
+/// lvl1     ab    cc             ab   c
+///         / |   /              / |   |
+/// lvl0    a b  c               a b   c
+///       (Duplicate)           (Promote)
+///
+/// The strict policy refuses such trees altogether: only the trees of the
+/// power of two leaves have no odd levels.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OddPolicy {
+    /// The node is hashed with itself
+    #[default]
+    Duplicate,
+    /// The node is carried up to the next level unchanged
+    Promote,
+    /// The calculation fails with [`MerkleError::OddLayer`]; the hash
+    /// functions can not fail, so the algorithms check the levels, see
+    /// [`Mode::try_calculate_strict`], and the node is hashed with itself if
+    /// the check is skipped
+    Strict,
+}
+
+impl OddPolicy {
+    ///
+    /// Wraps the hash function, so the node without the right branch is
+    /// handled according to the policy. The algorithms pass `None` as the
+    /// right branch of such node, so any of them follows the policy.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, OddPolicy};
+    ///
+    /// let promote = OddPolicy::Promote.apply(hash);
+    ///
+    /// assert_eq!([1u8; 32], promote(&[1u8; 32], None));
+    /// assert_eq!(hash(&[1u8; 32], Some(&[2u8; 32])), promote(&[1u8; 32], Some(&[2u8; 32])));
+    /// ```
+    pub fn apply<H, F>(self, hash_fn: F) -> impl Fn(&H, Option<&H>) -> H + Sync + Send
+    where
+        H: Clone,
+        F: Fn(&H, Option<&H>) -> H + Sync + Send,
+    {
+        move |left, right| match (self, right) {
+            (OddPolicy::Promote, None) => left.clone(),
+            (_, right) => hash_fn(left, right),
+        }
+    }
+}
+
+///
+/// The configurable node hash calculation, combining [`hash`] and
+/// [`hash_length_prefixed`] with the concatenation order and the
+/// [`OddPolicy`].
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{hash, Combiner, ConcatOrder};
+///
+/// let left = [0u8; 32];
+/// let right = [1u8; 32];
+/// let combiner = Combiner {
+///     order: ConcatOrder::RightLeft,
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(hash(&right, Some(&left)), combiner.hash(&left, Some(&right)));
+/// ```
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Combiner {
+    /// Prefix each branch with its length, see [`hash_length_prefixed`]
+    pub length_prefixed: bool,
+    /// The order the branches are concatenated in
+    pub order: ConcatOrder,
+    /// The handling of the node without the right branch
+    pub odd: OddPolicy,
+}
 
+impl Combiner {
+    ///
+    /// Calculates the hash of node, given the left and right branch hashes.
+    /// The missing right branch is replaced with the left one, regardless of
+    /// the concatenation order, or the left one is promoted as is, depending
+    /// on the [`OddPolicy`].
+    pub fn hash(&self, left: &Hash, right: Option<&Hash>) -> Hash {
+        match self.length_prefixed {
+            true => self.hash_with(left, right, &hash_length_prefixed),
+            false => self.hash_with(left, right, &hash),
+        }
+    }
+
+    ///
+    /// Calculates the hash of node the same way [`Combiner::hash`] does, with
+    /// the given hash function in place of [`hash`], e.g. the one of
+    /// [`hash_with_salt`]. The `length_prefixed` flag is ignored.
+    pub fn hash_with<F>(&self, left: &Hash, right: Option<&Hash>, hash_fn: &F) -> Hash
+    where
+        F: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        if self.odd == OddPolicy::Promote && right.is_none() {
+            return *left;
+        }
+        let (left, right) = match self.order {
+            ConcatOrder::LeftRight => (left, right),
+            ConcatOrder::RightLeft => (right.unwrap_or(left), Some(left)),
+        };
+        hash_fn(left, right)
+    }
+}
+
+///
+/// Calculates the hash of node the same way [`hash`] does, but binds it to the
+/// level of the node in the tree (level 0 being the leaves), i.e.
+/// `sha256(level || left || right)`, where level is a little-endian u64.
+///
+/// Use with the `calculate_from_level` functions of the algorithms.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{depth_walk::DepthWalk, hash_level_domain};
+///
+/// let leaves = vec![[0u8; 32], [1u8; 32]];
+/// let root = DepthWalk::calculate_from_level(&mut leaves.into_iter().peekable(), 0, &hash_level_domain);
+///
+/// assert_eq!(hash_level_domain(1, &[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+pub fn hash_level_domain(level: usize, left: &Hash, right: Option<&Hash>) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update((level as u64).to_le_bytes());
+    hasher.update(left);
+    hasher.update(right.unwrap_or(left));
+    hasher.finalize().into()
+}
+
+///
+/// Binds the leaf hash to its position in the source, calculating
+/// `sha256(index || leaf)`, where index is a little-endian u64.
+///
+/// Moving a bound leaf to a different position changes its value, thus
+/// changing the root of the tree.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::bind_index;
+///
+/// let leaf = [0u8; 32];
+///
+/// assert_ne!(bind_index(0, &leaf), bind_index(1, &leaf));
+/// ```
+pub fn bind_index(index: u64, leaf: &Hash) -> Hash {
     let mut hasher = Sha256::new();
-    hasher.update(input);
+    hasher.update(index.to_le_bytes());
+    hasher.update(leaf);
     hasher.finalize().into()
 }
+
+///
+/// Compares two hashes in constant time, i.e. the time spent does not depend
+/// on the position of the first differing byte.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::ct_eq;
+///
+/// assert!(ct_eq(&[0u8; 32], &[0u8; 32]));
+/// assert!(!ct_eq(&[0u8; 32], &[1u8; 32]));
+/// ```
+pub fn ct_eq(left: &Hash, right: &Hash) -> bool {
+    let diff = left
+        .iter()
+        .zip(right.iter())
+        .fold(0u8, |acc, (left, right)| acc | (left ^ right));
+    core::hint::black_box(diff) == 0
+}
+
+///
+/// Checks the root is not degenerate, i.e. it is not the [`ZERO_HASH`], and it
+/// is not equal to any leaf of a tree with more than one leaf. Either case
+/// indicates a logic or an input error. The leaves are streamed, each one
+/// compared with the root in constant time.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::{ensure_non_degenerate, hash, ZERO_HASH};
+///
+/// let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+/// let ab = hash(&leaves[0], Some(&leaves[1]));
+/// let root = hash(&ab, Some(&hash(&leaves[2], None)));
+///
+/// assert!(ensure_non_degenerate(&root, leaves).is_ok());
+/// assert!(ensure_non_degenerate(&leaves[1], leaves).is_err());
+/// assert!(ensure_non_degenerate(&ZERO_HASH, leaves).is_err());
+/// ```
+pub fn ensure_non_degenerate<I>(root: &Hash, leaves: I) -> Result<(), MerkleError>
+where
+    I: IntoIterator<Item = Hash>,
+{
+    let mut count = 0usize;
+    let mut equals_leaf = false;
+    for leaf in leaves {
+        count += 1;
+        equals_leaf |= ct_eq(root, &leaf);
+    }
+    match ct_eq(root, &ZERO_HASH) || (count > 1 && equals_leaf) {
+        true => Err(MerkleError::DegenerateRoot),
+        false => Ok(()),
+    }
+}
+
+///
+/// Checks the tree of the given number of leaves has no level with the odd
+/// number of nodes other than the root, failing with
+/// [`MerkleError::OddLayer`] on the lowest one otherwise, see
+/// [`OddPolicy::Strict`]. Only the power of two leaves pass.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::ensure_balanced;
+/// use merkle_root::error::MerkleError;
+///
+/// assert!(ensure_balanced(8).is_ok());
+/// assert!(matches!(
+///     ensure_balanced(12),
+///     Err(MerkleError::OddLayer { level: 2, size: 3 })
+/// ));
+/// ```
+pub fn ensure_balanced(leaf_count: usize) -> Result<(), MerkleError> {
+    match leaf_count.is_power_of_two() || leaf_count == 0 {
+        true => Ok(()),
+        false => {
+            let level = leaf_count.trailing_zeros() as usize;
+            Err(MerkleError::OddLayer {
+                level,
+                size: leaf_count >> level,
+            })
+        }
+    }
+}
+
+///
+/// Returns the height of the tree of the given number of leaves: the number of
+/// levels above the leaves, `ceil(log2(n))`. The single leaf tree, and the
+/// empty one, have the height of 0.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::tree_height;
+///
+/// assert_eq!(0, tree_height(1));
+/// assert_eq!(2, tree_height(3));
+/// assert_eq!(2, tree_height(4));
+/// assert_eq!(3, tree_height(5));
+/// ```
+pub fn tree_height(leaf_count: usize) -> usize {
+    leaf_count.next_power_of_two().trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::decode_hex;
+
+    fn root(leaves: &[Hash], bound: bool) -> Hash {
+        let mut source = leaves
+            .iter()
+            .enumerate()
+            .map(|(index, leaf)| match bound {
+                true => bind_index(index as u64, leaf),
+                false => *leaf,
+            })
+            .peekable();
+        DepthWalk::calculate(&mut source, &hash)
+    }
+
+    #[test]
+    fn empty_sha256_digest() {
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            base16ct::lower::encode_str(&empty_sha256(), &mut buf).unwrap()
+        );
+        assert_ne!(ZERO_HASH, empty_sha256());
+        assert_eq!(Hash::from(Sha256::digest([])), empty_sha256());
+    }
+
+    #[test]
+    fn progress() {
+        let leaves: Vec<Hash> = (0..2 * DEFAULT_STEP + 3)
+            .map(|index| bind_index(index as u64, &ZERO_HASH))
+            .collect();
+        for mode in [
+            Mode::DepthWalk,
+            Mode::WidthWalk,
+            Mode::ParallelShards,
+            Mode::Auto,
+        ] {
+            let mut reported = Vec::new();
+            let root = mode
+                .try_calculate_with_progress(
+                    &mut leaves.iter().copied().peekable(),
+                    &hash,
+                    &mut |done| reported.push(done),
+                )
+                .unwrap();
+            assert_eq!(
+                mode.try_calculate(&mut leaves.iter().copied().peekable(), &hash)
+                    .unwrap(),
+                root
+            );
+            assert_eq!(
+                vec![DEFAULT_STEP, 2 * DEFAULT_STEP, leaves.len()],
+                reported[..3]
+            );
+            assert!(reported.is_sorted());
+        }
+
+        let mut reported = Vec::new();
+        let root = Mode::DepthWalk.try_calculate_with_progress(
+            &mut Vec::<Hash>::new().into_iter().peekable(),
+            &hash,
+            &mut |done| reported.push(done),
+        );
+        assert!(matches!(root, Err(MerkleError::EmptySource)));
+        assert!(reported.is_empty());
+    }
+
+    #[test]
+    fn auto() {
+        let leaves: Vec<Hash> = (0..AUTO_THRESHOLD as u64 + 1)
+            .map(|index| bind_index(index, &ZERO_HASH))
+            .collect();
+        for count in [1, 2, AUTO_THRESHOLD - 1, AUTO_THRESHOLD, AUTO_THRESHOLD + 1] {
+            let leaves = &leaves[..count];
+            let (mode, head) = Mode::Auto.resolve(&mut leaves.iter());
+            let expected = match count < AUTO_THRESHOLD {
+                true => Mode::DepthWalk,
+                false => Mode::WidthWalk,
+            };
+            assert_eq!(expected, mode);
+            assert_eq!(count.min(AUTO_THRESHOLD), head.len());
+            assert_eq!(
+                DepthWalk::calculate(&mut leaves.iter().copied().peekable(), &hash),
+                Mode::Auto.calculate(&mut leaves.iter().copied().peekable(), &hash)
+            );
+        }
+        let (mode, head) = Mode::WidthWalk.resolve(&mut leaves.iter());
+        assert_eq!((Mode::WidthWalk, 0), (mode, head.len()));
+    }
+
+    #[test]
+    fn modes_agree() {
+        let leaves: Vec<Hash> = (0..32).map(|index| bind_index(index, &ZERO_HASH)).collect();
+        // the root of the single leaf tree is the leaf itself
+        for mode in [Mode::DepthWalk, Mode::WidthWalk, Mode::ParallelShards] {
+            let mut source = leaves[..1].iter().copied().peekable();
+            assert_eq!(leaves[0], mode.calculate(&mut source, &hash));
+        }
+        for count in 1..=leaves.len() {
+            let leaves = &leaves[..count];
+            let expected = DepthWalk::calculate(&mut leaves.iter().copied().peekable(), &hash);
+            for mode in [Mode::WidthWalk, Mode::ParallelShards, Mode::Auto] {
+                let mut source = leaves.iter().copied().peekable();
+                assert_eq!(
+                    expected,
+                    mode.calculate(&mut source, &hash),
+                    "{count} leaves"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn height() {
+        let leaves: Vec<Hash> = (0..20).map(|index| bind_index(index, &ZERO_HASH)).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().copied().peekable();
+            let levels = WidthWalk::calculate_levels(&mut source, &hash).unwrap();
+            assert_eq!(levels.len() - 1, tree_height(count));
+        }
+    }
+
+    #[test]
+    fn cancellable() {
+        let leaves: Vec<Hash> = (0..20).map(|index| bind_index(index, &ZERO_HASH)).collect();
+        for mode in [
+            Mode::DepthWalk,
+            Mode::WidthWalk,
+            Mode::ParallelShards,
+            Mode::Auto,
+        ] {
+            let mut source = leaves.iter().copied().peekable();
+            assert_eq!(
+                mode.calculate(&mut leaves.iter().copied().peekable(), &hash),
+                mode.try_calculate_cancellable(&mut source, &hash, &|| false)
+                    .unwrap()
+            );
+            let mut source = leaves.iter().copied().peekable();
+            assert!(matches!(
+                mode.try_calculate_cancellable(&mut source, &hash, &|| true),
+                Err(MerkleError::Cancelled)
+            ));
+        }
+    }
+
+    #[test]
+    fn strict() {
+        let leaves: Vec<Hash> = (0..20).map(|index| bind_index(index, &ZERO_HASH)).collect();
+        for count in 1..=leaves.len() {
+            let leaves = &leaves[..count];
+            for mode in [
+                Mode::DepthWalk,
+                Mode::WidthWalk,
+                Mode::ParallelShards,
+                Mode::Auto,
+            ] {
+                let mut source = leaves.iter().copied().peekable();
+                match (
+                    ensure_balanced(count),
+                    mode.try_calculate_strict(&mut source, &hash),
+                ) {
+                    (Ok(()), Ok(root)) => assert_eq!(
+                        mode.calculate(&mut leaves.iter().copied().peekable(), &hash),
+                        root
+                    ),
+                    (
+                        Err(MerkleError::OddLayer { level, size }),
+                        Err(MerkleError::OddLayer {
+                            level: got_level,
+                            size: got_size,
+                        }),
+                    ) => assert_eq!((level, size), (got_level, got_size)),
+                    (expected, got) => panic!("{count} leaves: {expected:?} != {got:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn root_from_hex() {
+        let hashes = [
+            "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053",
+            "915961583D426FF5D6726EE59FF7E1AD234D8343F60C57AB023B21741FDBA723",
+            "0x7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646",
+            "2163a680ddcd3b7dcfb444d1e19395e59c63781c09e29c8d4b66bfd6460ca142",
+        ];
+        let leaves: Vec<Hash> = hashes
+            .iter()
+            .enumerate()
+            .map(|(index, hex)| decode_hex(hex, index + 1).unwrap())
+            .collect();
+        let expected = hash(
+            &hash(&leaves[0], Some(&leaves[1])),
+            Some(&hash(&leaves[2], Some(&leaves[3]))),
+        );
+        for mode in [
+            Mode::DepthWalk,
+            Mode::WidthWalk,
+            Mode::ParallelShards,
+            Mode::Auto,
+        ] {
+            assert_eq!(expected, root_from_hex_iter(hashes.iter(), mode).unwrap());
+        }
+
+        let hashes = [hashes[0], "not a hash", hashes[1]];
+        assert!(matches!(
+            root_from_hex_iter(hashes.iter(), Mode::DepthWalk),
+            Err(MerkleError::BadLength { line: 2, .. })
+        ));
+        assert!(matches!(
+            root_from_hex_iter(Vec::<String>::new().into_iter(), Mode::DepthWalk),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn degenerate_root() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let single = root(&leaves[..1], false);
+        assert!(ensure_non_degenerate(&single, [leaves[0]]).is_ok());
+
+        let full = root(&leaves, false);
+        assert!(ensure_non_degenerate(&full, leaves).is_ok());
+        // any leaf, not only the edge ones
+        for leaf in leaves {
+            assert!(matches!(
+                ensure_non_degenerate(&leaf, leaves),
+                Err(MerkleError::DegenerateRoot)
+            ));
+        }
+        assert!(matches!(
+            ensure_non_degenerate(&ZERO_HASH, [ZERO_HASH]),
+            Err(MerkleError::DegenerateRoot)
+        ));
+    }
+
+    #[test]
+    fn length_prefixed_root() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32]];
+        let mut source = leaves.into_iter().peekable();
+        let prefixed = DepthWalk::calculate(&mut source, &hash_length_prefixed);
+        let mut source = leaves.into_iter().peekable();
+        let plain = DepthWalk::calculate(&mut source, &hash);
+
+        assert_ne!(plain, prefixed);
+        let left = hash_length_prefixed(&leaves[0], Some(&leaves[1]));
+        let right = hash_length_prefixed(&leaves[2], None);
+        assert_eq!(hash_length_prefixed(&left, Some(&right)), prefixed);
+
+        for index in 0..leaves.len() {
+            let mut source = leaves.into_iter().peekable();
+            let (root, siblings) =
+                DepthWalk::calculate_with_proof(&mut source, index, &hash_length_prefixed).unwrap();
+            assert_eq!(prefixed, root);
+            let proof = Proof { index, siblings };
+            let leaf = &leaves[index];
+            assert!(verify_proof_with(
+                leaf,
+                &proof,
+                &root,
+                &hash_length_prefixed
+            ));
+            assert!(!verify_proof_with(leaf, &proof, &root, &hash));
+        }
+    }
+
+    #[test]
+    fn level_domain_from_intermediate_level() {
+        let leaves: Vec<Hash> = (0..7).map(|byte| [byte; 32]).collect();
+        let level1: Vec<Hash> = leaves
+            .chunks(2)
+            .map(|chunk| hash_level_domain(1, &chunk[0], chunk.get(1)))
+            .collect();
+
+        let mut source = leaves.clone().into_iter().peekable();
+        let full = DepthWalk::calculate_from_level(&mut source, 0, &hash_level_domain);
+        let mut source = leaves.into_iter().peekable();
+        assert_eq!(
+            full,
+            WidthWalk::calculate_from_level(&mut source, 0, &hash_level_domain)
+        );
+
+        let mut source = level1.clone().into_iter().peekable();
+        assert_eq!(
+            full,
+            DepthWalk::calculate_from_level(&mut source, 1, &hash_level_domain)
+        );
+        let mut source = level1.clone().into_iter().peekable();
+        assert_eq!(
+            full,
+            WidthWalk::calculate_from_level(&mut source, 1, &hash_level_domain)
+        );
+        let mut source = level1.into_iter().peekable();
+        assert_ne!(
+            full,
+            DepthWalk::calculate_from_level(&mut source, 0, &hash_level_domain)
+        );
+    }
+
+    #[test]
+    fn from_level0() {
+        let leaves: Vec<Hash> = (0..7).map(|byte| [byte; 32]).collect();
+        // the full computation with the identity leaf hash
+        let level0 = leaves.iter().copied();
+        assert_eq!(
+            root(&leaves, false),
+            calculate_from_level0(level0, &hash).unwrap()
+        );
+        assert!(matches!(
+            calculate_from_level0(Vec::new(), &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn digest() {
+        use sha2::Sha512_256;
+
+        let leaves: Vec<Hash> = (0..5).map(|byte| [byte; 32]).collect();
+        let sha256 = calculate_from_level0(leaves.clone(), &hash_with::<Sha256, 32>).unwrap();
+        let sha512_256 =
+            calculate_from_level0(leaves.clone(), &hash_with::<Sha512_256, 32>).unwrap();
+        assert_eq!(calculate_from_level0(leaves, &hash).unwrap(), sha256);
+        assert_ne!(sha256, sha512_256);
+    }
+
+    ///
+    /// The test vectors of the Certificate Transparency reference
+    /// implementation: the roots of the first 1 to 8 leaves.
+    #[test]
+    fn rfc6962() {
+        let data: [&[u8]; 8] = [
+            b"",
+            b"\x00",
+            b"\x10",
+            b"\x20\x21",
+            b"\x30\x31",
+            b"\x40\x41\x42\x43",
+            b"\x50\x51\x52\x53\x54\x55\x56\x57",
+            b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+        ];
+        let roots = [
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+            "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ];
+        let leaves: Vec<Hash> = data.iter().map(|data| rfc6962_leaf_hash(data)).collect();
+        for (n, root) in roots.iter().enumerate() {
+            let expected = decode_hex(root, 1).unwrap();
+            for mode in [
+                Mode::DepthWalk,
+                Mode::WidthWalk,
+                Mode::ParallelShards,
+                Mode::Auto,
+            ] {
+                let mut source = leaves[..=n].iter().copied().peekable();
+                assert_eq!(expected, mode.calculate(&mut source, &hash_rfc6962));
+            }
+        }
+    }
+
+    #[test]
+    fn hashed_leaves() {
+        let data: [&[u8]; 3] = [b"", b"\x00", b"\x10"];
+        // the RFC 6962 root of the first 3 entries of the test vector
+        let expected = decode_hex(
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            1,
+        )
+        .unwrap();
+        let leaves = HashedLeaves::new(data.iter()).with_rfc6962(true);
+        assert_eq!(3, leaves.size_hint().0);
+        assert_eq!(
+            expected,
+            calculate_from_level0(leaves, &hash_rfc6962).unwrap()
+        );
+
+        let leaves: Vec<Hash> = HashedLeaves::new(data.iter()).collect();
+        assert_eq!(empty_sha256(), leaves[0]);
+        let digests = data.map(|data| Sha256::digest(data).into());
+        assert_eq!(root(&digests, false), root(&leaves, false));
+    }
+
+    #[test]
+    fn salt() {
+        let leaves: Vec<Hash> = (0..5).map(|byte| [byte; 32]).collect();
+        let root = |salt: &[u8], combiner: Combiner| {
+            let salted = hash_with_salt(salt);
+            let mut source = leaves.clone().into_iter().peekable();
+            DepthWalk::calculate(&mut source, &|left, right| {
+                combiner.hash_with(left, right, &salted)
+            })
+        };
+        let combiner = Combiner::default();
+
+        assert_eq!(root(b"", combiner), root(&[], combiner));
+        assert_ne!(root(b"a", combiner), root(b"b", combiner));
+        assert_ne!(root(b"a", combiner), root(b"", combiner));
+        let mut source = leaves.clone().into_iter().peekable();
+        assert_eq!(
+            DepthWalk::calculate(&mut source, &hash),
+            root(b"", combiner)
+        );
+
+        // the salt composes with the rest of the combiner
+        let promote = Combiner {
+            odd: OddPolicy::Promote,
+            ..Default::default()
+        };
+        assert_ne!(root(b"a", combiner), root(b"a", promote));
+        let salted = hash_with_salt(b"a");
+        assert_eq!(leaves[4], promote.hash_with(&leaves[4], None, &salted));
+    }
+
+    #[test]
+    fn concat_order() {
+        let leaves: Vec<Hash> = (0..5).map(|byte| [byte; 32]).collect();
+        let root = |combiner: Combiner| {
+            let mut source = leaves.clone().into_iter().peekable();
+            DepthWalk::calculate(&mut source, &|left, right| combiner.hash(left, right))
+        };
+        let lr = Combiner::default();
+        let rl = Combiner {
+            order: ConcatOrder::RightLeft,
+            ..Default::default()
+        };
+
+        assert_eq!(root(lr), root(Combiner::default()));
+        assert_eq!(root(rl), root(rl));
+        assert_ne!(root(lr), root(rl));
+        assert_eq!(
+            hash(&leaves[1], Some(&leaves[0])),
+            rl.hash(&leaves[0], Some(&leaves[1]))
+        );
+        assert_eq!(hash(&leaves[0], None), rl.hash(&leaves[0], None));
+    }
+
+    #[test]
+    fn bind_index_detects_swap() {
+        let leaves = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        let swapped = [[2u8; 32], [1u8; 32], [3u8; 32], [4u8; 32]];
+
+        assert_ne!(root(&leaves, true), root(&swapped, true));
+        assert_ne!(root(&leaves, true), root(&leaves, false));
+        assert_ne!(root(&swapped, true), root(&swapped, false));
+    }
+
+    ///
+    /// The in-memory leaf source, failing after the given number of leaves.
+    struct InMemory {
+        leaves: Vec<Hash>,
+        next: usize,
+        fail_at: Option<usize>,
+    }
+
+    impl LeafSource for InMemory {
+        fn next_leaf(&mut self) -> Option<Result<Hash, MerkleError>> {
+            if self.fail_at == Some(self.next) {
+                return Some(Err(MerkleError::BadHex {
+                    line: self.next + 1,
+                    content: String::new(),
+                }));
+            }
+            let leaf = *self.leaves.get(self.next)?;
+            self.next += 1;
+            Some(Ok(leaf))
+        }
+    }
+
+    #[test]
+    fn leaf_source() {
+        let leaves = vec![[0u8; 32], [1u8; 32], [2u8; 32]];
+        let source = |fail_at| InMemory {
+            leaves: leaves.clone(),
+            next: 0,
+            fail_at,
+        };
+        let expected = calculate_from_level0(leaves.clone(), &hash).unwrap();
+        for mode in [
+            Mode::DepthWalk,
+            Mode::WidthWalk,
+            Mode::ParallelShards,
+            Mode::Auto,
+        ] {
+            assert_eq!(expected, root_from_source(source(None), mode).unwrap());
+            assert!(matches!(
+                root_from_source(source(Some(2)), mode),
+                Err(MerkleError::BadHex { line: 3, .. })
+            ));
+        }
+        assert!(matches!(
+            root_from_source(source(Some(0)), Mode::DepthWalk),
+            Err(MerkleError::BadHex { line: 1, .. })
+        ));
+        assert!(matches!(
+            root_from_source(std::iter::empty(), Mode::DepthWalk),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn odd_policy() {
+        let [a, b, c, d, e] = [0u8, 1, 2, 3, 4].map(|byte| [byte; 32]);
+        let (ab, cd) = (hash(&a, Some(&b)), hash(&c, Some(&d)));
+        let abcd = hash(&ab, Some(&cd));
+        let cc = hash(&c, None);
+        let eeee = hash(&hash(&e, None), None);
+        let cases = [
+            (vec![a, b, c], OddPolicy::Duplicate, hash(&ab, Some(&cc))),
+            (vec![a, b, c], OddPolicy::Promote, hash(&ab, Some(&c))),
+            (
+                vec![a, b, c, d, e],
+                OddPolicy::Duplicate,
+                hash(&abcd, Some(&eeee)),
+            ),
+            (
+                vec![a, b, c, d, e],
+                OddPolicy::Promote,
+                hash(&abcd, Some(&e)),
+            ),
+        ];
+        for (leaves, odd, expected) in cases {
+            let combiner = Combiner {
+                odd,
+                ..Default::default()
+            };
+            for mode in [
+                Mode::DepthWalk,
+                Mode::WidthWalk,
+                Mode::ParallelShards,
+                Mode::Auto,
+            ] {
+                let mut source = leaves.clone().into_iter().peekable();
+                let root = mode.calculate(&mut source, &|l, r| combiner.hash(l, r));
+                assert_eq!(expected, root);
+                let mut source = leaves.clone().into_iter().peekable();
+                assert_eq!(expected, mode.calculate(&mut source, &odd.apply(hash)));
+            }
+            let hash_fn = odd.apply(hash);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let mut source = leaves.clone().into_iter().peekable();
+                let (root, siblings) =
+                    DepthWalk::calculate_with_proof(&mut source, index, &hash_fn).unwrap();
+                assert_eq!(expected, root);
+                assert!(verify_path_with(leaf, index, &siblings, &root, &hash_fn));
+                assert!(!verify_path_with(
+                    leaf,
+                    index ^ 1,
+                    &siblings,
+                    &root,
+                    &hash_fn
+                ));
+            }
+        }
+        let promote = OddPolicy::Promote.apply(hash);
+        let mut source = [a, b, c, d, e].into_iter().peekable();
+        let (_, siblings) = DepthWalk::calculate_with_proof(&mut source, 4, &promote).unwrap();
+        assert_eq!(vec![(abcd, Side::Left)], siblings);
+    }
+}
@@ -0,0 +1,238 @@
+//!
+//! Implements a sharded merkle tree root calculation.
+//!
+//! Time complexity: O(n*log(n))
+//! Space complexity: O(s*log(n)), where s is the number of shards
+//!
+//! The algorithm splits the leaves into contiguous shards of equal size, the
+//! shard size being a power of two, so every shard is a complete subtree of
+//! the merkle tree
+//!
+//! lvl3            abcdefef
+//!               /         |
+//! lvl2       abcd         efef        <- shard roots
+//!           /   |        /    |
+//! lvl1     ab  cd       ef    (ef)
+//!         / | / |      / |
+//! lvl0    a b c d      e f
+//!        [shard 0]   [shard 1]
+//!
+//! Each shard is read and reduced to its root in parallel with the depth-walk
+//! algorithm. The root of the last shard, which may be incomplete, is lifted
+//! up to the height of the shard by hashing it with itself, the same way the
+//! full tree would. The shard roots are then reduced with the width-walk
+//! algorithm. Since the shard boundaries are aligned, the result is the same
+//! as the root of the whole source.
+//!
+//! Pros: parallel reading and hashing, low space complexity.
+//!
+//! Cons: requires the number of leaves to be known upfront, and the source to
+//! be opened at an arbitrary leaf index.
+//!
+//! Use-cases: multi-thread environments, large files on fast storage.
+//!
+//! Without the `parallel` feature, the shards are reduced one by one.
+
+use super::{depth_walk::DepthWalk, width_walk::WidthWalk};
+use crate::error::MerkleError;
+use alloc::vec::Vec;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+pub struct ParallelShards;
+
+impl ParallelShards {
+    ///
+    /// Calculates the root of `leaves` leaves, split into at most `shards`
+    /// shards. The `open` function must return the source, starting at the
+    /// given leaf index; it is called once per shard.
+    ///
+    /// Panics if there are no leaves, or if a source ends early; see
+    /// [`ParallelShards::try_calculate`].
+    pub fn calculate<O, I, H, F>(leaves: usize, shards: usize, open: &O, hash_fn: &F) -> H
+    where
+        O: Fn(usize) -> I,
+        O: Sync,
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        let open = |start| Ok(open(start).map(Ok));
+        Self::try_calculate(leaves, shards, &open, hash_fn)
+            .expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root the same way [`ParallelShards::calculate`] does,
+    /// the `open` function and the sources being fallible. Fails with
+    /// [`MerkleError::EmptySource`] if there are no leaves, with the first
+    /// error of any shard, and with [`MerkleError::LeafCountMismatch`] if a
+    /// source ends before its shard does.
+    pub fn try_calculate<O, I, H, F>(
+        leaves: usize,
+        shards: usize,
+        open: &O,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        O: Fn(usize) -> Result<I, MerkleError>,
+        O: Sync,
+        I: Iterator<Item = Result<H, MerkleError>>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        if leaves == 0 {
+            return Err(MerkleError::EmptySource);
+        }
+        let shard_size = leaves.div_ceil(shards.max(1)).next_power_of_two();
+        let shards = 0..leaves.div_ceil(shard_size);
+        #[cfg(feature = "parallel")]
+        let shards = shards.into_par_iter();
+        let roots: Vec<H> = shards
+            .map(|shard| {
+                let start = shard * shard_size;
+                let len = shard_size.min(leaves - start);
+                let mut error = None;
+                let mut source = open(start)?
+                    .take(len)
+                    .map_while(|leaf| leaf.map_err(|leaf_error| error = Some(leaf_error)).ok())
+                    .peekable();
+                let root = DepthWalk::try_calculate_counted(&mut source, hash_fn);
+                drop(source);
+                let mut root = match (error, root) {
+                    (Some(error), _) => return Err(error),
+                    (None, Ok((root, read))) if read == len => root,
+                    (None, Ok((_, read))) => {
+                        return Err(MerkleError::LeafCountMismatch {
+                            expected: leaves,
+                            got: start + read,
+                        })
+                    }
+                    (None, Err(_)) => {
+                        return Err(MerkleError::LeafCountMismatch {
+                            expected: leaves,
+                            got: start,
+                        })
+                    }
+                };
+                // lift the root of the incomplete shard up to the shard height
+                let mut size = len.next_power_of_two();
+                while size < shard_size {
+                    root = hash_fn(&root, None);
+                    size *= 2;
+                }
+                Ok(root)
+            })
+            .collect::<Result<_, _>>()?;
+        WidthWalk::try_calculate(&mut roots.into_iter().peekable(), hash_fn)
+    }
+}
+
+///
+/// The number of shards used when none is given: the number of rayon
+/// threads, or a single shard without the `parallel` feature.
+pub fn default_shards() -> usize {
+    #[cfg(feature = "parallel")]
+    return rayon::current_num_threads();
+    #[cfg(not(feature = "parallel"))]
+    return 1;
+}
+
+///
+/// Hash is a Vec<char>, e.g. vec!['a'].
+///
+/// Hashing two branches is defined as a vector, expanded from the left and
+/// right branches, e.g.
+/// hash(vec!['a'], Some(vec!['b'])) => vec!['a', 'b']
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(left: &Vec<char>, right: Option<&Vec<char>>) -> Vec<char> {
+        let mut ret = Vec::new();
+        ret.extend(left);
+        match right {
+            None => ret.extend(left),
+            Some(right) => ret.extend(right),
+        }
+        ret
+    }
+
+    fn leaves(n: usize) -> Vec<Vec<char>> {
+        ('a'..='z').take(n).map(|leaf| vec![leaf]).collect()
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_source() {
+        let source = leaves(0);
+        let open = |start: usize| source[start..].iter().cloned();
+        ParallelShards::calculate(0, 2, &open, &hash);
+    }
+
+    #[test]
+    fn same_as_serial() {
+        for n in [1, 2, 5, 8, 11] {
+            let source = leaves(n);
+            let serial = DepthWalk::calculate(&mut source.clone().into_iter().peekable(), &hash);
+            let open = |start: usize| source[start..].iter().cloned();
+            for shards in 1..=4 {
+                assert_eq!(serial, ParallelShards::calculate(n, shards, &open, &hash));
+            }
+        }
+    }
+
+    #[test]
+    fn two_shards() {
+        let source = leaves(8);
+        let open = |start: usize| source[start..].iter().cloned();
+        assert_eq!(
+            vec!['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'],
+            ParallelShards::calculate(8, 2, &open, &hash)
+        );
+    }
+
+    #[test]
+    fn fallible() {
+        let source = leaves(5);
+        let open = |start: usize| Ok(source[start..].iter().cloned().map(Ok));
+        let serial = DepthWalk::calculate(&mut source.clone().into_iter().peekable(), &hash);
+        assert_eq!(
+            serial,
+            ParallelShards::try_calculate(5, 2, &open, &hash).unwrap()
+        );
+        assert!(matches!(
+            ParallelShards::try_calculate(0, 2, &open, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+        // the source ends before the last shard does
+        assert!(matches!(
+            ParallelShards::try_calculate(7, 2, &open, &hash),
+            Err(MerkleError::LeafCountMismatch {
+                expected: 7,
+                got: 5
+            })
+        ));
+
+        let broken =
+            |start: usize| {
+                Ok(source[start..].iter().cloned().enumerate().map(
+                    move |(index, leaf)| match start + index {
+                        3 => Err(MerkleError::BadUtf8 { line: 4 }),
+                        _ => Ok(leaf),
+                    },
+                ))
+            };
+        assert!(matches!(
+            ParallelShards::try_calculate(5, 2, &broken, &hash),
+            Err(MerkleError::BadUtf8 { line: 4 })
+        ));
+        let unopened = |_| Err::<core::iter::Empty<_>, _>(MerkleError::Cancelled);
+        assert!(matches!(
+            ParallelShards::try_calculate(5, 2, &unopened, &hash),
+            Err(MerkleError::Cancelled)
+        ));
+    }
+}
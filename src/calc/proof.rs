@@ -0,0 +1,891 @@
+//!
+//! Implements the merkle tree inclusion proof types.
+//!
+//! The proof of the leaf is the list of its siblings on the path from the leaf
+//! up to the root, along with the side each sibling sits on relative to the
+//! running node
+//!
+//! lvl2       abcd
+//!           /   |
+//! lvl1    (ab)  cd          proof of c: [(d, Right), (ab, Left)]
+//!         / |  / |
+//! lvl0    a b  c (d)
+//!
+//! The text representation of the proof is the leaf index, followed by the
+//! siblings, each being the side and the base16 hash, separated by a colon,
+//! e.g. `2 right:<hash> left:<hash>`.
+//!
+//! The binary representation of the proof (see [`Proof::to_bytes`]) is:
+//!
+//! | bytes  | content                                     |
+//! |--------|---------------------------------------------|
+//! | 8      | leaf index, little-endian u64               |
+//! | 4      | number of siblings, little-endian u32       |
+//! | 33 * k | siblings: side byte (0: left, 1: right) and |
+//! |        | 32 bytes of the hash                        |
+//!
+//! The right-edge node of the level with the odd number of nodes is hashed
+//! with itself, so its proof repeats the running node as the sibling
+//!
+//! lvl2       abcc
+//!           /    |
+//! lvl1    (ab)   cc         proof of c: [(c, Right), (ab, Left)]
+//!         / |   /
+//! lvl0    a b  c
+//!
+//! The compact binary representation (see [`Proof::to_compact_bytes`]) has the
+//! same layout, except such siblings are encoded as a single side byte 2,
+//! without the hash. They are reconstructed from the leaf when the compact
+//! proof is read back.
+//!
+//! The batch proof of several leaves (see [`BatchProof`]) shares the siblings
+//! of their paths: the nodes the verifier calculates from the proven leaves
+//! themselves are left out, as well as the right-edge nodes hashed with
+//! themselves, which the verifier finds from the number of leaves
+//!
+//! lvl2       abcd
+//!           /    |
+//! lvl1     ab     cd        batch proof of a and d: [b, c]
+//!         /  |   /  |
+//! lvl0    a (b) (c)  d
+//!
+//! The siblings are ordered level by level from the leaves up, and from left
+//! to right within the level.
+
+use super::{ct_eq, decode_hex, hash};
+use crate::error::MerkleError;
+use crate::hex::HexHash;
+use crate::Hash;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::Peekable;
+use core::str::FromStr;
+
+///
+/// The side the sibling sits on, relative to the running node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Side {
+    /// The parent is `hash(sibling, node)`
+    Left,
+    /// The parent is `hash(node, sibling)`
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+impl FromStr for Side {
+    type Err = MerkleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Side::Left),
+            "right" => Ok(Side::Right),
+            _ => Err(MerkleError::BadProof {
+                reason: format!("invalid side {s:?}"),
+            }),
+        }
+    }
+}
+
+///
+/// The inclusion proof of the leaf with the given index.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::Proof;
+///
+/// let proof = Proof::new(2).with_right([1u8; 32]).with_left([2u8; 32]);
+/// let text = proof.to_string();
+///
+/// assert_eq!(proof, text.parse().unwrap());
+/// assert_eq!(proof, Proof::from_bytes(&proof.to_bytes()).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Proof {
+    /// Index of the leaf the proof is for
+    pub index: usize,
+    /// Siblings of the path from the leaf up to the root
+    #[cfg_attr(feature = "serde", serde(with = "serde_siblings"))]
+    pub siblings: Vec<(Hash, Side)>,
+}
+
+impl Proof {
+    ///
+    /// Creates an empty proof of the leaf with the given index.
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            siblings: Vec::new(),
+        }
+    }
+
+    ///
+    /// Appends the sibling sitting on the left of the running node.
+    pub fn with_left(mut self, hash: Hash) -> Self {
+        self.siblings.push((hash, Side::Left));
+        self
+    }
+
+    ///
+    /// Appends the sibling sitting on the right of the running node.
+    pub fn with_right(mut self, hash: Hash) -> Self {
+        self.siblings.push((hash, Side::Right));
+        self
+    }
+
+    ///
+    /// Serializes the proof into its binary representation, see the module
+    /// documentation for the layout.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + 33 * self.siblings.len());
+        bytes.extend((self.index as u64).to_le_bytes());
+        bytes.extend((self.siblings.len() as u32).to_le_bytes());
+        for (hash, side) in &self.siblings {
+            bytes.push(match side {
+                Side::Left => 0,
+                Side::Right => 1,
+            });
+            bytes.extend(hash);
+        }
+        bytes
+    }
+
+    ///
+    /// Serializes the proof into its compact binary representation, replacing
+    /// the siblings equal to the running node with the "self" marker. The
+    /// running node is calculated from the `leaf` with `hash_fn`.
+    pub fn to_compact_bytes<F>(&self, leaf: &Hash, hash_fn: &F) -> Vec<u8>
+    where
+        F: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        let mut bytes = Vec::with_capacity(12 + 33 * self.siblings.len());
+        bytes.extend((self.index as u64).to_le_bytes());
+        bytes.extend((self.siblings.len() as u32).to_le_bytes());
+        let mut node = *leaf;
+        for (sibling, side) in &self.siblings {
+            match side {
+                Side::Right if *sibling == node => bytes.push(2),
+                Side::Left => {
+                    bytes.push(0);
+                    bytes.extend(sibling);
+                }
+                Side::Right => {
+                    bytes.push(1);
+                    bytes.extend(sibling);
+                }
+            }
+            node = parent(&node, sibling, *side, hash_fn);
+        }
+        bytes
+    }
+
+    ///
+    /// Deserializes the proof from its compact binary representation,
+    /// reconstructing the "self" siblings from the `leaf` with `hash_fn`.
+    pub fn from_compact_bytes<F>(
+        bytes: &[u8],
+        leaf: &Hash,
+        hash_fn: &F,
+    ) -> Result<Self, MerkleError>
+    where
+        F: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        let bad_proof = |reason: &str| MerkleError::BadProof {
+            reason: reason.to_string(),
+        };
+        if bytes.len() < 12 {
+            return Err(bad_proof("truncated header"));
+        }
+        let index = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let index = usize::try_from(index).map_err(|_| bad_proof("index out of range"))?;
+        let count = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let mut body = &bytes[12..];
+        let mut proof = Self::new(index);
+        let mut node = *leaf;
+        for _ in 0..count {
+            let (&side, rest) = body.split_first().ok_or(bad_proof("unexpected length"))?;
+            let (sibling, side, rest) = match side {
+                2 => (node, Side::Right, rest),
+                0 | 1 if rest.len() >= 32 => {
+                    let side = if side == 0 { Side::Left } else { Side::Right };
+                    (rest[..32].try_into().unwrap(), side, &rest[32..])
+                }
+                0 | 1 => return Err(bad_proof("unexpected length")),
+                _ => return Err(bad_proof("invalid side byte")),
+            };
+            node = parent(&node, &sibling, side, hash_fn);
+            proof.siblings.push((sibling, side));
+            body = rest;
+        }
+        if !body.is_empty() {
+            return Err(bad_proof("unexpected length"));
+        }
+        Ok(proof)
+    }
+
+    ///
+    /// Deserializes the proof from its binary representation.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let bad_proof = |reason: &str| MerkleError::BadProof {
+            reason: reason.to_string(),
+        };
+        if bytes.len() < 12 {
+            return Err(bad_proof("truncated header"));
+        }
+        let index = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let index = usize::try_from(index).map_err(|_| bad_proof("index out of range"))?;
+        let count = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let body = &bytes[12..];
+        if count.checked_mul(33) != Some(body.len()) {
+            return Err(bad_proof("unexpected length"));
+        }
+        let mut proof = Self::new(index);
+        for sibling in body.chunks(33) {
+            let hash: Hash = sibling[1..].try_into().unwrap();
+            proof = match sibling[0] {
+                0 => proof.with_left(hash),
+                1 => proof.with_right(hash),
+                _ => return Err(bad_proof("invalid side byte")),
+            };
+        }
+        Ok(proof)
+    }
+}
+
+impl fmt::Display for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index)?;
+        for (hash, side) in &self.siblings {
+            write!(f, " {side}:{}", HexHash(*hash))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Proof {
+    type Err = MerkleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let index = parts.next().unwrap_or_default();
+        let index = index.parse().map_err(|_| MerkleError::BadProof {
+            reason: format!("invalid index {index:?}"),
+        })?;
+        let mut proof = Self::new(index);
+        for sibling in parts {
+            let (side, hex) = sibling.split_once(':').ok_or(MerkleError::BadProof {
+                reason: format!("invalid sibling {sibling:?}"),
+            })?;
+            let hash = decode_hex(hex, 1).map_err(|_| MerkleError::BadProof {
+                reason: format!("invalid hash {hex:?}"),
+            })?;
+            proof.siblings.push((hash, side.parse()?));
+        }
+        Ok(proof)
+    }
+}
+
+///
+/// Verifies the proof of the `leaf` against the `root`, calculated with the
+/// default [`hash`] function.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{hash, verify_proof, Proof};
+///
+/// let (a, b) = ([0u8; 32], [1u8; 32]);
+/// let root = hash(&a, Some(&b));
+///
+/// assert!(verify_proof(&b, &Proof::new(1).with_left(a), &root));
+/// assert!(!verify_proof(&b, &Proof::new(0).with_left(a), &root));
+/// ```
+pub fn verify_proof(leaf: &Hash, proof: &Proof, root: &Hash) -> bool {
+    verify_proof_with(leaf, proof, root, &hash)
+}
+
+///
+/// Verifies the proof of the `leaf` against the `root`, calculated with the
+/// given hash function. The sides of the siblings must match the bits of the
+/// leaf index, from the lowest one.
+pub fn verify_proof_with<F>(leaf: &Hash, proof: &Proof, root: &Hash, hash_fn: &F) -> bool
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    verify_path_with(leaf, proof.index, &proof.siblings, root, hash_fn)
+}
+
+///
+/// Verifies the sibling path of the leaf with the given `index` against the
+/// `root`, calculated with the default [`hash`] function. The path is the
+/// one returned by
+/// [`DepthWalk::calculate_with_proof`](super::depth_walk::DepthWalk::calculate_with_proof),
+/// so the prover and the verifier need not share the [`Proof`] type.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{depth_walk::DepthWalk, hash, verify_path};
+///
+/// let leaves = [[0u8; 32], [1u8; 32], [2u8; 32]];
+/// let mut source = leaves.into_iter().peekable();
+/// let (root, siblings) = DepthWalk::calculate_with_proof(&mut source, 2, &hash).unwrap();
+///
+/// assert!(verify_path(&leaves[2], 2, &siblings, &root));
+/// assert!(!verify_path(&leaves[1], 2, &siblings, &root));
+/// ```
+pub fn verify_path(leaf: &Hash, index: usize, siblings: &[(Hash, Side)], root: &Hash) -> bool {
+    verify_path_with(leaf, index, siblings, root, &hash)
+}
+
+///
+/// Verifies the sibling path the same way [`verify_path`] does, with the
+/// given hash function. The sides of the siblings must match the bits of the
+/// leaf index, from the lowest one; the levels the node was carried up
+/// without a sibling are the zero bits skipped before a left sibling.
+pub fn verify_path_with<F>(
+    leaf: &Hash,
+    index: usize,
+    siblings: &[(Hash, Side)],
+    root: &Hash,
+    hash_fn: &F,
+) -> bool
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    let mut node = *leaf;
+    let mut level = 0;
+    for (sibling, side) in siblings {
+        // the left sibling at the zero bit means the node was carried up
+        // without the right branch, see `OddPolicy::Promote`
+        while *side == Side::Left && (index >> level) & 1 == 0 {
+            node = hash_fn(&node, None);
+            level += 1;
+            if level >= usize::BITS {
+                return false;
+            }
+        }
+        if *side == Side::Right && (index >> level) & 1 == 1 {
+            return false;
+        }
+        node = parent(&node, sibling, *side, hash_fn);
+        level += 1;
+    }
+    (level >= usize::BITS || index >> level == 0) && ct_eq(&node, root)
+}
+
+///
+/// Verifies the proofs of the leaves against the same `root` in parallel,
+/// returning the outcome of each proof in the order of `items`.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{hash, verify_proofs_par, Proof};
+///
+/// let (a, b) = ([0u8; 32], [1u8; 32]);
+/// let root = hash(&a, Some(&b));
+/// let items = [(a, Proof::new(0).with_right(b)), (b, Proof::new(1).with_left(b))];
+///
+/// assert_eq!(vec![true, false], verify_proofs_par(&root, &items, &hash));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn verify_proofs_par<F>(root: &Hash, items: &[(Hash, Proof)], hash_fn: &F) -> Vec<bool>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash + Sync,
+{
+    use rayon::prelude::*;
+    items
+        .par_iter()
+        .map(|(leaf, proof)| verify_proof_with(leaf, proof, root, hash_fn))
+        .collect()
+}
+
+///
+/// The inclusion proof of several leaves at once, see the module
+/// documentation.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{calculate_batch_proof, hash, verify_batch_proof};
+///
+/// let leaves: Vec<[u8; 32]> = (0..8u8).map(|byte| [byte; 32]).collect();
+/// let mut source = leaves.clone().into_iter().peekable();
+/// let (root, proof) = calculate_batch_proof(&mut source, &[0, 1], &hash).unwrap();
+///
+/// assert_eq!(2, proof.siblings.len());
+/// assert!(verify_batch_proof(&[leaves[0], leaves[1]], &proof, &root));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchProof {
+    /// Number of leaves of the tree
+    pub leaves: usize,
+    /// Indices of the proven leaves, ascending and without duplicates
+    pub indices: Vec<usize>,
+    /// Siblings the verifier can not calculate, from the leaves up
+    pub siblings: Vec<Hash>,
+}
+
+///
+/// Calculates the root along with the batch proof of the leaves with the
+/// given indices, in the same single walk the depth-walk does. The indices
+/// are sorted and deduplicated.
+///
+/// Fails with [`MerkleError::EmptySource`] if the source is empty, and with
+/// [`MerkleError::IndexOutOfRange`] if the source has no leaf with one of the
+/// indices.
+pub fn calculate_batch_proof<I, F>(
+    source: &mut Peekable<I>,
+    indices: &[usize],
+    hash_fn: &F,
+) -> Result<(Hash, BatchProof), MerkleError>
+where
+    I: Iterator<Item = Hash>,
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    let mut indices = indices.to_vec();
+    indices.sort_unstable();
+    indices.dedup();
+    let mut walk = BatchWalk {
+        next: 0,
+        indices: &indices,
+        siblings: Vec::new(),
+    };
+    let mut root = walk
+        .walk_down(0, source, hash_fn)
+        .ok_or(MerkleError::EmptySource)?;
+    while source.peek().is_some() {
+        let right = walk.walk_down(root.height, source, hash_fn);
+        root = walk.parent(root, right, hash_fn);
+    }
+    let leaves = walk.next;
+    if let Some(&index) = indices.last().filter(|&&index| index >= leaves) {
+        return Err(MerkleError::IndexOutOfRange { index, leaves });
+    }
+    // the siblings are found bottom-up within each subtree only
+    walk.siblings
+        .sort_unstable_by_key(|(height, position, _)| (*height, *position));
+    let siblings = walk.siblings.into_iter().map(|(.., hash)| hash).collect();
+    let proof = BatchProof {
+        leaves,
+        indices,
+        siblings,
+    };
+    Ok((root.hash, proof))
+}
+
+///
+/// Verifies the batch proof of the `leaves`, given in the order of the
+/// indices of the proof, against the `root`, calculated with the default
+/// [`hash`] function.
+pub fn verify_batch_proof(leaves: &[Hash], proof: &BatchProof, root: &Hash) -> bool {
+    verify_batch_proof_with(leaves, proof, root, &hash)
+}
+
+///
+/// Verifies the batch proof the same way [`verify_batch_proof`] does, with
+/// the given hash function. Every sibling of the proof must be used.
+pub fn verify_batch_proof_with<F>(
+    leaves: &[Hash],
+    proof: &BatchProof,
+    root: &Hash,
+    hash_fn: &F,
+) -> bool
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    let indices = &proof.indices;
+    let ascending = indices.windows(2).all(|pair| pair[0] < pair[1]);
+    let in_range = indices.last().is_some_and(|&last| last < proof.leaves);
+    if leaves.len() != indices.len() || !ascending || !in_range {
+        return false;
+    }
+    let mut siblings = proof.siblings.iter();
+    let mut layer: Vec<(usize, Hash)> = indices
+        .iter()
+        .copied()
+        .zip(leaves.iter().copied())
+        .collect();
+    let mut size = proof.leaves;
+    while size > 1 {
+        let mut next = Vec::with_capacity(layer.len());
+        let mut nodes = layer.into_iter().peekable();
+        while let Some((position, node)) = nodes.next() {
+            let parent = match position % 2 {
+                1 => match siblings.next() {
+                    Some(sibling) => hash_fn(sibling, Some(&node)),
+                    None => return false,
+                },
+                _ => match nodes.next_if(|(right, _)| *right == position + 1) {
+                    Some((_, right)) => hash_fn(&node, Some(&right)),
+                    // the right-edge node is hashed with itself
+                    None if position + 1 == size => hash_fn(&node, None),
+                    None => match siblings.next() {
+                        Some(sibling) => hash_fn(&node, Some(sibling)),
+                        None => return false,
+                    },
+                },
+            };
+            next.push((position / 2, parent));
+        }
+        layer = next;
+        size = size.div_ceil(2);
+    }
+    siblings.next().is_none() && ct_eq(&layer[0].1, root)
+}
+
+///
+/// The node of the [`BatchWalk`]: its hash, height and position within the
+/// level, and whether it covers any of the proven leaves.
+struct BatchNode {
+    hash: Hash,
+    height: usize,
+    position: usize,
+    on_path: bool,
+}
+
+///
+/// The state of [`calculate_batch_proof`]: the index of the next leaf read,
+/// the indices of the leaves being proven and the siblings found so far,
+/// along with their heights and positions.
+struct BatchWalk<'a> {
+    next: usize,
+    indices: &'a [usize],
+    siblings: Vec<(usize, usize, Hash)>,
+}
+
+impl BatchWalk<'_> {
+    fn walk_down<I, F>(
+        &mut self,
+        height: usize,
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Option<BatchNode>
+    where
+        I: Iterator<Item = Hash>,
+        F: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        if height == 0 {
+            let hash = source.next()?;
+            let position = self.next;
+            self.next += 1;
+            return Some(BatchNode {
+                hash,
+                height,
+                position,
+                on_path: self.indices.binary_search(&position).is_ok(),
+            });
+        }
+        let left = self.walk_down(height - 1, source, hash_fn)?;
+        let right = self.walk_down(height - 1, source, hash_fn);
+        Some(self.parent(left, right, hash_fn))
+    }
+
+    fn parent<F>(&mut self, left: BatchNode, right: Option<BatchNode>, hash_fn: &F) -> BatchNode
+    where
+        F: Fn(&Hash, Option<&Hash>) -> Hash,
+    {
+        let right_on_path = right.as_ref().is_some_and(|right| right.on_path);
+        match &right {
+            Some(right) if left.on_path && !right_on_path => {
+                self.siblings
+                    .push((right.height, right.position, right.hash))
+            }
+            Some(_) if right_on_path && !left.on_path => {
+                self.siblings.push((left.height, left.position, left.hash))
+            }
+            // the node without the right branch is hashed with itself
+            _ => {}
+        }
+        BatchNode {
+            hash: hash_fn(&left.hash, right.as_ref().map(|right| &right.hash)),
+            height: left.height + 1,
+            position: left.position / 2,
+            on_path: left.on_path || right_on_path,
+        }
+    }
+}
+
+fn parent<F>(node: &Hash, sibling: &Hash, side: Side, hash_fn: &F) -> Hash
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    match side {
+        Side::Left => hash_fn(sibling, Some(node)),
+        Side::Right => hash_fn(node, Some(sibling)),
+    }
+}
+
+///
+/// Serializes the siblings as a list of `{"side": ..., "hash": ...}` objects,
+/// the hash being a lowercase base16 string.
+#[cfg(feature = "serde")]
+mod serde_siblings {
+    use super::{Hash, HexHash, Side};
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Sibling {
+        side: Side,
+        hash: String,
+    }
+
+    pub fn serialize<S: Serializer>(
+        siblings: &[(Hash, Side)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        siblings
+            .iter()
+            .map(|(hash, side)| Sibling {
+                side: *side,
+                hash: HexHash(*hash).to_string(),
+            })
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(Hash, Side)>, D::Error> {
+        Vec::<Sibling>::deserialize(deserializer)?
+            .into_iter()
+            .map(|sibling| {
+                let mut hash = [0u8; 32];
+                base16ct::lower::decode(&sibling.hash, &mut hash)
+                    .map_err(|_| D::Error::custom("expected lowercase base16 hash"))?;
+                Ok((hash, sibling.side))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::depth_walk::DepthWalk;
+
+    #[test]
+    fn side_round_trip() {
+        for side in [Side::Left, Side::Right] {
+            assert_eq!(side, side.to_string().parse().unwrap());
+        }
+        assert!("up".parse::<Side>().is_err());
+    }
+
+    #[test]
+    fn proof_round_trip() {
+        let proof = Proof::new(5)
+            .with_left([1u8; 32])
+            .with_right([2u8; 32])
+            .with_left([3u8; 32]);
+        assert_eq!(proof, proof.to_string().parse().unwrap());
+        assert_eq!(proof, Proof::from_bytes(&proof.to_bytes()).unwrap());
+        assert_eq!(12 + 3 * 33, proof.to_bytes().len());
+
+        let proof = Proof::new(0);
+        assert_eq!("0", proof.to_string());
+        assert_eq!(proof, "0".parse().unwrap());
+
+        assert!("1 left".parse::<Proof>().is_err());
+        assert!("x left:00".parse::<Proof>().is_err());
+        assert!(Proof::from_bytes(&[0u8; 11]).is_err());
+        // the count of the siblings far beyond the body
+        let mut bytes = [0u8; 12];
+        bytes[8..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Proof::from_bytes(&bytes).is_err());
+        assert!(Proof::from_compact_bytes(&bytes, &[0u8; 32], &hash).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn proof_json_round_trip() {
+        let proof = Proof::new(2).with_left([0xabu8; 32]).with_right([1u8; 32]);
+        let json = serde_json::to_string(&proof).unwrap();
+        assert!(json.contains(&format!(
+            r#"{{"side":"left","hash":"{}"}}"#,
+            "ab".repeat(32)
+        )));
+        assert_eq!(proof, serde_json::from_str(&json).unwrap());
+
+        let upper = json.replace("ab", "AB");
+        assert!(serde_json::from_str::<Proof>(&upper).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn proofs_par() {
+        let leaves: Vec<Hash> = (0..4u8).map(|byte| [byte; 32]).collect();
+        let ab = hash(&leaves[0], Some(&leaves[1]));
+        let cd = hash(&leaves[2], Some(&leaves[3]));
+        let root = hash(&ab, Some(&cd));
+        let proof = |index: usize| {
+            let proof = Proof::new(index);
+            let proof = match index % 2 {
+                0 => proof.with_right(leaves[index + 1]),
+                _ => proof.with_left(leaves[index - 1]),
+            };
+            match index / 2 {
+                0 => proof.with_right(cd),
+                _ => proof.with_left(ab),
+            }
+        };
+        let mut items: Vec<(Hash, Proof)> =
+            (0..4).map(|index| (leaves[index], proof(index))).collect();
+        // tamper with the leaf, the sibling and the index
+        items[1].0 = [9u8; 32];
+        items[2].1.siblings[1].0 = [9u8; 32];
+        items.push((
+            leaves[0],
+            Proof {
+                index: 1,
+                ..proof(0)
+            },
+        ));
+        assert_eq!(
+            vec![true, false, false, true, false],
+            verify_proofs_par(&root, &items, &hash)
+        );
+    }
+
+    #[test]
+    fn compact_proof() {
+        let leaves: Vec<Hash> = (0..5u8).map(|byte| [byte; 32]).collect();
+        let mut source = leaves.clone().into_iter().peekable();
+        let root = DepthWalk::calculate(&mut source, &hash);
+
+        // lvl3         abcdeeee
+        // lvl2     abcd         eeee
+        // lvl1   ab    cd    ee
+        // lvl0  a  b  c  d  e
+        let ab = hash(&leaves[0], Some(&leaves[1]));
+        let abcd = hash(&ab, Some(&hash(&leaves[2], Some(&leaves[3]))));
+        let ee = hash(&leaves[4], None);
+        let proof = Proof::new(4)
+            .with_right(leaves[4])
+            .with_right(ee)
+            .with_left(abcd);
+        assert!(verify_proof(&leaves[4], &proof, &root));
+
+        let compact = proof.to_compact_bytes(&leaves[4], &hash);
+        assert_eq!(12 + 33 * 3, proof.to_bytes().len());
+        assert_eq!(12 + 1 + 1 + 33, compact.len());
+
+        let restored = Proof::from_compact_bytes(&compact, &leaves[4], &hash).unwrap();
+        assert_eq!(proof, restored);
+        assert!(verify_proof(&leaves[4], &restored, &root));
+
+        let proof = Proof::new(1)
+            .with_left(leaves[0])
+            .with_right(hash(&leaves[2], Some(&leaves[3])))
+            .with_right(hash(&ee, None));
+        assert!(verify_proof(&leaves[1], &proof, &root));
+        assert_eq!(
+            proof.to_bytes().len(),
+            proof.to_compact_bytes(&leaves[1], &hash).len()
+        );
+        assert!(Proof::from_compact_bytes(&compact[..20], &leaves[4], &hash).is_err());
+    }
+
+    #[test]
+    fn path_round_trip() {
+        for n in [1u8, 2, 3, 5, 8] {
+            let leaves: Vec<Hash> = (0..n).map(|byte| [byte; 32]).collect();
+            for index in 0..n as usize {
+                let mut source = leaves.clone().into_iter().peekable();
+                let (root, siblings) =
+                    DepthWalk::calculate_with_proof(&mut source, index, &hash).unwrap();
+                assert!(verify_path(&leaves[index], index, &siblings, &root));
+                // the path does not prove any other leaf or position
+                let other = (index + 1) % n as usize;
+                assert_eq!(n == 1, verify_path(&leaves[other], index, &siblings, &root));
+                assert_eq!(n == 1, verify_path(&leaves[index], other, &siblings, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn batch_proof() {
+        let leaves: Vec<Hash> = (0..8u8).map(|byte| [byte; 32]).collect();
+        let mut source = leaves.clone().into_iter().peekable();
+        let (root, proof) = calculate_batch_proof(&mut source, &[1, 0, 1], &hash).unwrap();
+        assert_eq!(
+            root,
+            DepthWalk::calculate(&mut leaves.clone().into_iter().peekable(), &hash)
+        );
+        assert_eq!(vec![0, 1], proof.indices);
+        // the sibling a of b and the sibling b of a are not needed
+        assert_eq!(2, proof.siblings.len());
+        let single = |index| {
+            let mut source = leaves.clone().into_iter().peekable();
+            let (_, siblings) = DepthWalk::calculate_with_proof(&mut source, index, &hash).unwrap();
+            siblings.len()
+        };
+        assert_eq!(6, single(0) + single(1));
+        assert!(verify_batch_proof(&leaves[..2], &proof, &root));
+
+        // tamper with the leaf, the sibling, the indices and the leaf count
+        assert!(!verify_batch_proof(&[leaves[1], leaves[0]], &proof, &root));
+        let mut tampered = proof.clone();
+        tampered.siblings[0] = [9u8; 32];
+        assert!(!verify_batch_proof(&leaves[..2], &tampered, &root));
+        let tampered = BatchProof {
+            indices: vec![2, 3],
+            ..proof.clone()
+        };
+        assert!(!verify_batch_proof(&leaves[2..4], &tampered, &root));
+        let tampered = BatchProof {
+            leaves: 3,
+            ..proof.clone()
+        };
+        assert!(!verify_batch_proof(&leaves[..2], &tampered, &root));
+        assert!(!verify_batch_proof(&leaves[..1], &proof, &root));
+
+        let mut source = leaves.clone().into_iter().peekable();
+        assert!(matches!(
+            calculate_batch_proof(&mut source, &[3, 8], &hash),
+            Err(MerkleError::IndexOutOfRange {
+                index: 8,
+                leaves: 8
+            })
+        ));
+        let mut source = Vec::new().into_iter().peekable();
+        assert!(matches!(
+            calculate_batch_proof(&mut source, &[0], &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn batch_proof_subsets() {
+        let promote = crate::calc::OddPolicy::Promote.apply(hash);
+        for n in 1..=9usize {
+            let leaves: Vec<Hash> = (0..n as u8).map(|byte| [byte; 32]).collect();
+            for subset in 1..1u32 << n {
+                let indices: Vec<usize> = (0..n).filter(|index| subset >> index & 1 == 1).collect();
+                let proven: Vec<Hash> = indices.iter().map(|&index| leaves[index]).collect();
+                let mut source = leaves.clone().into_iter().peekable();
+                let (root, proof) = calculate_batch_proof(&mut source, &indices, &hash).unwrap();
+                assert!(verify_batch_proof(&proven, &proof, &root));
+
+                let mut source = leaves.clone().into_iter().peekable();
+                let (root, proof) = calculate_batch_proof(&mut source, &indices, &promote).unwrap();
+                assert!(verify_batch_proof_with(&proven, &proof, &root, &promote));
+            }
+        }
+    }
+}
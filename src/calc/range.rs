@@ -0,0 +1,190 @@
+//!
+//! Implements the merkle subtree root calculation over byte ranges of the
+//! source file.
+//!
+//! Time complexity: O(k*log(k)) per range of k leaves
+//! Space complexity: O(log(k))
+//!
+//! The caller splits the file into byte ranges, each range being aligned to
+//! the line boundaries and holding a power of two number of leaves, so every
+//! range is a complete subtree of the merkle tree
+//!
+//! lvl3            abcdefef
+//!               /         |
+//! lvl2       abcd         efef        <- combined roots
+//!           /   |        /    |
+//! lvl1     ab  cd       ef    (ef)
+//!         / | / |      / |
+//! lvl0    a b c d      e f
+//!       [0, 260)    [260, 390)
+//!
+//! The subtree roots of the ranges are calculated independently (e.g. in
+//! separate processes or passes over the file) with [`subtree_root`], and then
+//! combined into the root of the whole file with [`combine_roots`], the same
+//! way the parallel-shards algorithm combines its shards.
+//!
+//! Pros: parallelizable at the I/O level, ranges can be calculated on demand.
+//!
+//! Cons: the caller is responsible for splitting the file.
+//!
+//! Use-cases: huge files processed in passes or by multiple workers.
+
+use super::{depth_walk::DepthWalk, width_walk::WidthWalk};
+use crate::error::MerkleError;
+use crate::source::{SourceReader, UntilError};
+use crate::Hash;
+
+// 65 bytes per leaf: 64 hex characters and a newline
+const STRIDE: u64 = 65;
+
+///
+/// Calculates the root of the subtree, formed by the leaves in the byte range
+/// `[start, end)` of the base16 source file. Both ends must be aligned to the
+/// line boundaries, the range must hold a power of two number of leaves.
+/// Returns the number of leaves along with the root.
+///
+/// # Examples
+///
+/// ```no_run
+/// use merkle_root::calc::{hash, range::{combine_roots, subtree_root}};
+///
+/// let file = String::from("input.txt");
+/// let left = subtree_root(file.clone(), 0, 4 * 65, &hash).unwrap();
+/// let right = subtree_root(file, 4 * 65, 6 * 65, &hash).unwrap();
+/// let root = combine_roots(&[left, right], &hash).unwrap();
+/// ```
+pub fn subtree_root<F>(
+    filename: String,
+    start: u64,
+    end: u64,
+    hash_fn: &F,
+) -> Result<(u64, Hash), MerkleError>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash,
+{
+    for offset in [start, end] {
+        if !offset.is_multiple_of(STRIDE) {
+            return Err(MerkleError::MisalignedOffset { offset });
+        }
+    }
+    let leaves = end.saturating_sub(start) / STRIDE;
+    if !leaves.is_power_of_two() {
+        return Err(MerkleError::BadRange {
+            reason: format!("[{start}, {end}) holds {leaves} leaves, not a power of two"),
+        });
+    }
+    let mut reader = SourceReader::new(filename)?;
+    reader.seek_offset(start)?;
+    let mut read = 0;
+    let mut leaves_read = UntilError::new(reader.take(leaves as usize));
+    let mut source = (&mut leaves_read).inspect(|_| read += 1).peekable();
+    let root = source
+        .peek()
+        .is_some()
+        .then(|| DepthWalk::calculate(&mut source, hash_fn));
+    drop(source);
+    if let Some(error) = leaves_read.error() {
+        return Err(error);
+    }
+    match root {
+        Some(root) if read == leaves => Ok((leaves, root)),
+        _ => Err(MerkleError::BadRange {
+            reason: format!("[{start}, {end}) exceeds the file"),
+        }),
+    }
+}
+
+///
+/// Combines the subtree roots of the consecutive ranges, given along with
+/// their number of leaves, into the root of the whole tree. All the ranges
+/// must hold the same power of two number of leaves, except for the last one,
+/// which may hold less.
+pub fn combine_roots<F>(roots: &[(u64, Hash)], hash_fn: &F) -> Result<Hash, MerkleError>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash + Sync + Send,
+{
+    let Some(((size, _), (last_size, last))) = roots.first().zip(roots.last()) else {
+        return Err(MerkleError::EmptySource);
+    };
+    let uneven = roots[..roots.len() - 1]
+        .iter()
+        .any(|(leaves, _)| leaves != size);
+    if !size.is_power_of_two() || !last_size.is_power_of_two() || uneven || last_size > size {
+        return Err(MerkleError::BadRange {
+            reason: "ranges must hold the same power of two number of leaves".to_string(),
+        });
+    }
+    let mut roots: Vec<Hash> = roots.iter().map(|(_, root)| *root).collect();
+    // lift the root of the shorter last range up to the range height
+    let mut lifted = *last;
+    let mut height = *last_size;
+    while height < *size {
+        lifted = hash_fn(&lifted, None);
+        height *= 2;
+    }
+    *roots.last_mut().unwrap() = lifted;
+    Ok(WidthWalk::calculate(
+        &mut roots.into_iter().peekable(),
+        hash_fn,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::hash;
+    use crate::test_fixtures::fixture;
+
+    ///
+    /// Writes the base16 fixture file with `n` leaves, returning its path along
+    /// with the leaves.
+    fn leaves_fixture(name: &str, n: u8) -> (String, Vec<Hash>) {
+        let leaves: Vec<Hash> = (0..n).map(|byte| [byte; 32]).collect();
+        let contents: String = leaves
+            .iter()
+            .map(|leaf| {
+                let mut buf = [0u8; 64];
+                format!("{}\n", base16ct::lower::encode_str(leaf, &mut buf).unwrap())
+            })
+            .collect();
+        (fixture("range", name, contents), leaves)
+    }
+
+    #[test]
+    fn two_ranges() {
+        let (file, leaves) = leaves_fixture("two_ranges.hex", 6);
+        let mut source = leaves.into_iter().peekable();
+        let expected = DepthWalk::calculate(&mut source, &hash);
+
+        let left = subtree_root(file.clone(), 0, 4 * STRIDE, &hash).unwrap();
+        let right = subtree_root(file.clone(), 4 * STRIDE, 6 * STRIDE, &hash).unwrap();
+        assert_eq!((4, 2), (left.0, right.0));
+        assert_eq!(expected, combine_roots(&[left, right], &hash).unwrap());
+
+        let halves: Vec<(u64, Hash)> = (0..3)
+            .map(|i| subtree_root(file.clone(), i * 2 * STRIDE, (i + 1) * 2 * STRIDE, &hash))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(expected, combine_roots(&halves, &hash).unwrap());
+    }
+
+    #[test]
+    fn bad_ranges() {
+        let (file, _) = leaves_fixture("bad_ranges.hex", 6);
+        assert!(matches!(
+            subtree_root(file.clone(), 10, 2 * STRIDE, &hash),
+            Err(MerkleError::MisalignedOffset { offset: 10 })
+        ));
+        assert!(matches!(
+            subtree_root(file.clone(), 0, 3 * STRIDE, &hash),
+            Err(MerkleError::BadRange { .. })
+        ));
+        assert!(matches!(
+            subtree_root(file.clone(), 4 * STRIDE, 8 * STRIDE, &hash),
+            Err(MerkleError::BadRange { .. })
+        ));
+        let range = (2, [0u8; 32]);
+        assert!(combine_roots(&[range, (4, [1u8; 32])], &hash).is_err());
+        assert!(combine_roots(&[], &hash).is_err());
+    }
+}
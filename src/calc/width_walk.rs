@@ -20,16 +20,49 @@
 //!
 //! Pros: parallel execution, might be fast in multicore systems.
 //!
-//! Cons: many memory allocations.
+//! Cons: many memory allocations, one per level; the buffered variant, see
+//! [`WidthWalk::try_calculate_buffered`], reuses two buffers instead.
 //!
 //! Use-cases: multi-thread environments, systems with large memory pools.
+//!
+//! Without the `parallel` feature, the levels are reduced sequentially.
+//!
+//! The batched variant, see [`WidthWalk::try_calculate_batched`], bounds the
+//! memory instead: it reduces each batch of B leaves (B being a power of two)
+//! to the root of its complete subtree level by level, and combines the
+//! subtree roots the way the accumulator combines its peaks
+//!
+//! lvl3           abcdefef
+//!               /       |
+//! lvl2       abcd    efef     <- the root of the batch abcd, and the root
+//!                   /             of the partial batch lifted to its height
+//! lvl1             ef          <- the root of the partial batch ef
+//!
+//! B = 4      a b c d  e f      <- the batches, each reduced in parallel
+//!
+//! The tree is no longer reduced level by level across the batches, but the
+//! batches are aligned to the subtrees of the full tree, so the root is the
+//! same.
+//!
+//! Time complexity: O(n*log(n))
+//! Space complexity: O(B + log(n))
 
+use crate::error::MerkleError;
+#[cfg(feature = "std")]
+use crate::hex::HexHash;
+#[cfg(feature = "std")]
+use crate::Hash;
+use alloc::{vec, vec::Vec};
+use core::iter::Peekable;
+#[cfg(feature = "parallel")]
 use rayon::prelude::*;
-use std::iter::Peekable;
 
 pub struct WidthWalk;
 
 impl WidthWalk {
+    ///
+    /// Calculates the root, panicking if the source is empty; see
+    /// [`WidthWalk::try_calculate`].
     pub fn calculate<I, H, F>(source: &mut Peekable<I>, hash_fn: &F) -> H
     where
         I: Iterator<Item = H>,
@@ -37,11 +70,380 @@ impl WidthWalk {
         F: Sync + Send,
         H: Sync + Send,
     {
-        let layer: Vec<H> = source.collect();
-        if layer.len() == 0 {
-            panic!("Expected source not to be empty");
+        Self::try_calculate(source, hash_fn).expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root, failing with [`MerkleError::EmptySource`] if the
+    /// source is empty.
+    pub fn try_calculate<I, H, F>(source: &mut Peekable<I>, hash_fn: &F) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Ok(Self::walk_layers(Self::collect(source)?, hash_fn))
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::try_calculate`] does,
+    /// calling `should_cancel` once the source is collected and then before
+    /// each level is reduced, and failing with [`MerkleError::Cancelled`] as
+    /// soon as it returns `true`.
+    pub fn try_calculate_cancellable<I, H, F, C>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+        should_cancel: &C,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+        C: Fn() -> bool,
+    {
+        Self::walk_layers_cancellable(Self::collect(source)?, hash_fn, should_cancel)
+    }
+
+    ///
+    /// Calculates the root of the leaves already in memory, panicking if there
+    /// are none; see [`WidthWalk::try_calculate_from_slice`].
+    pub fn calculate_from_slice<H, F>(leaves: &[H], hash_fn: &F) -> H
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        Self::try_calculate_from_slice(leaves, hash_fn).expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root of the leaves already in memory, failing with
+    /// [`MerkleError::EmptySource`] if there are none. The first level is
+    /// reduced from the slice itself, the leaves are not collected into the
+    /// bottom layer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, width_walk::WidthWalk};
+    ///
+    /// let leaves = vec![[0u8; 32], [1u8; 32], [2u8; 32]];
+    /// let root = WidthWalk::try_calculate_from_slice(&leaves, &hash).unwrap();
+    ///
+    /// assert_eq!(WidthWalk::calculate(&mut leaves.into_iter().peekable(), &hash), root);
+    /// ```
+    pub fn try_calculate_from_slice<H, F>(leaves: &[H], hash_fn: &F) -> Result<H, MerkleError>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        match leaves {
+            [] => Err(MerkleError::EmptySource),
+            [leaf] => Ok(leaf.clone()),
+            leaves => Ok(Self::walk_layers(Self::reduce(leaves, hash_fn), hash_fn)),
+        }
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::try_calculate`] does,
+    /// returning it along with the number of leaves read from the source.
+    pub fn try_calculate_counted<I, H, F>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<(H, usize), MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        let layer = Self::collect(source)?;
+        let leaves = layer.len();
+        Ok((Self::walk_layers(layer, hash_fn), leaves))
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::try_calculate`] does,
+    /// failing with [`MerkleError::OddLayer`] before reducing the first level
+    /// with the odd number of nodes (other than the root).
+    pub fn try_calculate_strict<I, H, F>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Self::walk_layers_strict(Self::collect(source)?, hash_fn)
+    }
+
+    ///
+    /// Calculates the root, treating the source as the level `level` of a
+    /// larger tree (level 0 being the leaves). The hash function receives the
+    /// level of the node being calculated along with its branches. Panics if
+    /// the source is empty.
+    pub fn calculate_from_level<I, H, F>(source: &mut Peekable<I>, level: usize, hash_fn: &F) -> H
+    where
+        I: Iterator<Item = H>,
+        F: Fn(usize, &H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Self::calculate_observed(source, level, hash_fn, &mut |_| {})
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::calculate_from_level`]
+    /// does, calling `observe` with the level number once the source is
+    /// collected, and then once each level is reduced. Panics if the source
+    /// is empty.
+    pub fn calculate_observed<I, H, F, O>(
+        source: &mut Peekable<I>,
+        level: usize,
+        hash_fn: &F,
+        observe: &mut O,
+    ) -> H
+    where
+        I: Iterator<Item = H>,
+        F: Fn(usize, &H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+        O: FnMut(usize),
+    {
+        Self::try_calculate_observed(source, level, hash_fn, observe)
+            .expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::calculate_observed`]
+    /// does, failing with [`MerkleError::EmptySource`] if the source is empty.
+    pub fn try_calculate_observed<I, H, F, O>(
+        source: &mut Peekable<I>,
+        level: usize,
+        hash_fn: &F,
+        observe: &mut O,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(usize, &H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+        O: FnMut(usize),
+    {
+        let mut layer = Self::collect(source)?;
+        let mut level = level;
+        observe(level);
+        while layer.len() > 1 {
+            level += 1;
+            layer = Self::reduce(&layer, &|left: &H, right: Option<&H>| {
+                hash_fn(level, left, right)
+            });
+            observe(level);
+        }
+        Ok(layer.pop().unwrap())
+    }
+
+    ///
+    /// Calculates every level of the tree, from the leaves up to the single
+    /// root, failing with [`MerkleError::EmptySource`] if the source is empty.
+    /// Meant for diagnostics: comparing the levels of two trees shows where
+    /// they diverge.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, width_walk::WidthWalk};
+    ///
+    /// let mut source = vec![[0u8; 32], [1u8; 32], [2u8; 32]].into_iter().peekable();
+    /// let levels = WidthWalk::calculate_levels(&mut source, &hash).unwrap();
+    ///
+    /// assert_eq!(vec![3, 2, 1], levels.iter().map(Vec::len).collect::<Vec<_>>());
+    /// ```
+    pub fn calculate_levels<I, H, F>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<Vec<Vec<H>>, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send + Clone,
+    {
+        let leaves = Self::collect(source)?;
+        let mut levels = vec![leaves.clone()];
+        levels.extend(LevelReducer::new(leaves, hash_fn));
+        Ok(levels)
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::try_calculate`] does,
+    /// handing at least `min_parallel_chunk` nodes of the level (rounded up to
+    /// a whole pair) to each rayon task, instead of a single pair. Coarser
+    /// tasks cut the scheduling overhead of the large levels; the tree, and
+    /// so the root, stays the same. Fails with [`MerkleError::EmptySource`] if
+    /// the source is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, width_walk::WidthWalk};
+    ///
+    /// let leaves: Vec<[u8; 32]> = (0..5u8).map(|byte| [byte; 32]).collect();
+    /// let chunked = WidthWalk::try_calculate_chunked(&mut leaves.iter().copied().peekable(), 4, &hash);
+    /// let root = WidthWalk::try_calculate(&mut leaves.into_iter().peekable(), &hash);
+    ///
+    /// assert_eq!(root.unwrap(), chunked.unwrap());
+    /// ```
+    pub fn try_calculate_chunked<I, H, F>(
+        source: &mut Peekable<I>,
+        min_parallel_chunk: usize,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        let pairs = min_parallel_chunk.div_ceil(2).max(1);
+        let mut layer = Self::collect(source)?;
+        while layer.len() > 1 {
+            layer = Self::reduce_chunked(&layer, pairs, hash_fn);
+        }
+        Ok(layer.pop().unwrap())
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::calculate`] does, reusing
+    /// two buffers for the levels; see [`WidthWalk::try_calculate_buffered`].
+    pub fn calculate_buffered<I, H, F>(source: &mut Peekable<I>, hash_fn: &F) -> H
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Self::try_calculate_buffered(source, hash_fn).expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root the same way [`WidthWalk::try_calculate`] does,
+    /// but instead of allocating every level, writes it into one of the two
+    /// buffers, swapping them after each level: the leaves and the half as
+    /// large one. Fails with [`MerkleError::EmptySource`] if the source is
+    /// empty.
+    pub fn try_calculate_buffered<I, H, F>(
+        source: &mut Peekable<I>,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Ok(Self::walk_layers_buffered(Self::collect(source)?, hash_fn))
+    }
+
+    ///
+    /// Calculates the root in batches of `batch` leaves, rounded up to a power
+    /// of two, panicking if the source is empty; see
+    /// [`WidthWalk::try_calculate_batched`].
+    pub fn calculate_batched<I, H, F>(source: &mut Peekable<I>, batch: usize, hash_fn: &F) -> H
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Self::try_calculate_batched(source, batch, hash_fn)
+            .expect("Expected source not to be empty")
+    }
+
+    ///
+    /// Calculates the root in batches of `batch` leaves, rounded up to a power
+    /// of two, keeping at most one batch and the roots of the batches reduced
+    /// so far in memory. The root is the same as the one of
+    /// [`WidthWalk::try_calculate`]. Fails with [`MerkleError::EmptySource`]
+    /// if the source is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::calc::{hash, width_walk::WidthWalk};
+    ///
+    /// let leaves: Vec<[u8; 32]> = (0..5u8).map(|byte| [byte; 32]).collect();
+    /// let batched = WidthWalk::try_calculate_batched(&mut leaves.iter().copied().peekable(), 2, &hash);
+    /// let root = WidthWalk::try_calculate(&mut leaves.into_iter().peekable(), &hash);
+    ///
+    /// assert_eq!(root.unwrap(), batched.unwrap());
+    /// ```
+    pub fn try_calculate_batched<I, H, F>(
+        source: &mut Peekable<I>,
+        batch: usize,
+        hash_fn: &F,
+    ) -> Result<H, MerkleError>
+    where
+        I: Iterator<Item = H>,
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        let batch = batch.max(1).next_power_of_two();
+        // the roots of the complete subtrees with their heights, highest first
+        let mut peaks: Vec<(u32, H)> = Vec::new();
+        loop {
+            let layer: Vec<H> = source.by_ref().take(batch).collect();
+            if layer.is_empty() {
+                break;
+            }
+            // only the last batch may be partial, so its root merges only
+            // upward, as the right-hand subtree of the earlier peaks
+            let height = layer.len().next_power_of_two().trailing_zeros();
+            let mut node = (height, Self::walk_layers(layer, hash_fn));
+            while peaks.last().is_some_and(|(height, _)| *height == node.0) {
+                let (height, peak) = peaks.pop().unwrap();
+                node = (height + 1, hash_fn(&peak, Some(&node.1)));
+            }
+            peaks.push(node);
+        }
+        // lift the running node up to the height of the next peak, the same
+        // way the full tree hashes the last node of the odd level
+        let mut peaks = peaks.into_iter().rev();
+        let (mut height, mut root) = peaks.next().ok_or(MerkleError::EmptySource)?;
+        for (peak_height, peak) in peaks {
+            while height < peak_height {
+                root = hash_fn(&root, None);
+                height += 1;
+            }
+            root = hash_fn(&peak, Some(&root));
+            height += 1;
+        }
+        Ok(root)
+    }
+
+    ///
+    /// Collects the source into the bottom layer, the only place the empty
+    /// source is detected.
+    fn collect<I, H>(source: &mut Peekable<I>) -> Result<Vec<H>, MerkleError>
+    where
+        I: Iterator<Item = H>,
+    {
+        // the readers bound the number of leaves by the size of the file, the
+        // reservation is only a hint, so the bound too large is ignored
+        let (lower, upper) = source.size_hint();
+        let mut layer = Vec::new();
+        let _ = layer.try_reserve(upper.unwrap_or(lower));
+        layer.extend(source);
+        match layer.is_empty() {
+            true => Err(MerkleError::EmptySource),
+            false => Ok(layer),
         }
-        Self::walk_layers(layer, hash_fn)
     }
 
     fn walk_layers<H, F>(mut layer: Vec<H>, hash_fn: &F) -> H
@@ -50,23 +452,217 @@ impl WidthWalk {
         F: Sync + Send,
         H: Sync + Send,
     {
-        if layer.len() == 1 {
-            return layer.pop().unwrap();
+        while layer.len() > 1 {
+            layer = Self::reduce(&layer, hash_fn);
+        }
+        layer.pop().unwrap()
+    }
+
+    fn walk_layers_cancellable<H, F, C>(
+        mut layer: Vec<H>,
+        hash_fn: &F,
+        should_cancel: &C,
+    ) -> Result<H, MerkleError>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+        C: Fn() -> bool,
+    {
+        while layer.len() > 1 {
+            if should_cancel() {
+                return Err(MerkleError::Cancelled);
+            }
+            layer = Self::reduce(&layer, hash_fn);
         }
-        let next_layer = layer
+        Ok(layer.pop().unwrap())
+    }
+
+    fn walk_layers_strict<H, F>(mut layer: Vec<H>, hash_fn: &F) -> Result<H, MerkleError>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        let mut level = 0;
+        while layer.len() > 1 {
+            if layer.len() % 2 == 1 {
+                return Err(MerkleError::OddLayer {
+                    level,
+                    size: layer.len(),
+                });
+            }
+            layer = Self::reduce(&layer, hash_fn);
+            level += 1;
+        }
+        Ok(layer.pop().unwrap())
+    }
+
+    fn walk_layers_buffered<H, F>(mut layer: Vec<H>, hash_fn: &F) -> H
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        let mut next = Vec::with_capacity(layer.len().div_ceil(2));
+        while layer.len() > 1 {
+            // the nodes are written in place into the spare buffer, which
+            // keeps its capacity across the levels
+            #[cfg(feature = "parallel")]
+            layer
+                .par_chunks(2)
+                .map(|chunk| Self::hash_chunk(chunk, hash_fn))
+                .collect_into_vec(&mut next);
+            #[cfg(not(feature = "parallel"))]
+            {
+                next.clear();
+                next.extend(
+                    layer
+                        .chunks(2)
+                        .map(|chunk| Self::hash_chunk(chunk, hash_fn)),
+                );
+            }
+            core::mem::swap(&mut layer, &mut next);
+        }
+        layer.pop().unwrap()
+    }
+
+    fn reduce<H, F>(layer: &[H], hash_fn: &F) -> Vec<H>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        Self::reduce_chunked(layer, 1, hash_fn)
+    }
+
+    ///
+    /// Reduces the level, each rayon task hashing at least `pairs` pairs.
+    #[cfg(feature = "parallel")]
+    fn reduce_chunked<H, F>(layer: &[H], pairs: usize, hash_fn: &F) -> Vec<H>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+        F: Sync + Send,
+        H: Sync + Send,
+    {
+        layer
             .par_chunks(2)
-            .map(|chunk| {
-                if chunk.len() == 2 {
-                    hash_fn(&chunk[0], Some(&chunk[1]))
-                } else {
-                    hash_fn(&chunk[0], None)
-                }
-            })
-            .collect();
-        Self::walk_layers(next_layer, hash_fn)
+            .with_min_len(pairs)
+            .map(|chunk| Self::hash_chunk(chunk, hash_fn))
+            .collect()
+    }
+
+    ///
+    /// Reduces the level sequentially, there are no tasks to size without the
+    /// `parallel` feature.
+    #[cfg(not(feature = "parallel"))]
+    fn reduce_chunked<H, F>(layer: &[H], _pairs: usize, hash_fn: &F) -> Vec<H>
+    where
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        layer
+            .chunks(2)
+            .map(|chunk| Self::hash_chunk(chunk, hash_fn))
+            .collect()
+    }
+
+    fn hash_chunk<H, F>(chunk: &[H], hash_fn: &F) -> H
+    where
+        F: Fn(&H, Option<&H>) -> H,
+    {
+        if chunk.len() == 2 {
+            hash_fn(&chunk[0], Some(&chunk[1]))
+        } else {
+            hash_fn(&chunk[0], None)
+        }
+    }
+}
+
+///
+/// The iterator over the levels of the merkle tree, starting from the leaves.
+///
+/// Each call to `next` reduces the current level into the next one and yields
+/// it, until the level converges into the single root hash. The leaves
+/// themselves are not yielded. The iterator keeps the current level to reduce
+/// it on the next call, so the yielded level is its clone: the current level
+/// and the yielded one are both in memory until the caller drops the latter.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{hash, width_walk::LevelReducer};
+///
+/// let leaves = vec![[0u8; 32], [1u8; 32], [2u8; 32]];
+/// let sizes: Vec<usize> = LevelReducer::new(leaves, &hash)
+///     .map(|level| level.len())
+///     .collect();
+///
+/// assert_eq!(vec![2, 1], sizes);
+/// ```
+pub struct LevelReducer<'a, H, F> {
+    layer: Vec<H>,
+    hash_fn: &'a F,
+}
+
+impl<'a, H, F> LevelReducer<'a, H, F> {
+    pub fn new(leaves: Vec<H>, hash_fn: &'a F) -> Self {
+        Self {
+            layer: leaves,
+            hash_fn,
+        }
     }
 }
 
+impl<H, F> Iterator for LevelReducer<'_, H, F>
+where
+    F: Fn(&H, Option<&H>) -> H,
+    F: Sync + Send,
+    H: Sync + Send + Clone,
+{
+    type Item = Vec<H>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.layer.len() <= 1 {
+            return None;
+        }
+        self.layer = WidthWalk::reduce(&self.layer, self.hash_fn);
+        Some(self.layer.clone())
+    }
+}
+
+///
+/// Writes the levels of [`WidthWalk::calculate_levels`] as text: each level
+/// starts with the `# level <n>: <size> nodes` line, the last one with the
+/// `# level <n>: root` line, followed by one base16 hash per line. The header
+/// lines are comments for [`SourceReader::with_skip_comments`](crate::source::SourceReader::with_skip_comments).
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{hash, width_walk::{write_levels, WidthWalk}};
+///
+/// let mut source = vec![[0u8; 32], [1u8; 32]].into_iter().peekable();
+/// let levels = WidthWalk::calculate_levels(&mut source, &hash).unwrap();
+/// let mut out = Vec::new();
+/// write_levels(&levels, &mut out).unwrap();
+///
+/// let text = String::from_utf8(out).unwrap();
+/// assert_eq!(5, text.lines().count());
+/// assert_eq!(Some("# level 1: root"), text.lines().nth(3));
+/// ```
+#[cfg(feature = "std")]
+pub fn write_levels<W: std::io::Write>(levels: &[Vec<Hash>], mut out: W) -> std::io::Result<()> {
+    for (level, nodes) in levels.iter().enumerate() {
+        match level + 1 == levels.len() {
+            true => writeln!(out, "# level {level}: root")?,
+            false => writeln!(out, "# level {level}: {} nodes", nodes.len())?,
+        }
+        for node in nodes {
+            writeln!(out, "{}", HexHash(*node))?;
+        }
+    }
+    Ok(())
+}
+
 ///
 /// Hash is a Vec<char>, e.g. vec!['a'].
 ///
@@ -76,6 +672,8 @@ impl WidthWalk {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::source::SourceReader;
+    use core::sync::atomic::{AtomicUsize, Ordering};
 
     fn hash(left: &Vec<char>, right: Option<&Vec<char>>) -> Vec<char> {
         let mut ret = Vec::new();
@@ -87,6 +685,19 @@ mod tests {
         ret
     }
 
+    #[test]
+    fn unbounded_hint() {
+        // the upper bound too large to reserve does not fail the collection
+        let mut source = (0..usize::MAX)
+            .map(|index| vec![(b'a' + index as u8) as char])
+            .take_while(|leaf| leaf[0] < 'd')
+            .peekable();
+        assert_eq!(
+            vec!['a', 'b', 'c', 'c'],
+            WidthWalk::calculate(&mut source, &hash)
+        );
+    }
+
     #[test]
     #[should_panic]
     fn empty_source() {
@@ -94,6 +705,113 @@ mod tests {
         WidthWalk::calculate(&mut source, &hash);
     }
 
+    #[test]
+    fn try_empty_source() {
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            WidthWalk::try_calculate(&mut source, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        let observed = WidthWalk::try_calculate_observed(
+            &mut source,
+            0,
+            &|_, left: &Vec<char>, right: Option<&Vec<char>>| hash(left, right),
+            &mut |_| panic!("Expected no level to be observed"),
+        );
+        assert!(matches!(observed, Err(MerkleError::EmptySource)));
+        let mut source = vec![vec!['a'], vec!['b']].into_iter().peekable();
+        assert_eq!(
+            vec!['a', 'b'],
+            WidthWalk::try_calculate(&mut source, &hash).unwrap()
+        );
+    }
+
+    #[test]
+    fn counted() {
+        let mut source = vec![vec!['a'], vec!['b'], vec!['c']].into_iter().peekable();
+        assert_eq!(
+            (vec!['a', 'b', 'c', 'c'], 3),
+            WidthWalk::try_calculate_counted(&mut source, &hash).unwrap()
+        );
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            WidthWalk::try_calculate_counted(&mut source, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn from_slice() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            assert_eq!(
+                WidthWalk::calculate(&mut source, &hash),
+                WidthWalk::calculate_from_slice(&leaves[..count], &hash)
+            );
+        }
+        assert!(matches!(
+            WidthWalk::try_calculate_from_slice(&[] as &[Vec<char>], &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn cancellable() {
+        use core::cell::Cell;
+
+        let leaves: Vec<Vec<char>> = ('a'..='e').map(|leaf| vec![leaf]).collect();
+        let checks = Cell::new(0);
+        let count = || {
+            checks.set(checks.get() + 1);
+            false
+        };
+        let mut source = leaves.clone().into_iter().peekable();
+        let root = WidthWalk::try_calculate_cancellable(&mut source, &hash, &count).unwrap();
+        assert_eq!(
+            WidthWalk::calculate(&mut leaves.clone().into_iter().peekable(), &hash),
+            root
+        );
+        // once before each of the levels 1, 2 and 3
+        assert_eq!(3, checks.get());
+
+        // cancelled before the last level
+        checks.set(0);
+        let cancel = || {
+            checks.set(checks.get() + 1);
+            checks.get() == 3
+        };
+        let mut source = leaves.into_iter().peekable();
+        assert!(matches!(
+            WidthWalk::try_calculate_cancellable(&mut source, &hash, &cancel),
+            Err(MerkleError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn strict() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let root = WidthWalk::try_calculate_strict(&mut source, &hash);
+            match count.is_power_of_two() {
+                true => assert_eq!(
+                    WidthWalk::calculate(&mut leaves[..count].iter().cloned().peekable(), &hash),
+                    root.unwrap()
+                ),
+                false => {
+                    let level = count.trailing_zeros() as usize;
+                    assert!(matches!(
+                        root,
+                        Err(MerkleError::OddLayer { level: l, size })
+                            if l == level && size == count >> level
+                    ));
+                }
+            }
+        }
+    }
+
     #[test]
     fn full_tree() {
         let mut source = vec![vec!['a'], vec!['b']].into_iter().peekable();
@@ -119,4 +837,155 @@ mod tests {
             WidthWalk::calculate(&mut source, &hash)
         );
     }
+
+    #[test]
+    fn observed_levels() {
+        let mut source = vec![vec!['a'], vec!['b'], vec!['c']].into_iter().peekable();
+        let mut levels = Vec::new();
+        let root = WidthWalk::calculate_observed(
+            &mut source,
+            1,
+            &|_, left: &Vec<char>, right: Option<&Vec<char>>| hash(left, right),
+            &mut |level| levels.push(level),
+        );
+        assert_eq!(vec!['a', 'b', 'c', 'c'], root);
+        assert_eq!(vec![1, 2, 3], levels);
+    }
+
+    #[test]
+    fn levels() {
+        let mut source = vec![vec!['a'], vec!['b'], vec!['c']].into_iter().peekable();
+        assert_eq!(
+            vec![
+                vec![vec!['a'], vec!['b'], vec!['c']],
+                vec![vec!['a', 'b'], vec!['c', 'c']],
+                vec![vec!['a', 'b', 'c', 'c']],
+            ],
+            WidthWalk::calculate_levels(&mut source, &hash).unwrap()
+        );
+
+        let mut source = vec![vec!['a']].into_iter().peekable();
+        assert_eq!(
+            vec![vec![vec!['a']]],
+            WidthWalk::calculate_levels(&mut source, &hash).unwrap()
+        );
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            WidthWalk::calculate_levels(&mut source, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn written_levels() {
+        let leaves: Vec<Hash> = (0..3u8).map(|byte| [byte; 32]).collect();
+        let mut source = leaves.into_iter().peekable();
+        let levels = WidthWalk::calculate_levels(&mut source, &crate::calc::hash).unwrap();
+        let mut out = Vec::new();
+        write_levels(&levels, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let headers: Vec<&str> = text.lines().filter(|line| line.starts_with('#')).collect();
+        assert_eq!(
+            vec![
+                "# level 0: 3 nodes",
+                "# level 1: 2 nodes",
+                "# level 2: root"
+            ],
+            headers
+        );
+        // the comments aside, the nodes are read back level by level
+        let nodes: Vec<Hash> = SourceReader::from_reader(std::io::Cursor::new(text))
+            .with_skip_comments(true)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(levels.concat(), nodes);
+    }
+
+    #[test]
+    fn buffered() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let root = WidthWalk::calculate(&mut source, &hash);
+            let mut source = leaves[..count].iter().cloned().peekable();
+            assert_eq!(root, WidthWalk::calculate_buffered(&mut source, &hash));
+        }
+
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            WidthWalk::try_calculate_buffered(&mut source, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn chunked() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let root = WidthWalk::calculate(&mut source, &hash);
+            for chunk in [0, 1, 2, 3, 8, 64] {
+                let mut source = leaves[..count].iter().cloned().peekable();
+                assert_eq!(
+                    root,
+                    WidthWalk::try_calculate_chunked(&mut source, chunk, &hash).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn batched() {
+        let leaves: Vec<Vec<char>> = ('a'..='t').map(|leaf| vec![leaf]).collect();
+        for count in 1..=leaves.len() {
+            let mut source = leaves[..count].iter().cloned().peekable();
+            let root = WidthWalk::calculate(&mut source, &hash);
+            for batch in [0, 1, 2, 3, 4, 8, 32] {
+                let mut source = leaves[..count].iter().cloned().peekable();
+                assert_eq!(
+                    root,
+                    WidthWalk::calculate_batched(&mut source, batch, &hash),
+                    "{count} leaves in batches of {batch}"
+                );
+            }
+        }
+
+        let mut source = Vec::<Vec<char>>::new().into_iter().peekable();
+        assert!(matches!(
+            WidthWalk::try_calculate_batched(&mut source, 4, &hash),
+            Err(MerkleError::EmptySource)
+        ));
+    }
+
+    #[test]
+    fn level_reducer() {
+        let leaves = vec![vec!['a'], vec!['b'], vec!['c'], vec!['d'], vec!['e']];
+        let levels: Vec<_> = LevelReducer::new(leaves, &hash).collect();
+        assert_eq!(
+            vec![3, 2, 1],
+            levels.iter().map(|level| level.len()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(&vec![vec!['a', 'b', 'c', 'd', 'e', 'e', 'e', 'e']]),
+            levels.last()
+        );
+
+        let leaves = vec![vec!['a']];
+        assert_eq!(0, LevelReducer::new(leaves, &hash).count());
+
+        // the level is reduced only once asked for
+        let calls = AtomicUsize::new(0);
+        let counted = |left: &Vec<char>, right: Option<&Vec<char>>| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            hash(left, right)
+        };
+        let leaves = vec![vec!['a'], vec!['b'], vec!['c'], vec!['d']];
+        let mut levels = LevelReducer::new(leaves, &counted);
+        assert_eq!(0, calls.load(Ordering::Relaxed));
+        assert_eq!(Some(vec![vec!['a', 'b'], vec!['c', 'd']]), levels.next());
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+        assert_eq!(Some(vec![vec!['a', 'b', 'c', 'd']]), levels.next());
+        assert_eq!(3, calls.load(Ordering::Relaxed));
+        assert_eq!(None, levels.next());
+    }
 }
@@ -0,0 +1,119 @@
+//!
+//! Implements a merkle tree root calculation over sliding windows.
+//!
+//! Time complexity: O(n*W)
+//! Space complexity: O(W)
+//!
+//! The window of W leaves slides over the source one leaf at a time, and the
+//! root of the leaves in the window is calculated for every window position
+//!
+//! position 0   [a b c] d e    => abcc
+//! position 1    a [b c d] e   => bcdd
+//! position 2    a b [c d e]   => cdee
+//!
+//! The root of each window is calculated from scratch with the depth-walk
+//! algorithm, i.e. the total cost is the cost of calculating the root of W
+//! leaves multiplied by the number of window positions.
+//!
+//! Pros: simple, low space complexity.
+//!
+//! Cons: the work is not shared between the overlapping windows.
+//!
+//! Use-cases: rolling-commitment analysis.
+
+use super::depth_walk::DepthWalk;
+use alloc::collections::VecDeque;
+
+///
+/// The iterator over the roots of the sliding windows, yielding the position
+/// of the window (the index of its first leaf) along with its root.
+///
+/// If the source contains fewer than `size` leaves, nothing is yielded.
+pub struct Windows<'a, I, H, F> {
+    source: I,
+    window: VecDeque<H>,
+    size: usize,
+    position: usize,
+    hash_fn: &'a F,
+}
+
+impl<'a, I, H, F> Windows<'a, I, H, F>
+where
+    I: Iterator<Item = H>,
+    H: Clone,
+    F: Fn(&H, Option<&H>) -> H,
+{
+    pub fn new(source: I, size: usize, hash_fn: &'a F) -> Self {
+        assert!(size > 0, "Expected window size to be positive");
+        Self {
+            source,
+            window: VecDeque::with_capacity(size),
+            size,
+            position: 0,
+            hash_fn,
+        }
+    }
+}
+
+impl<I, H, F> Iterator for Windows<'_, I, H, F>
+where
+    I: Iterator<Item = H>,
+    H: Clone,
+    F: Fn(&H, Option<&H>) -> H,
+{
+    type Item = (usize, H);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window.len() == self.size {
+            // slide the window by one leaf
+            self.window.pop_front();
+            self.position += 1;
+        }
+        while self.window.len() < self.size {
+            self.window.push_back(self.source.next()?);
+        }
+        let mut source = self.window.iter().cloned().peekable();
+        let root = DepthWalk::calculate(&mut source, self.hash_fn);
+        Some((self.position, root))
+    }
+}
+
+///
+/// Hash is a Vec<char>, e.g. vec!['a'].
+///
+/// Hashing two branches is defined as a vector, expanded from the left and
+/// right branches, e.g.
+/// hash(vec!['a'], Some(vec!['b'])) => vec!['a', 'b']
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(left: &Vec<char>, right: Option<&Vec<char>>) -> Vec<char> {
+        let mut ret = Vec::new();
+        ret.extend(left);
+        match right {
+            None => ret.extend(left),
+            Some(right) => ret.extend(right),
+        }
+        ret
+    }
+
+    #[test]
+    fn sliding_windows() {
+        let source = vec![vec!['a'], vec!['b'], vec!['c'], vec!['d'], vec!['e']];
+        let roots: Vec<_> = Windows::new(source.into_iter(), 3, &hash).collect();
+        assert_eq!(
+            vec![
+                (0, vec!['a', 'b', 'c', 'c']),
+                (1, vec!['b', 'c', 'd', 'd']),
+                (2, vec!['c', 'd', 'e', 'e']),
+            ],
+            roots
+        );
+    }
+
+    #[test]
+    fn short_source() {
+        let source = vec![vec!['a'], vec!['b']];
+        assert_eq!(0, Windows::new(source.into_iter(), 3, &hash).count());
+    }
+}
@@ -0,0 +1,288 @@
+//!
+//! Implements the transparent decompression of the input files and the
+//! standard input.
+//!
+//! The compression is detected by the magic bytes at the start of the file:
+//!
+//! | format | magic bytes         |
+//! |--------|---------------------|
+//! | gzip   | `1f 8b`             |
+//! | zstd   | `28 b5 2f fd`       |
+//! | xz     | `fd 37 7a 58 5a 00` |
+//!
+//! Any other file is read as plain text. The decoders are available with the
+//! `compression` feature only; without it, opening the compressed file, or
+//! forcing a decoder, fails with the "compression support is not enabled"
+//! error.
+//!
+//! The decompressed input and the standard input can not be seeked, so
+//! resuming from an offset or reading the shards of the file in parallel
+//! requires a plain text file.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+
+///
+/// The compression of the input file.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    /// Detect the compression by the magic bytes
+    #[default]
+    Auto,
+    /// Plain text
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    ///
+    /// Detects the compression by the magic bytes at the start of the input.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::compression::Compression;
+    ///
+    /// assert_eq!(Compression::Gzip, Compression::sniff(&[0x1f, 0x8b, 0x08]));
+    /// assert_eq!(Compression::None, Compression::sniff(b"77d519a5"));
+    /// ```
+    pub fn sniff(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+///
+/// The path standing for the standard input instead of a file.
+pub const STDIN: &str = "-";
+
+///
+/// The buffered input, either the plain file, or the stream: the standard
+/// input, or the file decompressed on the fly, or any other buffered reader.
+///
+/// # Examples
+///
+/// ```no_run
+/// use merkle_root::compression::{Compression, Input, STDIN};
+/// use std::io::BufRead;
+///
+/// let input = Input::open(STDIN.to_string(), 8 * 1024, Compression::Auto).unwrap();
+/// let lines = input.lines().count();
+/// ```
+pub enum Input {
+    Plain(BufReader<File>),
+    Stream(BufReader<Box<dyn Read + Send>>),
+    Reader(Box<dyn BufRead + Send>),
+}
+
+impl Input {
+    ///
+    /// Opens the file with the BufReader of the given capacity, decompressing
+    /// it with the given compression. The [`STDIN`] path reads the standard
+    /// input instead.
+    pub fn open(
+        filename: String,
+        capacity: usize,
+        compression: Compression,
+    ) -> Result<Self, Error> {
+        if filename == STDIN {
+            let stdin: Box<dyn Read + Send> = Box::new(std::io::stdin());
+            return Self::decode(
+                BufReader::with_capacity(capacity, stdin),
+                capacity,
+                compression,
+            );
+        }
+        let file = BufReader::with_capacity(capacity, File::open(filename)?);
+        Self::decode(file, capacity, compression)
+    }
+
+    fn decode<R>(
+        mut input: BufReader<R>,
+        capacity: usize,
+        compression: Compression,
+    ) -> Result<Self, Error>
+    where
+        R: Read + Send + 'static,
+        BufReader<R>: Into<Self>,
+    {
+        let compression = match compression {
+            // sniffed without the decoders too, to refuse the compressed input
+            Compression::Auto => Compression::sniff(input.fill_buf()?),
+            compression => compression,
+        };
+        match compression {
+            Compression::Auto | Compression::None => Ok(input.into()),
+            compression => Ok(Input::Stream(BufReader::with_capacity(
+                capacity,
+                decoder(input, compression)?,
+            ))),
+        }
+    }
+}
+
+impl From<BufReader<File>> for Input {
+    fn from(reader: BufReader<File>) -> Self {
+        Input::Plain(reader)
+    }
+}
+
+impl From<BufReader<Box<dyn Read + Send>>> for Input {
+    fn from(reader: BufReader<Box<dyn Read + Send>>) -> Self {
+        Input::Stream(reader)
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decoder<R>(input: BufReader<R>, compression: Compression) -> Result<Box<dyn Read + Send>, Error>
+where
+    R: Read + Send + 'static,
+{
+    Ok(match compression {
+        Compression::Auto | Compression::None => Box::new(input),
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(input)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(input)?),
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(input)),
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+fn decoder<R>(_: BufReader<R>, _: Compression) -> Result<Box<dyn Read + Send>, Error> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "compression support is not enabled",
+    ))
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Plain(reader) => f.debug_tuple("Plain").field(reader).finish(),
+            Input::Stream(_) => f.debug_tuple("Stream").finish_non_exhaustive(),
+            Input::Reader(_) => f.debug_tuple("Reader").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Input::Plain(reader) => reader.read(buf),
+            Input::Stream(reader) => reader.read(buf),
+            Input::Reader(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl BufRead for Input {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        match self {
+            Input::Plain(reader) => reader.fill_buf(),
+            Input::Stream(reader) => reader.fill_buf(),
+            Input::Reader(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self {
+            Input::Plain(reader) => reader.consume(amount),
+            Input::Stream(reader) => reader.consume(amount),
+            Input::Reader(reader) => reader.consume(amount),
+        }
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Input::Plain(reader) => reader.seek(pos),
+            Input::Stream(_) | Input::Reader(_) => Err(Error::new(
+                ErrorKind::Unsupported,
+                "the input stream can not be seeked",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SourceReader;
+    use crate::test_fixtures::fixture;
+    #[cfg(feature = "compression")]
+    use std::io::Write;
+
+    const LEAVES: &str = "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053\n\
+        915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723\n";
+
+    fn read(file: String, compression: Compression) -> Vec<u8> {
+        let mut contents = Vec::new();
+        Input::open(file, 64, compression)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn sniffed() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(LEAVES.as_bytes()).unwrap();
+        let gzip = fixture("compression", "leaves.gz", gzip.finish().unwrap());
+        let zstd = fixture(
+            "compression",
+            "leaves.zst",
+            zstd::encode_all(LEAVES.as_bytes(), 0).unwrap(),
+        );
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(LEAVES.as_bytes()).unwrap();
+        let xz = fixture("compression", "leaves.xz", xz.finish().unwrap());
+        let plain = fixture("compression", "leaves.hex", LEAVES.as_bytes());
+
+        for file in [&gzip, &zstd, &xz, &plain] {
+            assert_eq!(LEAVES.as_bytes(), read(file.clone(), Compression::Auto));
+        }
+        assert_eq!(LEAVES.as_bytes(), read(zstd.clone(), Compression::Zstd));
+        assert_ne!(LEAVES.as_bytes(), read(zstd.clone(), Compression::None));
+
+        let leaves = |file: &String| {
+            SourceReader::new(file.clone())
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(2, leaves(&plain).len());
+        assert_eq!(leaves(&plain), leaves(&gzip));
+        assert_eq!(leaves(&plain), leaves(&zstd));
+        assert!(Input::open(plain, 64, Compression::Gzip)
+            .and_then(|mut input| input.read_to_end(&mut Vec::new()))
+            .is_err());
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn not_enabled() {
+        let gzip = fixture("compression", "not_enabled.gz", [0x1f, 0x8b, 0x08, 0x00]);
+        let plain = fixture("compression", "not_enabled.hex", LEAVES.as_bytes());
+
+        for compression in [Compression::Auto, Compression::Gzip] {
+            let error = Input::open(gzip.clone(), 64, compression).unwrap_err();
+            assert_eq!(ErrorKind::Unsupported, error.kind());
+            assert_eq!("compression support is not enabled", error.to_string());
+        }
+        assert!(SourceReader::new(gzip).is_err());
+        assert_eq!(LEAVES.as_bytes(), read(plain.clone(), Compression::Auto));
+        assert_eq!(2, SourceReader::new(plain).unwrap().count());
+    }
+}
@@ -0,0 +1,162 @@
+use alloc::string::String;
+use core::error::Error;
+use core::fmt;
+
+///
+/// The error of the merkle tree root calculation.
+#[derive(Debug)]
+pub enum MerkleError {
+    /// Reading the source failed
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// The source contains no hashes
+    EmptySource,
+    /// The hash on the given line (1-based) has an unexpected length
+    BadLength {
+        line: usize,
+        got: usize,
+        want: usize,
+    },
+    /// The hash on the given line (1-based) is not a valid base16 string
+    BadHex { line: usize, content: String },
+    /// The hash on the given line (1-based) is not a valid base64 string of
+    /// 32 bytes
+    BadBase64 { line: usize, content: String },
+    /// The line (1-based) is not a valid UTF-8 string
+    BadUtf8 { line: usize },
+    /// The leaf on the given line (1-based) was already seen in the source
+    DuplicateLeaf { line: usize },
+    /// The header line is not a valid leaf count
+    BadHeader { header: String },
+    /// The number of leaves differs from the one declared in the header
+    LeafCountMismatch { expected: usize, got: usize },
+    /// The root is the zero hash, or equals a leaf of a multi-leaf tree
+    DegenerateRoot,
+    /// The line (1-based) exceeds the maximum line length
+    LineTooLong { line: usize },
+    /// The proof can not be parsed
+    BadProof { reason: String },
+    /// The saved accumulator state can not be loaded
+    BadState { reason: String },
+    /// The byte offset does not point to the start of a line
+    MisalignedOffset { offset: u64 },
+    /// The line (1-based) of the seeked source ends with "\r\n", while the
+    /// seeking relies on the '\n' line endings
+    CrlfLine { line: usize },
+    /// The binary record (1-based) is shorter than the hash
+    TruncatedRecord { record: usize, got: usize },
+    /// The peaks of the given number of first leaves differ from the frontier
+    FrontierMismatch { leaves: u64 },
+    /// The byte range of the source can not form a complete subtree
+    BadRange { reason: String },
+    /// The JSON input is not an array of base16 strings
+    BadJson { reason: String },
+    /// The hash on the given line (1-based) sorts before the previous one
+    Unsorted { line: usize },
+    /// The leaf index is past the last leaf of the source
+    IndexOutOfRange { index: usize, leaves: usize },
+    /// The level of the tree has the odd number of nodes, refused by
+    /// [`OddPolicy::Strict`](crate::calc::OddPolicy::Strict)
+    OddLayer { level: usize, size: usize },
+    /// The old tree of the consistency proof is empty or larger than the new
+    /// one
+    BadTreeSizes { old_size: usize, new_size: usize },
+    /// The calculation was cancelled by the caller
+    Cancelled,
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            MerkleError::Io(error) => write!(f, "{error}"),
+            MerkleError::EmptySource => write!(f, "source is empty"),
+            MerkleError::BadLength { line, got, want } => {
+                write!(f, "line {line}: expected {want} characters, got {got}")
+            }
+            MerkleError::BadHex { line, content } => {
+                write!(f, "line {line}: invalid base16 string {content:?}")
+            }
+            MerkleError::BadBase64 { line, content } => {
+                write!(f, "line {line}: invalid base64 string {content:?}")
+            }
+            MerkleError::BadUtf8 { line } => write!(f, "line {line}: invalid UTF-8"),
+            MerkleError::DuplicateLeaf { line } => write!(f, "duplicate leaf on line {line}"),
+            MerkleError::BadHeader { header } => write!(f, "invalid leaf count header {header:?}"),
+            MerkleError::LeafCountMismatch { expected, got } => {
+                write!(f, "expected {expected} leaves, got {got}")
+            }
+            MerkleError::DegenerateRoot => write!(f, "degenerate root"),
+            MerkleError::LineTooLong { line } => write!(f, "line {line}: line is too long"),
+            MerkleError::BadProof { reason } => write!(f, "invalid proof: {reason}"),
+            MerkleError::BadState { reason } => write!(f, "invalid state: {reason}"),
+            MerkleError::MisalignedOffset { offset } => {
+                write!(f, "offset {offset} is not on a line boundary")
+            }
+            MerkleError::CrlfLine { line } => {
+                write!(
+                    f,
+                    "line {line}: seeking requires '\\n' line endings, got \"\\r\\n\""
+                )
+            }
+            MerkleError::TruncatedRecord { record, got } => {
+                write!(f, "record {record}: expected 32 bytes, got {got}")
+            }
+            MerkleError::FrontierMismatch { leaves } => {
+                write!(f, "the first {leaves} leaves do not match the frontier")
+            }
+            MerkleError::BadRange { reason } => write!(f, "invalid range: {reason}"),
+            MerkleError::BadJson { reason } => write!(f, "invalid JSON: {reason}"),
+            MerkleError::Unsorted { line } => write!(f, "line {line}: hash is out of order"),
+            MerkleError::IndexOutOfRange { index, leaves } => {
+                write!(f, "leaf index {index} is out of range of {leaves} leaves")
+            }
+            MerkleError::OddLayer { level, size } => {
+                write!(f, "level {level} has the odd number of nodes {size}")
+            }
+            MerkleError::BadTreeSizes { old_size, new_size } => {
+                write!(
+                    f,
+                    "tree of {old_size} leaves can not be a prefix of {new_size} leaves"
+                )
+            }
+            MerkleError::Cancelled => write!(f, "calculation cancelled"),
+        }
+    }
+}
+
+impl MerkleError {
+    ///
+    /// Returns the line (1-based) of the source the error was found on, if
+    /// the error is bound to a single line.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            MerkleError::BadLength { line, .. }
+            | MerkleError::BadHex { line, .. }
+            | MerkleError::BadBase64 { line, .. }
+            | MerkleError::BadUtf8 { line }
+            | MerkleError::DuplicateLeaf { line }
+            | MerkleError::LineTooLong { line }
+            | MerkleError::CrlfLine { line }
+            | MerkleError::Unsorted { line } => Some(*line),
+            _ => None,
+        }
+    }
+}
+
+impl Error for MerkleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            MerkleError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for MerkleError {
+    fn from(error: std::io::Error) -> Self {
+        MerkleError::Io(error)
+    }
+}
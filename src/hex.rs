@@ -0,0 +1,147 @@
+//!
+//! Implements the base16 form of the hashes: parsing and printing.
+//!
+//! The algorithms work on the bare [`type@Hash`] arrays, [`HexHash`] wraps
+//! one to read it from and to write it to the text, e.g. the arguments, the
+//! files and the messages.
+
+use crate::calc::decode_hex;
+use crate::error::MerkleError;
+use crate::Hash;
+use core::fmt;
+use core::str::FromStr;
+
+///
+/// The hash parsed from and printed as the base16 string.
+///
+/// Parsing accepts any case, the `0x` prefix and the surrounding whitespace,
+/// see [`decode_hex`]. `Display` and `Debug` print the lowercase hex, the
+/// `{:X}` format prints the uppercase one, and the alternate `{:#x}` format
+/// adds the `0x` prefix.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::hex::HexHash;
+///
+/// let hex = "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053";
+/// let hash: HexHash = hex.to_uppercase().parse().unwrap();
+///
+/// assert_eq!(0x77, hash.0[0]);
+/// assert_eq!(hex, hash.to_string());
+/// assert_eq!(format!("0x{hex}"), format!("{hash:#x}"));
+/// assert!("77d5".parse::<HexHash>().is_err());
+/// ```
+#[derive(Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HexHash(pub Hash);
+
+impl HexHash {
+    fn write(&self, f: &mut fmt::Formatter<'_>, upper: bool) -> fmt::Result {
+        let mut buf = [0u8; 64];
+        // the buffer fits the hash exactly, the encoding can not fail
+        let hex = match upper {
+            true => base16ct::upper::encode_str(&self.0, &mut buf),
+            false => base16ct::lower::encode_str(&self.0, &mut buf),
+        }
+        .map_err(|_| fmt::Error)?;
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        f.write_str(hex)
+    }
+}
+
+impl FromStr for HexHash {
+    type Err = MerkleError;
+
+    ///
+    /// Parses the hash, the errors naming the line 1 of the string.
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        decode_hex(hex, 1).map(Self)
+    }
+}
+
+impl fmt::Display for HexHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl fmt::Debug for HexHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl fmt::LowerHex for HexHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl fmt::UpperHex for HexHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, true)
+    }
+}
+
+impl AsRef<[u8]> for HexHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Hash> for HexHash {
+    fn from(hash: Hash) -> Self {
+        Self(hash)
+    }
+}
+
+impl From<HexHash> for Hash {
+    fn from(hash: HexHash) -> Self {
+        hash.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::ToString;
+
+    const LEAF: &str = "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053";
+
+    #[test]
+    fn round_trip() {
+        let hash: HexHash = LEAF.parse().unwrap();
+        assert_eq!(LEAF, hash.to_string());
+        assert_eq!(LEAF, format!("{hash:?}"));
+        assert_eq!(LEAF.to_uppercase(), format!("{hash:X}"));
+        assert_eq!(format!("0x{LEAF}"), format!("{hash:#x}"));
+        assert_eq!(hash, format!("0x{}", LEAF.to_uppercase()).parse().unwrap());
+
+        let bytes: Hash = hash.into();
+        assert_eq!(hash, HexHash::from(bytes));
+        assert_eq!(&bytes[..], hash.as_ref());
+    }
+
+    #[test]
+    fn bad_hex() {
+        assert!(matches!(
+            LEAF[1..].parse::<HexHash>(),
+            Err(MerkleError::BadLength {
+                line: 1,
+                got: 63,
+                want: 64
+            })
+        ));
+        assert!(matches!(
+            format!("{LEAF}0").parse::<HexHash>(),
+            Err(MerkleError::BadLength { got: 65, .. })
+        ));
+        assert!(matches!(
+            LEAF.replacen("77", "zz", 1).parse::<HexHash>(),
+            Err(MerkleError::BadHex { line: 1, .. })
+        ));
+    }
+}
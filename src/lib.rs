@@ -1,4 +1,64 @@
+//!
+//! Calculates the merkle tree root of the list of hashes.
+//!
+//! Without the default `std` feature, the crate is `no_std`: the [`calc`]
+//! module only needs `alloc`, the readers of the files and the command line
+//! tool are left out.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "tokio")]
+pub mod async_source;
 pub mod calc;
+#[cfg(feature = "std")]
+pub mod compression;
+pub mod error;
+pub mod hex;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod retry;
+#[cfg(feature = "std")]
+pub mod selftest;
+#[cfg(feature = "std")]
 pub mod source;
+#[cfg(all(test, feature = "std"))]
+mod test_fixtures;
+
+pub use calc::Mode;
+
+#[cfg(feature = "std")]
+use error::MerkleError;
+#[cfg(feature = "std")]
+use source::SourceReader;
 
 pub type Hash = [u8; 32];
+
+///
+/// Calculates the root of the file with one base16 hash per line, the way the
+/// command line tool does by default. The file may be compressed, see
+/// [`compression`], and `-` reads the standard input.
+///
+/// # Examples:
+///
+/// ```
+/// use merkle_root::calc::hash;
+/// use merkle_root::{merkle_root_of_file, Mode};
+///
+/// let path = std::env::temp_dir().join("merkle_root_of_file.hex");
+/// let leaves = format!("{}\n{}\n", "00".repeat(32), "01".repeat(32));
+/// std::fs::write(&path, leaves).unwrap();
+/// let root = merkle_root_of_file(path.to_str().unwrap(), Mode::DepthWalk).unwrap();
+///
+/// assert_eq!(hash(&[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+#[cfg(feature = "std")]
+pub fn merkle_root_of_file(path: &str, mode: Mode) -> Result<Hash, MerkleError> {
+    calc::root_from_source(SourceReader::new(path.to_string())?, mode)
+}
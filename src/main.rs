@@ -1,34 +1,1126 @@
-use clap::{Parser, ValueEnum};
-use merkle_root::calc::{depth_walk::DepthWalk, hash, width_walk::WidthWalk};
-use merkle_root::source::SourceReader;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use merkle_root::calc::{
+    self,
+    accumulator::{MerkleAccumulator, VerifyFrontier},
+    bind_index, ct_eq,
+    depth_walk::DepthWalk,
+    dir::{dir_root, leaves_from_paths, sorted_files},
+    ensure_balanced, ensure_non_degenerate, hash_level_domain, hash_rfc6962, hash_with_salt,
+    parallel_shards::{default_shards, ParallelShards},
+    root_from_source, source_tag, tree_height,
+    width_walk::{write_levels, WidthWalk},
+    window::Windows,
+    Combiner, AUTO_THRESHOLD,
+};
+use merkle_root::compression::{self, Input, STDIN};
+use merkle_root::error::MerkleError;
+use merkle_root::hex::HexHash;
+use merkle_root::profile::{Profile, Timed};
+use merkle_root::progress::Progress;
+use merkle_root::selftest;
+use merkle_root::source::{
+    self, Counted, DiffSet, ForbidDuplicates, JsonArrayReader, LenientReader, RecordReader,
+    SourceReader, UntilError, DEFAULT_BUFFER_CAPACITY,
+};
+use merkle_root::Hash;
+#[cfg(feature = "parallel")]
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{stderr, stdout, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Input file, containing one base16 sha256 hash per line
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Input file, containing one base16 sha256 hash per line (default: -, the standard input)
     #[arg(short, long)]
-    file: String,
-    /// Calculation mode (default: depth-walk)
+    file: Option<String>,
+    /// Calculate the root of the directory tree: the roots of the files and the
+    /// subdirectories, sorted by name, combined per directory
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["file", "mode", "shards", "intermediate", "start_level"]
+    )]
+    dir: Option<String>,
+    /// Calculate the root over the sha256 of the files of the directory, not descending into
+    /// the subdirectories, sorted by name
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["file", "dir", "shards", "threads", "intermediate", "start_level"]
+    )]
+    files: Option<String>,
+    /// Calculation mode (default: auto)
     #[arg(short, long, value_enum)]
     mode: Option<Mode>,
+    /// Number of shards for the parallel-shards mode (default: number of CPUs)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    shards: Option<u64>,
+    /// Number of threads for the width-walk mode (default: 0, the rayon default)
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+    /// Bind each leaf to its position: leaf' = sha256(index_le_u64 || leaf)
+    #[arg(long)]
+    bind_index: bool,
+    /// Prefix each branch with its length: sha256(len || left || len || right)
+    #[arg(long)]
+    length_prefixed: bool,
+    /// Order of the branches in the concatenation: left||right or right||left
+    #[arg(long, value_enum, default_value_t = ConcatOrder::Lr)]
+    concat_order: ConcatOrder,
+    /// Handling of the last node of the odd level: hash it with itself, carry it up unchanged,
+    /// or fail unless the number of leaves is a power of two
+    #[arg(long, value_enum, default_value_t = OddPolicy::Duplicate, conflicts_with = "level_domain")]
+    odd_policy: OddPolicy,
+    /// Hash of the nodes: sha256(left || right), or the RFC 6962 (Certificate
+    /// Transparency) sha256(0x01 || left || right) over the leaf hashes sha256(0x00 || entry)
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = NodeHash::Sha256,
+        conflicts_with_all = ["length_prefixed", "concat_order", "odd_policy", "level_domain"]
+    )]
+    hash: NodeHash,
+    /// Prefix each node with the salt, so the trees of different salts share no subtrees:
+    /// sha256(salt || left || right)
+    #[arg(long, value_name = "STRING", conflicts_with_all = ["length_prefixed", "hash", "level_domain"])]
+    salt: Option<String>,
+    /// Print the root of every window of W consecutive leaves as "position\troot"
+    #[arg(long, value_name = "W", value_parser = clap::value_parser!(u64).range(1..))]
+    window: Option<u64>,
+    /// Bind each node to its level in the tree: sha256(level_le_u64 || left || right)
+    #[arg(long, conflicts_with_all = ["length_prefixed", "window"])]
+    level_domain: bool,
+    /// Treat the input as an intermediate level of a larger tree rather than leaves
+    #[arg(long)]
+    intermediate: bool,
+    /// Level of the intermediate input in the larger tree (leaves are level 0)
+    #[arg(long, value_name = "LEVEL", requires = "intermediate")]
+    start_level: Option<usize>,
+    /// Expect the first line to declare the number of leaves in the file
+    #[arg(long)]
+    counted_header: bool,
+    /// Prepend the synthetic leaf sha256(file name || file size) to bind the root to the
+    /// file; changes the root and adds one leaf
+    #[arg(long, conflicts_with_all = ["load_state", "verify_frontier"])]
+    tag_source: bool,
+    /// Calculate the root over the leaves absent from the other file (both files must be sorted)
+    #[arg(long, value_name = "OTHER")]
+    diff_set: Option<String>,
+    /// Fail on the first duplicate leaf (keeps every leaf seen in memory)
+    #[arg(long)]
+    forbid_duplicates: bool,
+    /// Fail if the root is degenerate: the zero hash, or a leaf of a multi-leaf tree (keeps every
+    /// leaf in memory)
+    #[arg(long, conflicts_with = "load_state")]
+    paranoid: bool,
+    /// Load the calculation state (saved with --save-state) before reading the leaves
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["window", "level_domain"])]
+    load_state: Option<String>,
+    /// Save the calculation state after reading the leaves, to resume it later
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["window", "level_domain"])]
+    save_state: Option<String>,
+    /// Start reading the leaves at the byte offset, covered by the --load-state state
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "load_state",
+        conflicts_with = "counted_header"
+    )]
+    resume_from_offset: Option<u64>,
+    /// Verify the leaves extend the frontier, saved with --save-state from a prefix
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["level_domain", "intermediate", "resume_from_offset"]
+    )]
+    verify_frontier: Option<String>,
+    /// Print the root as a 0x-prefixed hex string (same as --format prefixed)
+    #[arg(long, conflicts_with = "format")]
+    hex_prefix_output: bool,
+    /// Format of the printed hashes
+    #[arg(long, value_enum, default_value_t = Format::Lower)]
+    format: Format,
+    /// Output of the root: the bare hash, or the JSON object with the root, the
+    /// number of leaves and the mode
+    #[arg(long, value_enum, default_value_t = Output::Text, conflicts_with_all = ["dir", "window", "mmr"])]
+    output: Output,
+    /// Exit with 1 if the root differs from the expected base16 hash (case-insensitive)
+    #[arg(
+        long,
+        value_name = "HASH",
+        value_parser = parse_hash,
+        conflicts_with_all = ["window", "mmr"]
+    )]
+    expect: Option<Hash>,
+    /// Accept recoverable anomalies of the input (uppercase hex, 0x prefixes, CRLF
+    /// line endings, blank lines), printing a warning to stderr for each
+    #[arg(long, conflicts_with_all = ["counted_header", "resume_from_offset"])]
+    warnings: bool,
+    /// Skip the blank lines and the comment lines, starting with '#'
+    #[arg(long, conflicts_with_all = ["warnings", "json_input", "resume_from_offset"])]
+    skip_comments: bool,
+    /// Separator of the hashes: newlines, or any ASCII whitespace (spaces, tabs and
+    /// newlines), reading several hashes per line
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Separator::Newline,
+        conflicts_with_all = ["warnings", "json_input", "binary", "resume_from_offset"]
+    )]
+    separator: Separator,
+    /// Read the hashes from a JSON array of base16 strings
+    #[arg(
+        long,
+        conflicts_with_all = ["counted_header", "resume_from_offset", "warnings"]
+    )]
+    json_input: bool,
+    /// Read the hashes as raw 32 bytes records, concatenated without separators
+    #[arg(
+        long,
+        conflicts_with_all = ["counted_header", "resume_from_offset", "warnings", "skip_comments", "json_input"]
+    )]
+    binary: bool,
+    /// Print the Merkle Mountain Range peaks and their bagged root instead of the root
+    #[arg(long, conflicts_with_all = ["window", "level_domain", "intermediate", "paranoid"])]
+    mmr: bool,
+    /// Write every level of the tree, from the leaves up to the root, to the file as
+    /// base16 hashes under "# level" headers (keeps the whole tree in memory)
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["mode", "dir", "files", "window", "mmr", "level_domain", "load_state", "save_state"]
+    )]
+    dump_levels: Option<String>,
+    /// Compression of the input file (default: auto, detected by the magic bytes)
+    #[arg(long, value_enum, default_value_t = Compression::Auto, hide_default_value = true)]
+    compression: Compression,
+    /// Capacity of the read buffer of the input files, in bytes (default: 8192)
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    buffer_size: Option<u64>,
+    /// Print the number of leaves and the height of the tree to stderr
+    #[arg(long, conflicts_with_all = ["dir", "window", "mmr"])]
+    stats: bool,
+    /// Print the time spent in each stage of the calculation to stderr
+    #[arg(long, conflicts_with = "window")]
+    profile: bool,
+    /// Render progress to stderr (default: auto, only if stderr is a terminal)
+    #[arg(long, value_enum, default_value_t = When::Auto, hide_default_value = true)]
+    progress: When,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Exit with 0 if both files have the same root, with 1 otherwise
+    Equals {
+        /// First input file
+        left: String,
+        /// Second input file
+        right: String,
+        /// Encoding of the first input file
+        #[arg(long, value_enum, default_value_t = Encoding::Base16)]
+        left_encoding: Encoding,
+        /// Encoding of the second input file
+        #[arg(long, value_enum, default_value_t = Encoding::Base16)]
+        right_encoding: Encoding,
+        /// Print both roots
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Print the hashes of the file in the canonical form: one lowercase hex hash per line
+    Normalize {
+        /// Input file; hashes may be mixed-case, 0x-prefixed, with CRLF line
+        /// endings, blank lines and comment lines starting with '#'
+        input: String,
+    },
+    /// Print the number and the hash of the candidate root matching the root of the file,
+    /// exit with 0 if one matches, with 1 otherwise
+    Audit {
+        /// Input file
+        file: String,
+        /// File of candidate roots, one base16 hash per line
+        candidates: String,
+    },
+    /// Run the built-in known-answer tests, exit with 0 if all of them pass, with 1 otherwise
+    Selftest,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Mode {
+    /// Depth-walk below 4096 leaves, width-walk otherwise
+    Auto,
     /// Depth-walk algorithm: time O(n*log(n)), space O(log(n))
     DepthWalk,
     /// Width-walk algorithm: time *O(n*log(n)), space O(n*log(n))
     WidthWalk,
+    /// Parallel-shards algorithm: time O(n*log(n)), space O(s*log(n)), s = shards
+    ParallelShards,
+}
+
+impl From<Mode> for calc::Mode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::DepthWalk => calc::Mode::DepthWalk,
+            Mode::WidthWalk => calc::Mode::WidthWalk,
+            Mode::ParallelShards => calc::Mode::ParallelShards,
+            Mode::Auto => calc::Mode::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum When {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ConcatOrder {
+    Lr,
+    Rl,
+}
+
+impl From<ConcatOrder> for calc::ConcatOrder {
+    fn from(order: ConcatOrder) -> Self {
+        match order {
+            ConcatOrder::Lr => calc::ConcatOrder::LeftRight,
+            ConcatOrder::Rl => calc::ConcatOrder::RightLeft,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Format {
+    /// Lowercase hex
+    Lower,
+    /// Uppercase hex
+    Upper,
+    /// Lowercase hex with the 0x prefix
+    Prefixed,
+}
+
+impl Format {
+    fn hash(&self, hash: &Hash) -> String {
+        let hash = HexHash(*hash);
+        match self {
+            Format::Lower => format!("{hash:x}"),
+            Format::Upper => format!("{hash:X}"),
+            Format::Prefixed => format!("{hash:#x}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Output {
+    /// The hash alone
+    Text,
+    /// {"root":"<hash>","leaves":<n>,"mode":"<mode>"}
+    Json,
+}
+
+impl Output {
+    fn root(&self, root: &str, leaves: u64, mode: Mode) -> String {
+        match self {
+            Output::Text => root.to_string(),
+            Output::Json => format!(
+                r#"{{"root":"{root}","leaves":{leaves},"mode":"{}"}}"#,
+                mode.to_possible_value().unwrap().get_name()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum NodeHash {
+    Sha256,
+    Rfc6962,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum OddPolicy {
+    Duplicate,
+    Promote,
+    Strict,
+}
+
+impl From<OddPolicy> for calc::OddPolicy {
+    fn from(odd: OddPolicy) -> Self {
+        match odd {
+            OddPolicy::Duplicate => calc::OddPolicy::Duplicate,
+            OddPolicy::Promote => calc::OddPolicy::Promote,
+            OddPolicy::Strict => calc::OddPolicy::Strict,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Compression {
+    Auto,
+    None,
+    Gzip,
+    Zstd,
+    Xz,
 }
 
-fn main() {
+impl From<Compression> for compression::Compression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Auto => compression::Compression::Auto,
+            Compression::None => compression::Compression::None,
+            Compression::Gzip => compression::Compression::Gzip,
+            Compression::Zstd => compression::Compression::Zstd,
+            Compression::Xz => compression::Compression::Xz,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Encoding {
+    Base16,
+    Base64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum Separator {
+    Newline,
+    Whitespace,
+}
+
+impl From<Separator> for source::Separator {
+    fn from(separator: Separator) -> Self {
+        match separator {
+            Separator::Newline => source::Separator::Newline,
+            Separator::Whitespace => source::Separator::Whitespace,
+        }
+    }
+}
+
+impl From<Encoding> for source::Encoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Base16 => source::Encoding::Base16,
+            Encoding::Base64 => source::Encoding::Base64,
+        }
+    }
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
-    let mut reader = SourceReader::new(args.file).unwrap().peekable();
-    let mut hash = match args.mode {
-        Some(Mode::DepthWalk) | None => DepthWalk::calculate(&mut reader, &hash),
-        Some(Mode::WidthWalk) => WidthWalk::calculate(&mut reader, &hash),
-    };
-    let mut buf = [0u8; 64];
-    let root = base16ct::lower::encode_str(&mut hash, &mut buf).unwrap();
-    println!("{root}");
+    match args.command {
+        Some(Command::Equals {
+            left,
+            right,
+            left_encoding,
+            right_encoding,
+            verbose,
+        }) => equals(left, left_encoding, right, right_encoding, verbose),
+        Some(Command::Normalize { input }) => normalize(input),
+        Some(Command::Audit { file, candidates }) => audit(file, candidates),
+        Some(Command::Selftest) => selftest(),
+        None => root(args),
+    }
+}
+
+fn root(args: Args) -> ExitCode {
+    let combiner = Combiner {
+        length_prefixed: args.length_prefixed,
+        order: args.concat_order.into(),
+        odd: args.odd_policy.into(),
+    };
+    let node_hash = args.hash;
+    let salted = args
+        .salt
+        .as_deref()
+        .map(|salt| hash_with_salt(salt.as_bytes()));
+    let hash_fn = move |left: &Hash, right: Option<&Hash>| match (node_hash, salted) {
+        (NodeHash::Sha256, None) => combiner.hash(left, right),
+        (NodeHash::Sha256, Some(salted)) => combiner.hash_with(left, right, &salted),
+        (NodeHash::Rfc6962, _) => hash_rfc6962(left, right),
+    };
+    let format = match args.hex_prefix_output {
+        true => Format::Prefixed,
+        false => args.format,
+    };
+    let strict = args.odd_policy == OddPolicy::Strict;
+    if strict && (args.dir.is_some() || args.window.is_some() || args.mmr) {
+        // the directory levels, the windows and the mountain ranges are odd by design
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--odd-policy strict does not support --dir, --window or --mmr",
+            )
+            .exit();
+    }
+    if let Some(dir) = &args.dir {
+        // the directory is walked instead of reading the leaf stream
+        if leaf_stream_options(&args) {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--dir does not support leaf stream options",
+                )
+                .exit();
+        }
+        return match dir_root(Path::new(dir), &hash_fn) {
+            Ok(root) => {
+                println!("{}", format.hash(&root));
+                check_expected(&root, args.expect.as_ref())
+            }
+            Err(error) => fail(error),
+        };
+    }
+    if let Some(dir) = &args.files {
+        // the leaves are hashed from the files instead of reading the leaf stream
+        if leaf_stream_options(&args) {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--files does not support leaf stream options",
+                )
+                .exit();
+        }
+        let leaves = match sorted_files(Path::new(dir)).and_then(|files| leaves_from_paths(&files))
+        {
+            Ok(leaves) => leaves,
+            Err(error) => return fail(error),
+        };
+        let count = leaves.len();
+        if strict {
+            if let Err(error) = ensure_balanced(count) {
+                return fail(error);
+            }
+        }
+        let mode = resolve(args.mode, count);
+        let root = match calc::Mode::from(mode)
+            .try_calculate(&mut leaves.into_iter().peekable(), &hash_fn)
+        {
+            Ok(root) => root,
+            Err(error) => return fail(error),
+        };
+        println!(
+            "{}",
+            args.output.root(&format.hash(&root), count as u64, mode)
+        );
+        if args.stats {
+            print_stats(count as u64);
+        }
+        return check_expected(&root, args.expect.as_ref());
+    }
+    let file = args.file.clone().unwrap_or_else(|| STDIN.to_string());
+    if args.mode == Some(Mode::ParallelShards) {
+        // shards are read directly from the file, bypassing the leaf stream
+        if leaf_stream_options(&args) {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "parallel-shards mode does not support leaf stream options",
+                )
+                .exit();
+        }
+        if file == STDIN {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "parallel-shards mode can not read the standard input",
+                )
+                .exit();
+        }
+        let shards = args.shards.map(|shards| shards as usize);
+        let (root, leaves) = match parallel_shards(file, shards, strict, &hash_fn) {
+            Ok(counted) => counted,
+            Err(error) => return fail(error),
+        };
+        println!(
+            "{}",
+            args.output
+                .root(&format.hash(&root), leaves as u64, Mode::ParallelShards)
+        );
+        if args.stats {
+            print_stats(leaves as u64);
+        }
+        return check_expected(&root, args.expect.as_ref());
+    }
+    // 65 bytes per leaf: 64 hex characters and a newline, possibly missing on
+    // the last line, or 32 raw bytes
+    let leaf_bytes = match args.binary {
+        true => 32,
+        false => 65,
+    };
+    let total = match file.as_str() {
+        STDIN => None,
+        file => std::fs::metadata(file)
+            .ok()
+            .map(|meta| (meta.len() as usize).div_ceil(leaf_bytes)),
+    };
+    let buffer_size = args
+        .buffer_size
+        .map_or(DEFAULT_BUFFER_CAPACITY, |size| size as usize);
+    let mut reader = match Input::open(file.clone(), buffer_size, args.compression.into()) {
+        Ok(input) => SourceReader::from_input(input)
+            .with_skip_comments(args.skip_comments)
+            .with_separator(args.separator.into()),
+        Err(error) => return fail(error.into()),
+    };
+    // index of the first leaf read, non-zero when resuming
+    let mut first_index = 0;
+    if let Some(offset) = args.resume_from_offset {
+        first_index = match reader.seek_offset(offset) {
+            Ok(index) => index as u64,
+            Err(error) => return fail(error),
+        };
+    }
+    let accumulator = match &args.load_state {
+        Some(path) => match load_state(path, Some(first_index)) {
+            Ok(accumulator) => Some(accumulator),
+            Err(error) => return fail(error),
+        },
+        None if args.save_state.is_some() || args.mmr => Some(MerkleAccumulator::new()),
+        None => None,
+    };
+    let mut checked: Box<dyn Iterator<Item = Result<Hash, MerkleError>>> = if args.json_input {
+        Box::new(JsonArrayReader::new(reader.into_input()))
+    } else if args.binary {
+        Box::new(RecordReader::new(reader.into_input()))
+    } else if args.warnings {
+        // the fixed-width reader can not recover from the anomalies, the
+        // lenient one reads the input instead
+        Box::new(
+            LenientReader::new(reader.into_input())
+                .with_warnings(|warning| eprintln!("warning: {warning}")),
+        )
+    } else if args.counted_header {
+        match reader.read_header() {
+            Ok(expected) => Box::new(Counted::new(reader, expected)),
+            Err(error) => return fail(error),
+        }
+    } else {
+        Box::new(reader)
+    };
+    if let Some(other) = &args.diff_set {
+        match SourceReader::with_buffer_capacity(buffer_size, other.clone()) {
+            Ok(other) => {
+                let other = other
+                    .with_skip_comments(args.skip_comments)
+                    .with_separator(args.separator.into());
+                checked = Box::new(DiffSet::new(checked, other))
+            }
+            Err(error) => return fail(error.into()),
+        }
+    }
+    if args.tag_source {
+        if file == STDIN {
+            Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--tag-source can not tag the standard input",
+                )
+                .exit();
+        }
+        let name = Path::new(&file).file_name().unwrap_or_default();
+        match std::fs::metadata(&file) {
+            Ok(meta) => {
+                let tag = source_tag(&name.to_string_lossy(), meta.len());
+                checked = Box::new(std::iter::once(Ok(tag)).chain(checked));
+            }
+            Err(error) => return fail(error.into()),
+        }
+    }
+    if args.forbid_duplicates {
+        checked = Box::new(ForbidDuplicates::new(checked));
+    }
+    if args.bind_index {
+        // items past the first error are never read, so the index of a leaf is
+        // the index of its item
+        checked = Box::new(checked.enumerate().map(move |(index, leaf)| {
+            leaf.map(|leaf| bind_index(first_index + index as u64, &leaf))
+        }));
+    }
+    if let Some(path) = &args.verify_frontier {
+        match load_state(path, None) {
+            Ok(frontier) => {
+                checked = Box::new(VerifyFrontier::new(checked, frontier.with_hash_fn(hash_fn)))
+            }
+            Err(error) => return fail(error),
+        }
+    }
+    // count of the leaves, and every leaf for the paranoid check
+    let mut count = 0;
+    let mut seen = Vec::new();
+    let mut source = UntilError::new(checked);
+    let mut leaves: Box<dyn Iterator<Item = Hash>> = Box::new(&mut source);
+    let paranoid = args.paranoid;
+    leaves = Box::new(leaves.inspect(|leaf| {
+        count += 1;
+        if paranoid {
+            seen.push(*leaf);
+        }
+    }));
+    let mut profile = args.profile.then(Profile::new);
+    let read_time = Cell::new(Duration::ZERO);
+    if profile.is_some() {
+        leaves = Box::new(Timed::new(leaves, &read_time));
+    }
+    let mut leaves = match args.progress {
+        When::Auto => Progress::stderr(leaves),
+        When::Always => Progress::new(leaves, stderr(), true),
+        When::Never => Progress::new(leaves, stderr(), false),
+    };
+    if let Some(total) = total {
+        leaves = leaves.with_total(total);
+    }
+    if let Some(window) = args.window {
+        for (position, root) in Windows::new(leaves, window as usize, &hash_fn) {
+            println!("{position}\t{}", format.hash(&root));
+        }
+        return match source.error() {
+            Some(error) => fail(error),
+            None => ExitCode::SUCCESS,
+        };
+    }
+    if let Some(accumulator) = accumulator {
+        let mut accumulator = accumulator.with_hash_fn(hash_fn);
+        accumulator.extend(leaves);
+        if let Some(error) = source.error() {
+            return fail(error);
+        }
+        if strict {
+            if let Err(error) = ensure_balanced(accumulator.leaf_count() as usize) {
+                return fail(error);
+            }
+        }
+        if let Some(profile) = &mut profile {
+            profile.record("read", read_time.get());
+            profile.record("hash", profile.elapsed() - read_time.get());
+            let _ = profile.write(stderr());
+        }
+        let (root, label) = match args.mmr {
+            true => (accumulator.bagged_root(), "root\t"),
+            false => (accumulator.root(), ""),
+        };
+        // the state of the degenerate root is not saved
+        if let (true, Some(root)) = (paranoid, &root) {
+            if let Err(error) = ensure_non_degenerate(root, seen) {
+                return fail(error);
+            }
+        }
+        if let Some(path) = args.save_state {
+            if let Err(error) = File::create(path).and_then(|file| accumulator.save(file)) {
+                return fail(error.into());
+            }
+        }
+        if args.mmr {
+            for (height, peak) in accumulator.peaks() {
+                println!("peak\t{height}\t{}", format.hash(peak));
+            }
+        }
+        return match root {
+            Some(root) => {
+                let leaves = accumulator.leaf_count();
+                let mode = args.mode.unwrap_or(Mode::DepthWalk);
+                println!(
+                    "{label}{}",
+                    args.output.root(&format.hash(&root), leaves, mode)
+                );
+                if args.stats {
+                    print_stats(leaves);
+                }
+                check_expected(&root, args.expect.as_ref())
+            }
+            None => fail(MerkleError::EmptySource),
+        };
+    }
+    // the intermediate input is reduced by the same rules as leaves, only the
+    // level of the nodes differs
+    let level = args.start_level.unwrap_or(0);
+    // the width-walk runs in its own pool, unless the thread count is left to rayon
+    #[cfg(feature = "parallel")]
+    let pool = match args.threads {
+        0 => None,
+        threads => match ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => Some(pool),
+            Err(error) => return fail(MerkleError::Io(std::io::Error::other(error))),
+        },
+    };
+    // without rayon, the width-walk runs on the calling thread
+    #[cfg(not(feature = "parallel"))]
+    let pool = match args.threads {
+        0 => None,
+        _ => Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--threads requires the parallel feature",
+            )
+            .exit(),
+    };
+    // the reduction of each level is timed for the width-walk only
+    let mut levels = Vec::new();
+    let mut lap = Instant::now();
+    let mut observe = |level| {
+        levels.push((level, lap.elapsed()));
+        lap = Instant::now();
+    };
+    // the auto mode picks the algorithm by the number of leaves, buffering
+    // them up to the threshold
+    let head: Vec<Hash> = match (args.mode, &args.dump_levels) {
+        (None | Some(Mode::Auto), None) => leaves.by_ref().take(AUTO_THRESHOLD).collect(),
+        _ => Vec::new(),
+    };
+    let mode = match args.dump_levels {
+        // the levels are kept by the width-walk only
+        Some(_) => Mode::WidthWalk,
+        None => resolve(args.mode, head.len()),
+    };
+    let leaves = head.into_iter().chain(leaves);
+    let hash = match (&args.dump_levels, args.level_domain) {
+        (Some(path), _) => dump_levels(leaves, path, &hash_fn),
+        (None, true) => calculate(
+            leaves,
+            mode,
+            pool.as_ref(),
+            level,
+            &hash_level_domain,
+            &mut observe,
+        ),
+        (None, false) => calculate(
+            leaves,
+            mode,
+            pool.as_ref(),
+            level,
+            &|_, left, right| hash_fn(left, right),
+            &mut observe,
+        ),
+    };
+    if let Some(error) = source.error() {
+        return fail(error);
+    }
+    let hash = match hash {
+        Ok(hash) => hash,
+        Err(error) => return fail(error),
+    };
+    if strict {
+        // the odd node was hashed with itself, the levels are checked once
+        // the number of nodes is known
+        if let Err(error) = ensure_balanced(count) {
+            return fail(match error {
+                MerkleError::OddLayer { level: odd, size } => MerkleError::OddLayer {
+                    level: level + odd,
+                    size,
+                },
+                error => error,
+            });
+        }
+    }
+    if let Some(profile) = &mut profile {
+        profile.record("read", read_time.get());
+        profile.record("hash", profile.elapsed() - read_time.get());
+        // the first observed level is the source, collected before reduction
+        for (level, duration) in levels.into_iter().skip(1) {
+            profile.record(format!("level {level}"), duration);
+        }
+        let _ = profile.write(stderr());
+    }
+    if paranoid {
+        if let Err(error) = ensure_non_degenerate(&hash, seen) {
+            return fail(error);
+        }
+    }
+    let root = format.hash(&hash);
+    println!("{}", args.output.root(&root, count as u64, mode));
+    if args.stats {
+        print_stats(count as u64);
+    }
+    check_expected(&hash, args.expect.as_ref())
+}
+
+///
+/// Prints the number of leaves and the height of the tree to stderr, aligned
+/// the same way the profile is.
+fn print_stats(leaves: u64) {
+    eprintln!("{:<10}{leaves:>10}", "leaves");
+    eprintln!("{:<10}{:>10}", "height", tree_height(leaves as usize));
+}
+
+///
+/// Calculates the root with the width-walk, writing every level of the tree
+/// to the file.
+fn dump_levels<I, F>(leaves: I, path: &str, hash_fn: &F) -> Result<Hash, MerkleError>
+where
+    I: Iterator<Item = Hash>,
+    F: Fn(&Hash, Option<&Hash>) -> Hash + Sync + Send,
+{
+    let levels = WidthWalk::calculate_levels(&mut leaves.peekable(), hash_fn)?;
+    let mut out = BufWriter::new(File::create(path)?);
+    write_levels(&levels, &mut out)?;
+    out.flush()?;
+    // the last level is the single root
+    Ok(levels[levels.len() - 1][0])
+}
+
+///
+/// Picks the algorithm of the auto mode, the default one, for the number of
+/// leaves, see [`calc::Mode::Auto`].
+fn resolve(mode: Option<Mode>, leaves: usize) -> Mode {
+    match mode {
+        None | Some(Mode::Auto) if leaves < AUTO_THRESHOLD => Mode::DepthWalk,
+        None | Some(Mode::Auto) => Mode::WidthWalk,
+        Some(mode) => mode,
+    }
+}
+
+///
+/// Parses the base16 hash of any case, with an optional 0x prefix.
+fn parse_hash(value: &str) -> Result<Hash, String> {
+    match value.parse::<HexHash>() {
+        Ok(hash) => Ok(hash.into()),
+        Err(_) => Err("expected a base16 string of 32 bytes".to_string()),
+    }
+}
+
+///
+/// Exits with 1, printing both roots, if the root differs from the expected one.
+fn check_expected(root: &Hash, expected: Option<&Hash>) -> ExitCode {
+    match expected {
+        Some(expected) if !ct_eq(root, expected) => {
+            eprintln!(
+                "error: root mismatch: expected {}, got {}",
+                encode(expected),
+                encode(root)
+            );
+            ExitCode::FAILURE
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+///
+/// Checks whether any of the options processing the leaf stream is set.
+fn leaf_stream_options(args: &Args) -> bool {
+    args.counted_header
+        || args.forbid_duplicates
+        || args.diff_set.is_some()
+        || args.tag_source
+        || args.bind_index
+        || args.level_domain
+        || args.paranoid
+        || args.window.is_some()
+        || args.load_state.is_some()
+        || args.save_state.is_some()
+        || args.verify_frontier.is_some()
+        || args.profile
+        || args.warnings
+        || args.skip_comments
+        || args.separator != Separator::Newline
+        || args.json_input
+        || args.binary
+        || args.mmr
+}
+
+fn load_state(path: &str, first_index: Option<u64>) -> Result<MerkleAccumulator, MerkleError> {
+    let accumulator = MerkleAccumulator::load(BufReader::new(File::open(path)?))?;
+    let Some(first_index) = first_index else {
+        return Ok(accumulator);
+    };
+    if accumulator.leaf_count() != first_index {
+        return Err(MerkleError::BadState {
+            reason: format!(
+                "state covers {} leaves, expected {first_index}",
+                accumulator.leaf_count()
+            ),
+        });
+    }
+    Ok(accumulator)
+}
+
+///
+/// Calculates the root of the file in shards, returning it along with the
+/// number of leaves. The shards are opened at the leaf offsets, so the file
+/// must be plain, with the '\n' line endings.
+fn parallel_shards<F>(
+    file: String,
+    shards: Option<usize>,
+    strict: bool,
+    hash_fn: &F,
+) -> Result<(Hash, usize), MerkleError>
+where
+    F: Fn(&Hash, Option<&Hash>) -> Hash + Sync + Send,
+{
+    let leaves = SourceReader::new(file.clone())?.seekable_leaves()?;
+    if strict {
+        ensure_balanced(leaves)?;
+    }
+    let shards = shards.unwrap_or_else(default_shards);
+    let open = |start| {
+        let mut reader = SourceReader::new(file.clone())?;
+        reader.seek_leaf(start)?;
+        Ok(reader)
+    };
+    let root = ParallelShards::try_calculate(leaves, shards, &open, hash_fn)?;
+    Ok((root, leaves))
+}
+
+fn equals(
+    left: String,
+    left_encoding: Encoding,
+    right: String,
+    right_encoding: Encoding,
+    verbose: bool,
+) -> ExitCode {
+    let left = match SourceReader::with_encoding(left_encoding.into(), left) {
+        Ok(reader) => reader,
+        Err(error) => return fail(error.into()),
+    };
+    let left = match root_from_source(left, calc::Mode::DepthWalk) {
+        Ok(left) => left,
+        Err(error) => return fail(error),
+    };
+    let right = match SourceReader::with_encoding(right_encoding.into(), right) {
+        Ok(reader) => reader,
+        Err(error) => return fail(error.into()),
+    };
+    let right = match root_from_source(right, calc::Mode::DepthWalk) {
+        Ok(right) => right,
+        Err(error) => return fail(error),
+    };
+    if verbose {
+        println!("{}", encode(&left));
+        println!("{}", encode(&right));
+    }
+    match ct_eq(&left, &right) {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+fn normalize(input: String) -> ExitCode {
+    let input = match Input::open(
+        input,
+        DEFAULT_BUFFER_CAPACITY,
+        compression::Compression::Auto,
+    ) {
+        Ok(input) => input,
+        Err(error) => return fail(error.into()),
+    };
+    let mut out = BufWriter::new(stdout().lock());
+    for leaf in LenientReader::new(input) {
+        let written = match leaf {
+            Ok(leaf) => writeln!(out, "{}", encode(&leaf)),
+            Err(error) => return fail(error),
+        };
+        if let Err(error) = written {
+            return fail(error.into());
+        }
+    }
+    match out.flush() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => fail(error.into()),
+    }
+}
+
+fn audit(file: String, candidates: String) -> ExitCode {
+    let leaves = match SourceReader::new(file) {
+        Ok(leaves) => leaves,
+        Err(error) => return fail(error.into()),
+    };
+    let root = match root_from_source(leaves, calc::Mode::DepthWalk) {
+        Ok(root) => root,
+        Err(error) => return fail(error),
+    };
+    let candidates = match File::open(candidates) {
+        Ok(candidates) => LenientReader::new(BufReader::new(candidates)),
+        Err(error) => return fail(error.into()),
+    };
+    for (number, candidate) in candidates.enumerate() {
+        match candidate {
+            Ok(candidate) if ct_eq(&root, &candidate) => {
+                println!("{}\t{}", number + 1, encode(&candidate));
+                return ExitCode::SUCCESS;
+            }
+            Ok(_) => {}
+            Err(error) => return fail(error),
+        }
+    }
+    ExitCode::FAILURE
+}
+
+fn selftest() -> ExitCode {
+    let outcomes = selftest::run();
+    for outcome in &outcomes {
+        let status = match outcome.passed {
+            true => "pass",
+            false => "FAIL",
+        };
+        println!("{status}\t{}", outcome.name);
+    }
+    match outcomes.iter().all(|outcome| outcome.passed) {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+fn fail(error: MerkleError) -> ExitCode {
+    eprintln!("error: {error}");
+    ExitCode::from(2)
+}
+
+///
+/// The stand-in for the rayon pool without the `parallel` feature, never
+/// created.
+#[cfg(not(feature = "parallel"))]
+enum ThreadPool {}
+
+#[cfg(not(feature = "parallel"))]
+impl ThreadPool {
+    fn install<R>(&self, _: impl FnOnce() -> R) -> R {
+        match *self {}
+    }
+}
+
+///
+/// Calculates the root of the leaves in the mode, running the width-walk in
+/// the pool when one is given.
+fn calculate<I, F, O>(
+    leaves: I,
+    mode: Mode,
+    pool: Option<&ThreadPool>,
+    level: usize,
+    hash_fn: &F,
+    observe: &mut O,
+) -> Result<Hash, MerkleError>
+where
+    I: Iterator<Item = Hash>,
+    F: Fn(usize, &Hash, Option<&Hash>) -> Hash + Sync + Send,
+    O: FnMut(usize) + Send,
+{
+    let mut reader = leaves.peekable();
+    match (mode, pool) {
+        (Mode::DepthWalk, _) => DepthWalk::try_calculate_from_level(&mut reader, level, hash_fn),
+        (Mode::WidthWalk, None) => {
+            WidthWalk::try_calculate_observed(&mut reader, level, hash_fn, observe)
+        }
+        (Mode::WidthWalk, Some(pool)) => {
+            // the leaves are read on the calling thread, only the reduction
+            // runs in the pool
+            let leaves: Vec<Hash> = reader.collect();
+            pool.install(|| {
+                let mut reader = leaves.into_iter().peekable();
+                WidthWalk::try_calculate_observed(&mut reader, level, hash_fn, observe)
+            })
+        }
+        (Mode::ParallelShards, _) => {
+            unreachable!("Expected parallel-shards to read the file")
+        }
+        (Mode::Auto, _) => unreachable!("Expected the auto mode to be resolved"),
+    }
+}
+
+fn encode(hash: &Hash) -> String {
+    HexHash(*hash).to_string()
 }
@@ -0,0 +1,236 @@
+//!
+//! Implements the memory-mapped reader of the input files, available with the
+//! `mmap` feature.
+//!
+//! The file is mapped into memory as a whole, and the hashes are sliced out of
+//! the mapping at the fixed stride of the hash width plus the newline, so
+//! reading a hash does neither a system call nor a copy into the line buffer.
+//! The pages are loaded by the OS on demand and evicted under pressure, so the
+//! file may be larger than the memory.
+//!
+//! Pros: no per-line reads, no copies, fast sequential access to huge files.
+//!
+//! Cons: the file must be a plain text file with '\n' line endings; the
+//! standard input, the compressed files and the CRLF endings are only
+//! supported by [`SourceReader`](crate::source::SourceReader). Modifying the
+//! file while it is mapped is undefined behavior.
+//!
+//! Use-cases: multi-gigabyte input files on the local filesystem.
+
+use crate::error::MerkleError;
+use crate::source::Encoding;
+use crate::Hash;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::Error;
+use std::str;
+
+///
+/// The reader of the memory-mapped input file with one hash per line, yielding
+/// the same items as [`SourceReader`](crate::source::SourceReader).
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{depth_walk::DepthWalk, hash};
+/// use merkle_root::mmap::MmapSourceReader;
+/// use merkle_root::source::UntilError;
+///
+/// let path = std::env::temp_dir().join("mmap_source_reader.hex");
+/// std::fs::write(&path, format!("{}\n{}\n", "00".repeat(32), "01".repeat(32))).unwrap();
+/// let reader = MmapSourceReader::new(path.to_string_lossy().into_owned()).unwrap();
+/// let mut leaves = UntilError::new(reader);
+/// let root = DepthWalk::calculate(&mut (&mut leaves).peekable(), &hash);
+///
+/// assert!(leaves.error().is_none());
+/// assert_eq!(hash(&[0u8; 32], Some(&[1u8; 32])), root);
+/// ```
+#[derive(Debug)]
+pub struct MmapSourceReader {
+    map: Mmap,
+    encoding: Encoding,
+    keep_going: bool,
+    // set once the iteration ended on an error
+    fused: bool,
+    // byte offset of the next line
+    position: usize,
+    // number of lines read so far
+    line: usize,
+}
+
+impl MmapSourceReader {
+    ///
+    /// Maps the input file into memory.
+    pub fn new(filename: String) -> Result<Self, Error> {
+        let file = File::open(filename)?;
+        // SAFETY: the mapping is read-only; the file is expected not to be
+        // modified while it is read, see the module docs
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            map,
+            encoding: Encoding::default(),
+            keep_going: false,
+            fused: false,
+            position: 0,
+            line: 0,
+        })
+    }
+
+    ///
+    /// Maps the input file into memory, decoding the hashes with the
+    /// specified encoding.
+    pub fn with_encoding(encoding: Encoding, filename: String) -> Result<Self, Error> {
+        let mut reader = Self::new(filename)?;
+        reader.encoding = encoding;
+        Ok(reader)
+    }
+
+    ///
+    /// Sets whether the reader moves on past the lines that fail to decode,
+    /// the same way [`SourceReader::with_keep_going`](crate::source::SourceReader::with_keep_going)
+    /// does. The lines are found by the newlines, so no error ends the
+    /// iteration then.
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    ///
+    /// Returns the number of hashes in the file, assuming all the lines are
+    /// well-formed.
+    pub fn len(&self) -> usize {
+        self.map.len().div_ceil(self.encoding.width() + 1)
+    }
+
+    ///
+    /// Checks whether the file has no hashes.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl Iterator for MmapSourceReader {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fused {
+            return None;
+        }
+        let leaf = self.read_leaf()?;
+        self.fused = leaf.is_err() && !self.keep_going;
+        Some(leaf)
+    }
+}
+
+impl MmapSourceReader {
+    ///
+    /// Reads and decodes the next line.
+    fn read_leaf(&mut self) -> Option<Result<Hash, MerkleError>> {
+        let rest = self
+            .map
+            .get(self.position..)
+            .filter(|rest| !rest.is_empty())?;
+        self.line += 1;
+        let width = self.encoding.width();
+        // the well-formed line is the hash followed by the newline, except for
+        // the final one, which may lack the newline
+        let line = match rest.get(width) {
+            Some(b'\n') | None if rest.len() >= width => &rest[..width],
+            _ => {
+                let end = rest.iter().position(|&byte| byte == b'\n');
+                self.position += end.map_or(rest.len(), |end| end + 1);
+                return Some(Err(MerkleError::BadLength {
+                    line: self.line,
+                    got: end.unwrap_or(rest.len()),
+                    want: width,
+                }));
+            }
+        };
+        self.position += width + 1;
+        Some(match str::from_utf8(line) {
+            Ok(str) => self.encoding.decode(str, self.line),
+            Err(_) => Err(MerkleError::BadUtf8 { line: self.line }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SourceReader;
+    use crate::test_fixtures::fixture;
+
+    const LEAVES: &str = "\
+77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053
+915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723
+7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646
+";
+
+    fn read_all(file: String) -> Result<Vec<Hash>, MerkleError> {
+        MmapSourceReader::new(file).unwrap().collect()
+    }
+
+    #[test]
+    fn same_as_source_reader() {
+        let file = fixture("mmap", "leaves.hex", LEAVES);
+        let expected: Vec<Hash> = SourceReader::new(file.clone())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(expected, read_all(file.clone()).unwrap());
+        assert_eq!(3, MmapSourceReader::new(file).unwrap().len());
+
+        let file = fixture("mmap", "no_final_newline.hex", &LEAVES[..LEAVES.len() - 1]);
+        assert_eq!(expected, read_all(file).unwrap());
+
+        let file = fixture("mmap", "empty.hex", "");
+        assert!(MmapSourceReader::new(file.clone()).unwrap().is_empty());
+        assert!(read_all(file).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_lines() {
+        let contents = LEAVES.replacen('\n', "0\n", 1);
+        let mut reader = MmapSourceReader::new(fixture("mmap", "long.hex", &contents)).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::BadLength {
+                line: 1,
+                got: 65,
+                want: 64
+            }))
+        ));
+        // the reader ends on the broken line, unless asked to move on past it
+        assert!(reader.next().is_none());
+        let reader = MmapSourceReader::new(fixture("mmap", "long.hex", &contents)).unwrap();
+        assert_eq!(
+            2,
+            reader.with_keep_going(true).filter(Result::is_ok).count()
+        );
+
+        let file = fixture("mmap", "short_final_line.hex", &LEAVES[..LEAVES.len() - 2]);
+        assert!(matches!(
+            read_all(file),
+            Err(MerkleError::BadLength {
+                line: 3,
+                got: 63,
+                want: 64
+            })
+        ));
+
+        let file = fixture("mmap", "empty_trailing_line.hex", format!("{LEAVES}\n"));
+        assert!(matches!(
+            read_all(file),
+            Err(MerkleError::BadLength {
+                line: 4,
+                got: 0,
+                want: 64
+            })
+        ));
+
+        let contents = LEAVES.replacen("77", "zz", 1);
+        assert!(matches!(
+            read_all(fixture("mmap", "bad_hex.hex", &contents)),
+            Err(MerkleError::BadHex { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,146 @@
+//!
+//! Implements lightweight timers for the stages of the calculation.
+//!
+//! The time spent reading and decoding the leaves is measured by wrapping the
+//! leaf iterator with [`Timed`], the rest of the calculation is attributed to
+//! hashing. The report is a small table, one stage per line
+//!
+//! read           1.234 ms
+//! hash          12.345 ms
+//! level 1        8.000 ms
+//! total         13.579 ms
+
+use std::cell::Cell;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+///
+/// The iterator adapter, accumulating the time spent in the `next` calls of
+/// the wrapped iterator.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::profile::Timed;
+/// use std::cell::Cell;
+/// use std::time::Duration;
+///
+/// let elapsed = Cell::new(Duration::ZERO);
+/// let leaves = vec![[0u8; 32]; 3].into_iter();
+///
+/// assert_eq!(3, Timed::new(leaves, &elapsed).count());
+/// ```
+pub struct Timed<'a, I> {
+    inner: I,
+    elapsed: &'a Cell<Duration>,
+}
+
+impl<'a, I> Timed<'a, I> {
+    pub fn new(inner: I, elapsed: &'a Cell<Duration>) -> Self {
+        Self { inner, elapsed }
+    }
+}
+
+impl<I: Iterator> Iterator for Timed<'_, I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let item = self.inner.next();
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+///
+/// The report of the time spent in each stage of the calculation.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::profile::Profile;
+/// use std::time::Duration;
+///
+/// let mut profile = Profile::new();
+/// profile.record("read", Duration::from_millis(2));
+/// let mut out = Vec::new();
+/// profile.write(&mut out).unwrap();
+///
+/// assert!(String::from_utf8(out).unwrap().starts_with("read           2.000 ms\n"));
+/// ```
+#[derive(Debug)]
+pub struct Profile {
+    start: Instant,
+    stages: Vec<(String, Duration)>,
+}
+
+impl Profile {
+    ///
+    /// Creates an empty report, starting the total time clock.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            stages: Vec::new(),
+        }
+    }
+
+    ///
+    /// Records the time spent in the stage.
+    pub fn record(&mut self, stage: impl Into<String>, duration: Duration) {
+        self.stages.push((stage.into(), duration));
+    }
+
+    ///
+    /// Returns the time elapsed since the report was created.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    ///
+    /// Writes the stages followed by the total time, one per line.
+    pub fn write<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        let total = ("total".to_string(), self.elapsed());
+        for (stage, duration) in self.stages.iter().chain([&total]) {
+            let millis = duration.as_secs_f64() * 1000.0;
+            writeln!(out, "{stage:<10}{millis:>10.3} ms")?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timed() {
+        let elapsed = Cell::new(Duration::ZERO);
+        let leaves = (0..3).inspect(|_| std::thread::sleep(Duration::from_millis(1)));
+        assert_eq!(3, Timed::new(leaves, &elapsed).count());
+        assert!(elapsed.get() >= Duration::from_millis(3));
+    }
+
+    #[test]
+    fn report() {
+        let mut profile = Profile::new();
+        profile.record("read", Duration::from_micros(1500));
+        profile.record("level 1", Duration::from_secs(2));
+        let mut out = Vec::new();
+        profile.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("read           1.500 ms", lines[0]);
+        assert_eq!("level 1     2000.000 ms", lines[1]);
+        assert!(lines[2].starts_with("total "));
+    }
+}
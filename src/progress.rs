@@ -0,0 +1,160 @@
+//!
+//! Implements a progress indicator for the leaves being read from the source.
+//!
+//! The indicator is a spinner followed by the number of leaves read so far
+//! (and the percentage, if the total number of leaves is known). It is meant
+//! to be rendered to a terminal only: when the output is redirected, the
+//! indicator stays silent, so it never pollutes piped output.
+
+use std::io::{IsTerminal, Stderr, Write};
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+///
+/// Default number of leaves between two consecutive progress updates.
+pub const DEFAULT_STEP: usize = 4096;
+
+///
+/// The iterator adapter, rendering the progress of the wrapped iterator.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::progress::Progress;
+///
+/// let mut out = Vec::new();
+/// let leaves = vec![[0u8; 32]; 3].into_iter();
+/// let count = Progress::new(leaves, &mut out, false).count();
+///
+/// assert_eq!(3, count);
+/// assert!(out.is_empty()); // not a terminal, nothing rendered
+/// ```
+pub struct Progress<I, W: Write> {
+    inner: I,
+    out: W,
+    enabled: bool,
+    step: usize,
+    count: usize,
+    total: Option<usize>,
+}
+
+impl<I> Progress<I, Stderr> {
+    ///
+    /// Creates a progress adapter rendering to stderr, enabled only if stderr
+    /// is a terminal.
+    pub fn stderr(inner: I) -> Self {
+        let out = std::io::stderr();
+        let enabled = out.is_terminal();
+        Self::new(inner, out, enabled)
+    }
+}
+
+impl<I, W: Write> Progress<I, W> {
+    ///
+    /// Creates a progress adapter rendering to `out`. If `enabled` is false,
+    /// nothing is ever written to `out`.
+    pub fn new(inner: I, out: W, enabled: bool) -> Self {
+        Self {
+            inner,
+            out,
+            enabled,
+            step: DEFAULT_STEP,
+            count: 0,
+            total: None,
+        }
+    }
+
+    ///
+    /// Sets the expected total number of leaves, enabling percentage output.
+    pub fn with_total(mut self, total: usize) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    ///
+    /// Sets the number of leaves between two consecutive progress updates.
+    pub fn with_step(mut self, step: usize) -> Self {
+        self.step = step.max(1);
+        self
+    }
+
+    fn render(&mut self, done: bool) {
+        let spinner = match done {
+            true => ' ',
+            false => SPINNER[(self.count / self.step) % SPINNER.len()],
+        };
+        // progress output is best-effort, write errors are ignored
+        let _ = match self.total {
+            Some(total) if total > 0 => write!(
+                self.out,
+                "\r{spinner} {} leaves ({}%)",
+                self.count,
+                (self.count * 100 / total).min(100)
+            ),
+            _ => write!(self.out, "\r{spinner} {} leaves", self.count),
+        };
+        if done {
+            let _ = writeln!(self.out);
+        }
+        let _ = self.out.flush();
+    }
+}
+
+impl<I: Iterator, W: Write> Iterator for Progress<I, W> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next();
+        if !self.enabled {
+            return item;
+        }
+        match item {
+            Some(_) => {
+                self.count += 1;
+                if self.count.is_multiple_of(self.step) {
+                    self.render(false);
+                }
+            }
+            None => {
+                // render the final state only once
+                self.render(true);
+                self.enabled = false;
+            }
+        }
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_when_not_terminal() {
+        let mut out = Vec::new();
+        let leaves = vec![[0u8; 32]; 10].into_iter();
+        let count = Progress::new(leaves, &mut out, false)
+            .with_total(10)
+            .with_step(1)
+            .count();
+        assert_eq!(10, count);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn renders_when_terminal() {
+        let mut out = Vec::new();
+        let leaves = vec![[0u8; 32]; 4].into_iter();
+        let count = Progress::new(leaves, &mut out, true)
+            .with_total(4)
+            .with_step(2)
+            .count();
+        assert_eq!(4, count);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("2 leaves (50%)"));
+        assert!(out.ends_with("4 leaves (100%)\n"));
+    }
+}
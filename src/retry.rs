@@ -0,0 +1,219 @@
+//!
+//! Implements the retries of the transient I/O errors of the input.
+//!
+//! The network-backed sources (sockets, network filesystems) may fail a read
+//! with the error that goes away on its own. [`Retrying`] wraps any reader and
+//! repeats the failed read according to the [`RetryPolicy`], sleeping between
+//! the attempts, so the whole calculation is not aborted by the single hiccup.
+//!
+//! Only the `Interrupted`, `WouldBlock` and `TimedOut` errors are retried, any
+//! other error is returned at once. A failed read consumes no input, so the
+//! repeated read continues exactly where the failed one started.
+
+use std::io::{BufRead, Error, ErrorKind, Read};
+use std::thread;
+use std::time::Duration;
+
+///
+/// The policy of retrying the transient I/O errors.
+///
+/// The backoff is doubled after each failed attempt, up to the maximum.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::retry::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(3).with_backoff(Duration::from_millis(1));
+///
+/// assert_eq!(3, policy.max_attempts());
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    ///
+    /// Creates the policy making at most `max_attempts` attempts of each read,
+    /// the first one included, with the default backoff.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+
+    ///
+    /// Sets the sleep before the first retry.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    ///
+    /// Sets the upper bound of the sleep between the retries.
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    ///
+    /// Returns the maximum number of attempts of each read.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    ///
+    /// Checks whether the error is transient and worth retrying.
+    pub fn is_retryable(error: &Error) -> bool {
+        matches!(
+            error.kind(),
+            ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+        )
+    }
+
+    ///
+    /// Runs the operation, retrying it on the transient errors.
+    fn run<T, O>(&self, mut operation: O) -> Result<T, Error>
+    where
+        O: FnMut() -> Result<T, Error>,
+    {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(error) if Self::is_retryable(&error) && attempt < self.max_attempts => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+///
+/// The reader, retrying the transient errors of the wrapped one.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::retry::{RetryPolicy, Retrying};
+/// use merkle_root::source::RecordReader;
+///
+/// let bytes = [[0u8; 32], [1u8; 32]].concat();
+/// let mut reader = RecordReader::new(Retrying::new(bytes.as_slice(), RetryPolicy::default()));
+///
+/// assert_eq!([0u8; 32], reader.next().unwrap().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct Retrying<R> {
+    inner: R,
+    policy: RetryPolicy,
+}
+
+impl<R> Retrying<R> {
+    pub fn new(inner: R, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    ///
+    /// Returns the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Retrying<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let inner = &mut self.inner;
+        self.policy.run(|| inner.read(buf))
+    }
+}
+
+impl<R: BufRead> BufRead for Retrying<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let inner = &mut self.inner;
+        self.policy.run(|| inner.fill_buf().map(|_| ()))?;
+        // the buffer is filled, so this call does no I/O
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.inner.consume(amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MerkleError;
+    use crate::source::RecordReader;
+
+    ///
+    /// The reader failing with the given errors before reading the data.
+    struct Flaky {
+        errors: Vec<ErrorKind>,
+        data: &'static [u8],
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.errors.pop() {
+                Some(kind) => Err(Error::from(kind)),
+                None => self.data.read(buf),
+            }
+        }
+    }
+
+    fn read(errors: Vec<ErrorKind>, policy: RetryPolicy) -> Result<Vec<u8>, MerkleError> {
+        let data: &[u8] = &[7u8; 32];
+        let reader = Retrying::new(Flaky { errors, data }, policy);
+        let leaves: Vec<_> = RecordReader::new(reader).collect::<Result<_, _>>()?;
+        Ok(leaves.concat())
+    }
+
+    #[test]
+    fn retried() {
+        let policy = RetryPolicy::new(3).with_backoff(Duration::ZERO);
+        let errors = vec![ErrorKind::TimedOut, ErrorKind::WouldBlock];
+        assert_eq!(vec![7u8; 32], read(errors, policy).unwrap());
+
+        let errors = vec![ErrorKind::TimedOut; 3];
+        assert!(matches!(
+            read(errors, policy),
+            Err(MerkleError::Io(error)) if error.kind() == ErrorKind::TimedOut
+        ));
+
+        let errors = vec![ErrorKind::PermissionDenied];
+        assert!(matches!(
+            read(errors, policy),
+            Err(MerkleError::Io(error)) if error.kind() == ErrorKind::PermissionDenied
+        ));
+    }
+
+    #[test]
+    fn buffered() {
+        let flaky = Flaky {
+            errors: vec![ErrorKind::WouldBlock],
+            data: b"line\n",
+        };
+        let policy = RetryPolicy::new(2).with_backoff(Duration::ZERO);
+        let mut reader = Retrying::new(std::io::BufReader::new(flaky), policy);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!("line\n", line);
+    }
+}
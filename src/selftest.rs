@@ -0,0 +1,149 @@
+//!
+//! Implements the known-answer tests of the merkle tree root calculation.
+//!
+//! Each vector is the list of base16 leaves along with the hash function and
+//! the expected root, calculated independently of this crate. The vectors are
+//! run with every algorithm, so a broken hash backend or tree logic shows up
+//! as a mismatch.
+
+use crate::calc::{decode_hex, hash, hash_rfc6962, hash_sha256d, Mode};
+use crate::hex::HexHash;
+use crate::Hash;
+use sha2::{Digest, Sha256};
+
+const ZERO: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+///
+/// The known-answer test vector.
+#[derive(Debug, Copy, Clone)]
+pub struct Vector {
+    pub name: &'static str,
+    pub leaves: &'static [&'static str],
+    pub hash_fn: fn(&Hash, Option<&Hash>) -> Hash,
+    pub root: &'static str,
+}
+
+///
+/// The built-in known-answer test vectors.
+pub const VECTORS: &[Vector] = &[
+    Vector {
+        name: "single leaf",
+        leaves: &[ZERO],
+        hash_fn: hash,
+        root: ZERO,
+    },
+    Vector {
+        name: "full tree of 2 zero leaves",
+        leaves: &[ZERO, ZERO],
+        hash_fn: hash,
+        root: "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b",
+    },
+    Vector {
+        name: "full tree of 4 zero leaves",
+        leaves: &[ZERO, ZERO, ZERO, ZERO],
+        hash_fn: hash,
+        root: "db56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71",
+    },
+    Vector {
+        name: "partial tree of 3 zero leaves",
+        leaves: &[ZERO, ZERO, ZERO],
+        hash_fn: hash,
+        root: "db56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71",
+    },
+    Vector {
+        name: "full tree of 8 zero leaves",
+        leaves: &[ZERO, ZERO, ZERO, ZERO, ZERO, ZERO, ZERO, ZERO],
+        hash_fn: hash,
+        root: "c78009fdf07fc56a11f122370658a353aaa542ed63e44c4bc15ff4cd105ab33c",
+    },
+    Vector {
+        name: "partial tree of 3 leaves",
+        leaves: &[
+            "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053",
+            "915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723",
+            "7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646",
+        ],
+        hash_fn: hash,
+        root: "f635de0509854cee47184a166eba580b37f9c64ad7e1bfe9c74cd3bb17119cdb",
+    },
+    Vector {
+        // the leaf hashes of the first 3 entries of the Certificate
+        // Transparency reference test vectors
+        name: "RFC 6962 tree of 3 leaves",
+        leaves: &[
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "0298d122906dcfc10892cb53a73992fc5b9f493ea4c9badb27b791b4127a7fe7",
+        ],
+        hash_fn: hash_rfc6962,
+        root: "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+    },
+    Vector {
+        // the transaction ids and the merkle root in the internal byte order,
+        // reversed from the displayed one
+        name: "Bitcoin block 100000",
+        leaves: &[
+            "876dd0a3ef4a2816ffd1c12ab649825a958b0ff3bb3d6f3e1250f13ddbf0148c",
+            "c40297f730dd7b5a99567eb8d27b78758f607507c52292d02d4031895b52f2ff",
+            "c46e239ab7d28e2c019b6d66ad8fae98a56ef1f21aeecb94d1b1718186f05963",
+            "1d0cb83721529a062d9675b98d6e5c587e4a770fc84ed00abc5a5de04568a6e9",
+        ],
+        hash_fn: hash_sha256d,
+        root: "6657a9252aacd5c0b2940996ecff952228c3067cc38d4885efb5a4ac4247e9f3",
+    },
+];
+
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+///
+/// The outcome of the single known-answer test.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub name: String,
+    pub passed: bool,
+}
+
+///
+/// Runs the known-answer tests: the sha256 digest of the empty input, and
+/// every vector with every algorithm.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::selftest;
+///
+/// assert!(selftest::run().iter().all(|outcome| outcome.passed));
+/// ```
+pub fn run() -> Vec<Outcome> {
+    let mut outcomes = vec![Outcome {
+        name: "sha256 of the empty input".to_string(),
+        passed: encode(&Sha256::digest([]).into()) == EMPTY_SHA256,
+    }];
+    for vector in VECTORS {
+        for (mode, mode_name) in [
+            (Mode::DepthWalk, "depth-walk"),
+            (Mode::WidthWalk, "width-walk"),
+            (Mode::ParallelShards, "parallel-shards"),
+            (Mode::Auto, "auto"),
+        ] {
+            let root = vector
+                .leaves
+                .iter()
+                .enumerate()
+                .map(|(index, leaf)| decode_hex(leaf, index + 1))
+                .collect::<Result<Vec<Hash>, _>>()
+                .and_then(|leaves| {
+                    mode.try_calculate(&mut leaves.into_iter().peekable(), &vector.hash_fn)
+                });
+            outcomes.push(Outcome {
+                name: format!("{} ({mode_name})", vector.name),
+                passed: matches!(root, Ok(root) if encode(&root) == vector.root),
+            });
+        }
+    }
+    outcomes
+}
+
+fn encode(hash: &Hash) -> String {
+    HexHash(*hash).to_string()
+}
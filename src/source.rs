@@ -1,13 +1,198 @@
+pub use crate::calc::decode_hex;
+use crate::compression::{Compression, Input};
+use crate::error::MerkleError;
 use crate::Hash;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
 use std::io::Error;
+use std::io::SeekFrom;
 use std::str;
 
+///
+/// Encoding of the hashes in the input file.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    /// Base16 of any case, 64 characters per hash
+    #[default]
+    Base16,
+    /// Standard padded base64, 44 characters per hash
+    Base64,
+}
+
+impl Encoding {
+    ///
+    /// Number of characters a single encoded hash occupies.
+    pub fn width(&self) -> usize {
+        self.width_of(32)
+    }
+
+    ///
+    /// Number of characters a single encoded hash of the given number of bytes
+    /// occupies.
+    pub fn width_of(&self, bytes: usize) -> usize {
+        match self {
+            Encoding::Base16 => bytes * 2,
+            Encoding::Base64 => bytes.div_ceil(3) * 4,
+        }
+    }
+
+    ///
+    /// Decodes the hash of `N` bytes, found on the given line (1-based) of the
+    /// source.
+    pub(crate) fn decode<const N: usize>(
+        &self,
+        str: &str,
+        line: usize,
+    ) -> Result<[u8; N], MerkleError> {
+        let mut byte_buf = [0u8; N];
+        match self {
+            Encoding::Base16 => {
+                base16ct::mixed::decode(str, &mut byte_buf).map_err(|_| MerkleError::BadHex {
+                    line,
+                    content: str.to_string(),
+                })?;
+            }
+            Encoding::Base64 => {
+                byte_buf = BASE64
+                    .decode(str)
+                    .ok()
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .ok_or_else(|| MerkleError::BadBase64 {
+                        line,
+                        content: str.to_string(),
+                    })?;
+            }
+        }
+        Ok(byte_buf)
+    }
+
+    ///
+    /// Decodes the hash of `N` bytes off the line (1-based) read along with its
+    /// line ending, checking the length of the line first.
+    pub(crate) fn decode_line<const N: usize>(
+        &self,
+        buf: &[u8],
+        line: usize,
+    ) -> Result<[u8; N], MerkleError> {
+        // the final line may lack the newline, but not the characters
+        let trimmed = trim_line_ending(buf);
+        let width = self.width_of(N);
+        if trimmed.len() != width {
+            return Err(MerkleError::BadLength {
+                line,
+                got: trimmed.len(),
+                want: width,
+            });
+        }
+        match str::from_utf8(trimmed) {
+            Ok(str) => self.decode(str, line),
+            Err(_) => Err(MerkleError::BadUtf8 { line }),
+        }
+    }
+}
+
+///
+/// Separator of the hashes in the input file.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Separator {
+    /// One hash per line, the lines ending with '\n' or "\r\n"
+    #[default]
+    Newline,
+    /// Any run of the ASCII whitespace: spaces, tabs and newlines, so a line
+    /// may hold several hashes
+    Whitespace,
+}
+
+///
+/// Decodes the base16 lines of any origin lazily with [`decode_hex`], yielding
+/// the hash or the error for each line. Errors carry the line number
+/// (1-based) of the offending item. Wrap the result with [`UntilError`] to feed
+/// it to the calculation algorithms.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::source::hex_lines_to_hashes;
+///
+/// let lines = vec!["00".repeat(32), "zz".to_string()];
+/// let hashes: Vec<_> = hex_lines_to_hashes(lines.into_iter()).collect();
+///
+/// assert_eq!([0u8; 32], *hashes[0].as_ref().unwrap());
+/// assert!(hashes[1].is_err());
+/// ```
+pub fn hex_lines_to_hashes<I, S>(iter: I) -> impl Iterator<Item = Result<Hash, MerkleError>>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    iter.enumerate()
+        .map(|(index, line)| decode_hex(line.as_ref(), index + 1))
+}
+
+///
+/// Default capacity of the buffer of [`SourceReader`], in bytes.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
+
+///
+/// Default maximum length of a line in bytes, see
+/// [`SourceReader::with_max_line_bytes`].
+pub const DEFAULT_MAX_LINE_BYTES: usize = 64 * 1024;
+
+///
+/// Reads the line (up to and including the newline) into `buf`, failing with
+/// [`MerkleError::LineTooLong`] once the line exceeds `max` bytes, instead of
+/// buffering it unboundedly. Returns the number of bytes read, 0 meaning the
+/// end of the source.
+pub fn read_line_bounded<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+    line: usize,
+) -> Result<usize, MerkleError> {
+    let mut read = 0;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(read);
+        }
+        let (chunk, done) = match available.iter().position(|byte| *byte == b'\n') {
+            Some(newline) => (&available[..=newline], true),
+            None => (available, false),
+        };
+        let len = chunk.len();
+        if read + len > max {
+            return Err(MerkleError::LineTooLong { line });
+        }
+        buf.extend_from_slice(chunk);
+        reader.consume(len);
+        read += len;
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct SourceReader {
-    reader: BufReader<File>,
+pub struct SourceReader<const N: usize = 32> {
+    reader: Input,
+    encoding: Encoding,
+    separator: Separator,
+    max_line_bytes: usize,
+    skip_comments: bool,
+    keep_going: bool,
+    // set once the iteration ended on an error
+    fused: bool,
+    // the line being read, reused across the lines
+    buf: Vec<u8>,
+    // number of lines read so far, including the header
+    line: usize,
+    // bytes left in the plain file, unknown for the streams
+    remaining: Option<u64>,
+    // set once seeked, the lines must end with '\n' alone
+    seeked: bool,
 }
 
 ///
@@ -19,8 +204,19 @@ pub struct SourceReader {
 /// Assumptions:
 /// - A hash is a 64 bytes long ASCII string
 /// - A hash is a base16 string
-/// - A hash is a lowercase string
-/// - Hashes are separated by newlines ('\n')
+/// - A hash is a lowercase, uppercase or mixed-case string
+/// - Hashes are separated by newlines ('\n' or "\r\n")
+///
+/// Seeking relies on the fixed line length, so it requires the '\n'
+/// separators: the "\r\n" ones fail with [`MerkleError::CrlfLine`].
+///
+/// The base64 encoding (see [`Encoding`]) replaces the first three assumptions
+/// with a 44 bytes long padded base64 string.
+///
+/// The compressed files are decompressed on the fly, see [`crate::compression`].
+///
+/// The hashes of other sizes than 32 bytes, e.g. sha512, are read by the
+/// reader of the hash size `N`, see [`SourceReader::into_width`].
 ///
 /// Implements Iterator trait <...>
 ///
@@ -30,39 +226,1872 @@ pub struct SourceReader {
 impl SourceReader {
     ///
     /// Creates a new input file reader with the BufReader of default buffer
-    /// size. To tune the buffer size, use [`with_buffer_capacity`].
+    /// size. To tune the buffer size, use
+    /// [`SourceReader::with_buffer_capacity`]. The
+    /// [`STDIN`](crate::compression::STDIN) filename reads the standard input.
     pub fn new(filename: String) -> Result<Self, Error> {
-        let file = File::open(filename)?;
-        Ok(Self {
-            reader: BufReader::new(file),
-        })
+        Self::with_buffer_capacity(DEFAULT_BUFFER_CAPACITY, filename)
     }
 
     ///
     /// Creates a new input file reader with the BufReader of specified buffer
     /// size.
     pub fn with_buffer_capacity(capacity: usize, filename: String) -> Result<Self, Error> {
-        let file = File::open(filename)?;
-        Ok(Self {
-            reader: BufReader::with_capacity(capacity, file),
+        let input = Input::open(filename, capacity, Compression::Auto)?;
+        Ok(Self::from_input(input))
+    }
+
+    ///
+    /// Creates a new input file reader with the BufReader of default buffer
+    /// size, decompressing the file with the specified compression instead of
+    /// detecting it.
+    pub fn with_compression(compression: Compression, filename: String) -> Result<Self, Error> {
+        let input = Input::open(filename, DEFAULT_BUFFER_CAPACITY, compression)?;
+        Ok(Self::from_input(input))
+    }
+
+    ///
+    /// Creates a new reader of any buffered reader, e.g. a `Cursor` over the
+    /// hashes already in memory. The reader can not be seeked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::source::SourceReader;
+    /// use std::io::Cursor;
+    ///
+    /// let hashes = format!("{}\n{}\n", "00".repeat(32), "01".repeat(32));
+    /// let mut reader = SourceReader::from_reader(Cursor::new(hashes));
+    ///
+    /// assert_eq!([1u8; 32], reader.nth(1).unwrap().unwrap());
+    /// ```
+    pub fn from_reader<R: BufRead + Send + 'static>(reader: R) -> Self {
+        Self::from_input(Input::Reader(Box::new(reader)))
+    }
+
+    ///
+    /// Creates a new reader of the already opened input.
+    pub fn from_input(mut input: Input) -> Self {
+        Self {
+            remaining: remaining_bytes(&mut input),
+            reader: input,
+            encoding: Encoding::default(),
+            separator: Separator::default(),
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            skip_comments: false,
+            keep_going: false,
+            fused: false,
+            buf: Vec::with_capacity(Encoding::Base16.width() + 2),
+            line: 0,
+            seeked: false,
+        }
+    }
+
+    ///
+    /// Creates a new input file reader with the BufReader of default buffer
+    /// size, decoding the hashes with the specified encoding.
+    pub fn with_encoding(encoding: Encoding, filename: String) -> Result<Self, Error> {
+        let mut reader = Self::new(filename)?;
+        reader.encoding = encoding;
+        Ok(reader)
+    }
+}
+
+impl<const N: usize> SourceReader<N> {
+    ///
+    /// Turns the reader into the reader of the hashes of `M` bytes, e.g. 64
+    /// for sha512, the lines being `2 * M` base16 characters long.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::source::SourceReader;
+    ///
+    /// let path = std::env::temp_dir().join("into_width.hex");
+    /// std::fs::write(&path, format!("{}\n", "01".repeat(64))).unwrap();
+    /// let mut reader = SourceReader::new(path.to_string_lossy().into_owned())
+    ///     .unwrap()
+    ///     .into_width::<64>();
+    ///
+    /// assert_eq!([1u8; 64], reader.next().unwrap().unwrap());
+    /// ```
+    pub fn into_width<const M: usize>(self) -> SourceReader<M> {
+        SourceReader {
+            reader: self.reader,
+            encoding: self.encoding,
+            separator: self.separator,
+            max_line_bytes: self.max_line_bytes,
+            skip_comments: self.skip_comments,
+            keep_going: self.keep_going,
+            fused: self.fused,
+            buf: self.buf,
+            line: self.line,
+            remaining: self.remaining,
+            seeked: self.seeked,
+        }
+    }
+
+    ///
+    /// Returns the underlying input, positioned past the leaves read so far.
+    pub fn into_input(self) -> Input {
+        self.reader
+    }
+
+    ///
+    /// Moves the reader to the leaf with the given index, counting from the
+    /// start of the file. Fails with [`MerkleError::CrlfLine`] on the "\r\n"
+    /// line endings, see [`SourceReader::seek_offset`].
+    pub fn seek_leaf(&mut self, index: usize) -> Result<(), MerkleError> {
+        let stride = self.encoding.width_of(N) as u64 + 1; // accomodate for newline
+        self.seek_offset(index as u64 * stride).map(|_| ())
+    }
+
+    ///
+    /// Moves the reader to the given byte offset, counting from the start of
+    /// the file, returning the index of the leaf at the offset. The offset
+    /// must point to the start of a line, otherwise
+    /// [`MerkleError::MisalignedOffset`] is returned.
+    ///
+    /// The offsets are counted in the lines ending with '\n': if the first
+    /// line ends with "\r\n", [`MerkleError::CrlfLine`] is returned, and so
+    /// is it for every such line read past the offset.
+    pub fn seek_offset(&mut self, offset: u64) -> Result<usize, MerkleError> {
+        let stride = self.encoding.width_of(N) as u64 + 1; // accomodate for newline
+        if !offset.is_multiple_of(stride) {
+            return Err(MerkleError::MisalignedOffset { offset });
+        }
+        // the first line tells the line endings of the file
+        let mut ending = Vec::with_capacity(2);
+        self.reader.seek(SeekFrom::Start(stride - 1))?;
+        (&mut self.reader).take(2).read_to_end(&mut ending)?;
+        if ending == b"\r\n" {
+            return Err(MerkleError::CrlfLine { line: 1 });
+        }
+        if offset > 0 {
+            // the previous line must end right before the offset
+            let mut newline = [0u8; 1];
+            self.reader.seek(SeekFrom::Start(offset - 1))?;
+            self.reader.read_exact(&mut newline)?;
+            if newline[0] != b'\n' {
+                return Err(MerkleError::MisalignedOffset { offset });
+            }
+        }
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.line = (offset / stride) as usize;
+        self.remaining = remaining_bytes(&mut self.reader);
+        self.seeked = true;
+        Ok(self.line)
+    }
+
+    ///
+    /// Returns the number of leaves of the plain file, derived from its size,
+    /// the last line possibly missing its newline, and moves the reader to
+    /// the start of the file. Fails on the streams, which can not be seeked,
+    /// and with [`MerkleError::CrlfLine`] on the "\r\n" line endings.
+    pub fn seekable_leaves(&mut self) -> Result<usize, MerkleError> {
+        let stride = self.encoding.width_of(N) as u64 + 1; // accomodate for newline
+        self.seek_offset(0)?;
+        let len = self.remaining.unwrap_or_default();
+        Ok(len.div_ceil(stride) as usize)
+    }
+
+    ///
+    /// Reads the header line, declaring the number of leaves in the file. Must
+    /// be called before any hash is read. Use [`Counted`] to validate the
+    /// number of leaves read afterwards.
+    pub fn read_header(&mut self) -> Result<usize, MerkleError> {
+        let mut header = Vec::new();
+        let read = read_line_bounded(&mut self.reader, &mut header, self.max_line_bytes, 1)?;
+        self.consumed(read);
+        self.line = 1;
+        let header = String::from_utf8_lossy(&header);
+        header.trim().parse().map_err(|_| MerkleError::BadHeader {
+            header: header.into_owned(),
         })
     }
+
+    ///
+    /// Sets the separator of the hashes. With [`Separator::Whitespace`], the
+    /// source is split into the tokens rather than the lines: each token is
+    /// decoded as a hash, the errors naming the line the token is on, and the
+    /// comments run from a token starting with `#` to the end of the line.
+    /// The hashes are no longer at the fixed stride the seeking relies on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::source::{Separator, SourceReader};
+    ///
+    /// let hashes = format!("{}\t{}\n  {}\n", "00".repeat(32), "01".repeat(32), "02".repeat(32));
+    /// let reader = SourceReader::from_reader(std::io::Cursor::new(hashes))
+    ///     .with_separator(Separator::Whitespace);
+    ///
+    /// assert_eq!(3, reader.filter(Result::is_ok).count());
+    /// ```
+    pub fn with_separator(mut self, separator: Separator) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    ///
+    /// Sets the maximum length of a line in bytes, or of a token with
+    /// [`Separator::Whitespace`]. Reading a longer one fails with
+    /// [`MerkleError::LineTooLong`].
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = max_line_bytes;
+        self
+    }
+
+    ///
+    /// Sets whether the blank lines and the comment lines, starting with `#`
+    /// after the leading whitespace, are skipped rather than failing to
+    /// decode. The skipped lines still count in the line numbers of the
+    /// errors, but break the fixed line length the seeking relies on.
+    pub fn with_skip_comments(mut self, skip_comments: bool) -> Self {
+        self.skip_comments = skip_comments;
+        self
+    }
+
+    ///
+    /// Sets whether the reader moves on past the lines that fail to decode, so
+    /// every bad line of the file is reported. By default, the reader yields
+    /// the first error and then ends, not reading the rest of the file. The
+    /// read errors and the overlong lines end the iteration regardless: the
+    /// rest of the line is left unread, so the next one can not be found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use merkle_root::source::SourceReader;
+    ///
+    /// let input = format!("abc\nxyz\n{}\n", "00".repeat(32));
+    /// let reader = || SourceReader::from_reader(std::io::Cursor::new(input.clone()));
+    ///
+    /// assert_eq!(1, reader().count());
+    /// assert_eq!(3, reader().with_keep_going(true).count());
+    /// ```
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
 }
 
-impl Iterator for SourceReader {
+impl<const N: usize> Iterator for SourceReader<N> {
+    type Item = Result<[u8; N], MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fused {
+            return None;
+        }
+        let leaf = self.read_leaf()?;
+        if let Err(error) = &leaf {
+            let framing = matches!(error, MerkleError::Io(_) | MerkleError::LineTooLong { .. });
+            self.fused = framing || !self.keep_going;
+        }
+        Some(leaf)
+    }
+
+    ///
+    /// The upper bound is derived from the size of the plain file: each leaf
+    /// takes a full line, and the reader ends on the first error. The bound
+    /// is unknown for the streams and for the reader moving on past the bad
+    /// lines, which may be as short as a byte.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match (self.fused, self.remaining) {
+            (true, _) | (_, Some(0)) => (0, Some(0)),
+            (false, Some(_)) if self.keep_going => (0, None),
+            (false, Some(remaining)) => {
+                let leaves = remaining / self.encoding.width_of(N) as u64;
+                (0, usize::try_from(leaves + 1).ok())
+            }
+            (false, None) => (0, None),
+        }
+    }
+}
+
+impl<const N: usize> SourceReader<N> {
+    ///
+    /// Reads and decodes the next line, skipping the comments if asked to.
+    fn read_leaf(&mut self) -> Option<Result<[u8; N], MerkleError>> {
+        if self.separator == Separator::Whitespace {
+            return self.read_token();
+        }
+        loop {
+            self.buf.clear();
+            self.line += 1;
+            match read_line_bounded(
+                &mut self.reader,
+                &mut self.buf,
+                self.max_line_bytes,
+                self.line,
+            ) {
+                Ok(0) => return None,
+                Ok(read) => self.consumed(read),
+                Err(error) => return Some(Err(error)),
+            }
+            if !(self.skip_comments && is_comment(&self.buf)) {
+                break;
+            }
+        }
+        if self.seeked && self.buf.ends_with(b"\r\n") {
+            return Some(Err(MerkleError::CrlfLine { line: self.line }));
+        }
+        Some(self.encoding.decode_line(&self.buf, self.line))
+    }
+
+    ///
+    /// Reads and decodes the next token, the line numbers counting the
+    /// newlines passed.
+    fn read_token(&mut self) -> Option<Result<[u8; N], MerkleError>> {
+        self.buf.clear();
+        match read_token_bounded(
+            &mut self.reader,
+            &mut self.buf,
+            self.max_line_bytes,
+            self.skip_comments,
+            &mut self.line,
+        ) {
+            Ok(read) => self.consumed(read),
+            Err(error) => return Some(Err(error)),
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        // the separator ending the token is left unread
+        Some(self.encoding.decode_line(&self.buf, self.line + 1))
+    }
+
+    ///
+    /// Counts the bytes read off the plain file.
+    fn consumed(&mut self, read: usize) {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(read as u64);
+        }
+    }
+}
+
+///
+/// Reads the next token, separated by any ASCII whitespace, into `buf`,
+/// skipping the `#` comments to the end of the line if asked to, and adding
+/// the newlines passed to `lines`. The whitespace ending the token is left
+/// unread. Fails with [`MerkleError::LineTooLong`] once the token exceeds
+/// `max` bytes. Returns the number of bytes read, `buf` being empty at the end
+/// of the source.
+fn read_token_bounded<R: BufRead>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+    skip_comments: bool,
+    lines: &mut usize,
+) -> Result<usize, MerkleError> {
+    let mut read = 0;
+    let mut comment = false;
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(read);
+        }
+        let mut used = 0;
+        let mut done = false;
+        for &byte in available {
+            if byte.is_ascii_whitespace() && !buf.is_empty() {
+                done = true;
+                break;
+            }
+            used += 1;
+            match byte {
+                b'\n' => {
+                    *lines += 1;
+                    comment = false;
+                }
+                _ if comment || byte.is_ascii_whitespace() => {}
+                b'#' if skip_comments && buf.is_empty() => comment = true,
+                _ if buf.len() == max => return Err(MerkleError::LineTooLong { line: *lines + 1 }),
+                _ => buf.push(byte),
+            }
+        }
+        reader.consume(used);
+        read += used;
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+///
+/// Returns the number of bytes left past the position of the plain file,
+/// `None` for the streams.
+fn remaining_bytes(input: &mut Input) -> Option<u64> {
+    let Input::Plain(reader) = input else {
+        return None;
+    };
+    let len = reader.get_ref().metadata().ok()?.len();
+    let position = reader.stream_position().ok()?;
+    Some(len.saturating_sub(position))
+}
+
+///
+/// Checks whether the line is blank or a `#` comment.
+pub(crate) fn is_comment(line: &[u8]) -> bool {
+    matches!(line.trim_ascii_start().first(), None | Some(b'#'))
+}
+
+///
+/// Strips the trailing "\n" or "\r\n" off the line.
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+///
+/// The source of the leaves, the extension point of the ingestion layer.
+///
+/// Any iterator of fallible leaves is a leaf source, which covers the
+/// [`SourceReader`], [`RecordReader`], [`LenientReader`], [`JsonArrayReader`]
+/// and the iterator adapters of this module. A custom backend (e.g. a
+/// database cursor) implements
+/// [`LeafSource::next_leaf`] and is passed to
+/// [`root_from_source`](crate::calc::root_from_source).
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::calc::{hash, root_from_source, Mode};
+/// use merkle_root::error::MerkleError;
+/// use merkle_root::source::LeafSource;
+/// use merkle_root::Hash;
+///
+/// struct Countdown(u8);
+///
+/// impl LeafSource for Countdown {
+///     fn next_leaf(&mut self) -> Option<Result<Hash, MerkleError>> {
+///         self.0 = self.0.checked_sub(1)?;
+///         Some(Ok([self.0; 32]))
+///     }
+/// }
+///
+/// let root = root_from_source(Countdown(2), Mode::WidthWalk).unwrap();
+///
+/// assert_eq!(hash(&[1u8; 32], Some(&[0u8; 32])), root);
+/// ```
+pub trait LeafSource {
+    ///
+    /// Returns the next leaf, `None` once the source is exhausted.
+    fn next_leaf(&mut self) -> Option<Result<Hash, MerkleError>>;
+
+    ///
+    /// Turns the source into the iterator of its leaves.
+    fn leaves(self) -> Leaves<Self>
+    where
+        Self: Sized,
+    {
+        Leaves { source: self }
+    }
+}
+
+impl<I: Iterator<Item = Result<Hash, MerkleError>>> LeafSource for I {
+    fn next_leaf(&mut self) -> Option<Result<Hash, MerkleError>> {
+        self.next()
+    }
+}
+
+///
+/// The iterator over the leaves of the [`LeafSource`], see
+/// [`LeafSource::leaves`].
+#[derive(Debug)]
+pub struct Leaves<S> {
+    source: S,
+}
+
+impl<S: LeafSource> Iterator for Leaves<S> {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.next_leaf()
+    }
+}
+
+///
+/// Default number of records read from the underlying reader at once by the
+/// [`RecordReader`].
+pub const DEFAULT_BATCH_RECORDS: usize = 32 * 1024;
+
+const RECORD_SIZE: usize = std::mem::size_of::<Hash>();
+
+///
+/// The reader of binary input with the hashes packed as fixed-size 32 bytes
+/// records, without any separators.
+///
+/// Reads the records in large batches into its own buffer, so the number of
+/// read syscalls is amortized over [`DEFAULT_BATCH_RECORDS`] records, the same
+/// way BufReader does for the text input, without copying each record through
+/// the intermediate buffer of BufReader.
+///
+/// The trailing partial record fails with [`MerkleError::TruncatedRecord`].
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::source::RecordReader;
+///
+/// let bytes = [[0u8; 32], [1u8; 32]].concat();
+/// let mut reader = RecordReader::new(bytes.as_slice());
+///
+/// assert_eq!([0u8; 32], reader.next().unwrap().unwrap());
+/// assert_eq!(1, reader.records_read());
+/// assert_eq!(64, reader.bytes_read());
+/// ```
+#[derive(Debug)]
+pub struct RecordReader<R> {
+    reader: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    end: usize,
+    bytes_read: u64,
+    records_read: u64,
+    done: bool,
+}
+
+impl RecordReader<File> {
+    ///
+    /// Opens the binary input file with the default batch size.
+    pub fn open(filename: String) -> Result<Self, Error> {
+        Ok(Self::new(File::open(filename)?))
+    }
+}
+
+impl<R: Read> RecordReader<R> {
+    ///
+    /// Creates a new binary reader with the default batch size.
+    pub fn new(reader: R) -> Self {
+        Self::with_batch_records(DEFAULT_BATCH_RECORDS, reader)
+    }
+
+    ///
+    /// Creates a new binary reader, reading `records` records at once.
+    pub fn with_batch_records(records: usize, reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0u8; records.max(1) * RECORD_SIZE].into_boxed_slice(),
+            pos: 0,
+            end: 0,
+            bytes_read: 0,
+            records_read: 0,
+            done: false,
+        }
+    }
+
+    ///
+    /// Returns the number of bytes read from the underlying reader so far,
+    /// including the ones buffered, but not yet yielded.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    ///
+    /// Returns the number of records yielded so far.
+    pub fn records_read(&self) -> u64 {
+        self.records_read
+    }
+
+    fn fill(&mut self) -> Result<(), Error> {
+        // move the partial record to the start of the buffer
+        self.buf.copy_within(self.pos..self.end, 0);
+        self.end -= self.pos;
+        self.pos = 0;
+        while self.end < self.buf.len() {
+            match self.reader.read(&mut self.buf[self.end..]) {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.end += read;
+                    self.bytes_read += read as u64;
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.end - self.pos < RECORD_SIZE {
+            if let Err(error) = self.fill() {
+                self.done = true;
+                return Some(Err(error.into()));
+            }
+        }
+        match self.end - self.pos {
+            0 => {
+                self.done = true;
+                None
+            }
+            got if got < RECORD_SIZE => {
+                self.done = true;
+                Some(Err(MerkleError::TruncatedRecord {
+                    record: self.records_read as usize + 1,
+                    got,
+                }))
+            }
+            _ => {
+                let record = self.buf[self.pos..self.pos + RECORD_SIZE]
+                    .try_into()
+                    .unwrap();
+                self.pos += RECORD_SIZE;
+                self.records_read += 1;
+                Some(Ok(record))
+            }
+        }
+    }
+}
+
+///
+/// The lenient reader of input with one base16 hash per line.
+///
+/// Unlike [`SourceReader`], the lines are not required to have a fixed width:
+/// - A hash may be mixed-case and `0x`-prefixed, see [`decode_hex`]
+/// - Lines may end with `\n` or `\r\n`, the last one may miss the newline
+/// - Blank lines and comment lines, starting with `#`, are skipped
+///
+/// Yields the decoded hashes, or the error with the line number (1-based)
+/// of the offending line.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::source::LenientReader;
+///
+/// let input = "# leaves\r\n0X77D519A56A3BB197BCA02ED25F880A122487914556D587588E633C8368D13053\r\n\n";
+/// let leaves: Vec<_> = LenientReader::new(input.as_bytes()).collect();
+///
+/// assert_eq!(1, leaves.len());
+/// assert_eq!(0x77, leaves[0].as_ref().unwrap()[0]);
+/// ```
+#[derive(Debug)]
+pub struct LenientReader<R, W = fn(Warning)> {
+    reader: R,
+    buf: Vec<u8>,
+    line: usize,
+    max_line_bytes: usize,
+    done: bool,
+    warn: W,
+}
+
+///
+/// The recoverable anomaly of the input, accepted by the [`LenientReader`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The hash contains uppercase hex digits
+    Uppercase,
+    /// The hash is `0x`-prefixed
+    HexPrefix,
+    /// The line ends with `\r\n`
+    Crlf,
+    /// The line is blank
+    BlankLine,
+    /// The last line misses the newline
+    MissingNewline,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Uppercase => write!(f, "uppercase hex"),
+            Anomaly::HexPrefix => write!(f, "0x prefix"),
+            Anomaly::Crlf => write!(f, "CRLF line ending"),
+            Anomaly::BlankLine => write!(f, "blank line"),
+            Anomaly::MissingNewline => write!(f, "missing final newline"),
+        }
+    }
+}
+
+///
+/// The anomaly found on the given line (1-based).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub line: usize,
+    pub anomaly: Anomaly,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.anomaly)
+    }
+}
+
+impl<R: BufRead> LenientReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            line: 0,
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            done: false,
+            warn: |_| {},
+        }
+    }
+}
+
+impl<R: BufRead, W: FnMut(Warning)> LenientReader<R, W> {
+    ///
+    /// Sets the maximum length of a line in bytes. Reading a longer line fails
+    /// with [`MerkleError::LineTooLong`].
+    pub fn with_max_line_bytes(mut self, max_line_bytes: usize) -> Self {
+        self.max_line_bytes = max_line_bytes;
+        self
+    }
+
+    ///
+    /// Sets the function, called with each recoverable anomaly of the input.
+    /// The anomalies are ignored by default.
+    pub fn with_warnings<G: FnMut(Warning)>(self, warn: G) -> LenientReader<R, G> {
+        LenientReader {
+            reader: self.reader,
+            buf: self.buf,
+            line: self.line,
+            max_line_bytes: self.max_line_bytes,
+            done: self.done,
+            warn,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Option<Hash>, MerkleError> {
+        loop {
+            self.buf.clear();
+            self.line += 1;
+            let read = read_line_bounded(
+                &mut self.reader,
+                &mut self.buf,
+                self.max_line_bytes,
+                self.line,
+            )?;
+            if read == 0 {
+                return Ok(None);
+            }
+            let line = str::from_utf8(&self.buf)
+                .map_err(|_| MerkleError::BadUtf8 { line: self.line })?
+                .trim();
+            let mut anomalies = Vec::new();
+            if self.buf.ends_with(b"\r\n") {
+                anomalies.push(Anomaly::Crlf);
+            } else if !self.buf.ends_with(b"\n") {
+                anomalies.push(Anomaly::MissingNewline);
+            }
+            if line.is_empty() {
+                anomalies.push(Anomaly::BlankLine);
+            } else if !line.starts_with('#') {
+                if line.starts_with("0x") || line.starts_with("0X") {
+                    anomalies.push(Anomaly::HexPrefix);
+                }
+                if line.bytes().any(|byte| matches!(byte, b'A'..=b'F')) {
+                    anomalies.push(Anomaly::Uppercase);
+                }
+            }
+            for anomaly in anomalies {
+                (self.warn)(Warning {
+                    line: self.line,
+                    anomaly,
+                });
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return decode_hex(line, self.line).map(Some);
+        }
+    }
+}
+
+impl<R: BufRead, W: FnMut(Warning)> Iterator for LenientReader<R, W> {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.read_line().transpose();
+        // the reader stops on the end of the source or on the first error
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+///
+/// The streaming reader of input with the base16 hashes as a JSON array of
+/// strings, e.g. `["77d5...", "9159..."]`.
+///
+/// The array is read element by element, without loading the whole input.
+/// The hashes are decoded with [`decode_hex`]. Elements other than strings,
+/// including nested arrays, fail with [`MerkleError::BadJson`], naming the
+/// element (1-based) and what was found instead. Escape sequences are not
+/// supported, as base16 hashes never need them.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::source::JsonArrayReader;
+///
+/// let input = r#"["0x0000000000000000000000000000000000000000000000000000000000000000"]"#;
+/// let leaves: Vec<_> = JsonArrayReader::new(input.as_bytes()).collect();
+///
+/// assert_eq!([0u8; 32], *leaves[0].as_ref().unwrap());
+/// assert!(JsonArrayReader::new("[1]".as_bytes()).next().unwrap().is_err());
+/// ```
+#[derive(Debug)]
+pub struct JsonArrayReader<R> {
+    reader: R,
+    element: usize,
+    done: bool,
+}
+
+impl<R: BufRead> JsonArrayReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            element: 0,
+            done: false,
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, MerkleError> {
+        Ok(self.reader.fill_buf()?.first().copied())
+    }
+
+    ///
+    /// Skips the whitespace, returning the next byte without consuming it.
+    fn peek_token(&mut self) -> Result<Option<u8>, MerkleError> {
+        while let Some(byte) = self.peek()? {
+            if !byte.is_ascii_whitespace() {
+                return Ok(Some(byte));
+            }
+            self.reader.consume(1);
+        }
+        Ok(None)
+    }
+
+    fn read_element(&mut self) -> Result<Option<Hash>, MerkleError> {
+        if self.element == 0 {
+            if self.peek_token()? != Some(b'[') {
+                return Err(bad_json("expected an array".to_string()));
+            }
+            self.reader.consume(1);
+            if self.peek_token()? == Some(b']') {
+                return self.finish();
+            }
+        } else {
+            match self.peek_token()? {
+                Some(b',') => self.reader.consume(1),
+                Some(b']') => return self.finish(),
+                _ => {
+                    let reason = format!("element {}: expected ',' or ']'", self.element);
+                    return Err(bad_json(reason));
+                }
+            }
+        }
+        self.element += 1;
+        let found = match self.peek_token()? {
+            Some(b'"') => None,
+            Some(b'[') => Some("an array"),
+            Some(b'{') => Some("an object"),
+            Some(b'-' | b'0'..=b'9') => Some("a number"),
+            Some(b't' | b'f') => Some("a boolean"),
+            Some(b'n') => Some("null"),
+            Some(_) => Some("an invalid token"),
+            None => Some("the end of input"),
+        };
+        if let Some(found) = found {
+            let reason = format!("element {}: expected a string, got {found}", self.element);
+            return Err(bad_json(reason));
+        }
+        self.reader.consume(1);
+        let mut string = Vec::new();
+        self.reader.read_until(b'"', &mut string)?;
+        if string.pop() != Some(b'"') {
+            let reason = format!("element {}: unterminated string", self.element);
+            return Err(bad_json(reason));
+        }
+        if string.contains(&b'\\') {
+            let reason = format!(
+                "element {}: escape sequences are not supported",
+                self.element
+            );
+            return Err(bad_json(reason));
+        }
+        let hex = String::from_utf8_lossy(&string);
+        match decode_hex(&hex, self.element) {
+            Ok(hash) => Ok(Some(hash)),
+            Err(_) => {
+                let reason = format!("element {}: invalid hash {hex:?}", self.element);
+                Err(bad_json(reason))
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<Option<Hash>, MerkleError> {
+        self.reader.consume(1);
+        match self.peek_token()? {
+            None => Ok(None),
+            Some(_) => Err(bad_json(
+                "unexpected characters after the array".to_string(),
+            )),
+        }
+    }
+}
+
+fn bad_json(reason: String) -> MerkleError {
+    MerkleError::BadJson { reason }
+}
+
+impl<R: BufRead> Iterator for JsonArrayReader<R> {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.read_element().transpose();
+        // the reader stops on the end of the array or on the first error
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+///
+/// The iterator adapter, yielding the hashes of the wrapped fallible iterator
+/// until the first error occurs. The error is then stored and can be
+/// retrieved with [`UntilError::error`], the iteration stops.
+///
+/// Allows to feed fallible sources to the calculation algorithms, which
+/// expect plain iterators of hashes.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::error::MerkleError;
+/// use merkle_root::source::UntilError;
+///
+/// let source = vec![Ok([0u8; 32]), Err(MerkleError::DuplicateLeaf { line: 2 })];
+/// let mut leaves = UntilError::new(source.into_iter());
+///
+/// assert_eq!(1, (&mut leaves).count());
+/// assert!(leaves.error().is_some());
+/// ```
+pub struct UntilError<I> {
+    inner: I,
+    error: Option<MerkleError>,
+}
+
+impl<I> UntilError<I> {
+    pub fn new(inner: I) -> Self {
+        Self { inner, error: None }
+    }
+
+    ///
+    /// Returns the error the iteration stopped on, if any.
+    pub fn error(self) -> Option<MerkleError> {
+        self.error
+    }
+}
+
+impl<I: Iterator<Item = Result<Hash, MerkleError>>> Iterator for UntilError<I> {
     type Item = Hash;
     fn next(&mut self) -> Option<Self::Item> {
-        let mut str_buf = [0u8; 65]; // 65: accomodate for newline
-        let mut byte_buf = [0u8; 32];
-
-        // This code may actually work wrong if the last hash is corrupted, e.g.
-        // too short (63 bytes instead of 64). In this case last hash will be
-        // silently ignored.
-        if let Ok(()) = self.reader.read_exact(&mut str_buf) {
-            let str = str::from_utf8(&str_buf[0..64]).expect("Expected valid UTF-8 string");
-            base16ct::lower::decode(str, &mut byte_buf).expect("Expected valid base16 string");
-            return Some(byte_buf);
-        }
-        None
+        if self.error.is_some() {
+            return None;
+        }
+        match self.inner.next()? {
+            Ok(hash) => Some(hash),
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.error {
+            Some(_) => (0, Some(0)),
+            // any item may be the error, ending the iteration
+            None => (0, self.inner.size_hint().1),
+        }
+    }
+}
+
+///
+/// The iterator adapter, failing with [`MerkleError::DuplicateLeaf`] on the
+/// first leaf that was already seen in the wrapped iterator.
+///
+/// Every leaf seen is stored in a set, so the space complexity is O(n).
+pub struct ForbidDuplicates<I> {
+    inner: I,
+    seen: HashSet<Hash>,
+    line: usize,
+}
+
+impl<I> ForbidDuplicates<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            seen: HashSet::new(),
+            line: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Hash, MerkleError>>> Iterator for ForbidDuplicates<I> {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let hash = self.inner.next()?;
+        self.line += 1;
+        match hash {
+            Ok(hash) if !self.seen.insert(hash) => {
+                Some(Err(MerkleError::DuplicateLeaf { line: self.line }))
+            }
+            hash => Some(hash),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+///
+/// The iterator adapter, failing with [`MerkleError::LeafCountMismatch`] if
+/// the wrapped iterator yields more or fewer leaves than expected.
+pub struct Counted<I> {
+    inner: I,
+    expected: usize,
+    got: usize,
+}
+
+impl<I> Counted<I> {
+    pub fn new(inner: I, expected: usize) -> Self {
+        Self {
+            inner,
+            expected,
+            got: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Hash, MerkleError>>> Iterator for Counted<I> {
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let expected = self.expected;
+        match self.inner.next() {
+            None if self.got < expected => Some(Err(MerkleError::LeafCountMismatch {
+                expected,
+                got: self.got,
+            })),
+            None => None,
+            Some(_) if self.got == expected => {
+                // count the extra leaves to report the actual number
+                let got = expected + 1 + (&mut self.inner).count();
+                Some(Err(MerkleError::LeafCountMismatch { expected, got }))
+            }
+            Some(hash) => {
+                self.got += 1;
+                Some(hash)
+            }
+        }
+    }
+}
+
+///
+/// The iterator adapter, yielding the leaves of the wrapped iterator absent
+/// from the other one: the set difference of two sorted sources.
+///
+/// Both sources must be sorted in ascending order, the same order as the
+/// base16 lines sorted bytewise (e.g. with `LC_ALL=C sort`), so they are
+/// merged in a single streaming pass, without keeping the leaves in memory.
+/// A leaf sorting before the previous one of the same source fails with
+/// [`MerkleError::Unsorted`], the errors of the other source are passed
+/// through.
+///
+/// # Examples
+///
+/// ```
+/// use merkle_root::source::DiffSet;
+///
+/// let main = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]].map(Ok);
+/// let other = [[2u8; 32], [4u8; 32]].map(Ok);
+/// let diff: Vec<_> = DiffSet::new(main.into_iter(), other.into_iter())
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+///
+/// assert_eq!(vec![[1u8; 32], [3u8; 32]], diff);
+/// ```
+pub struct DiffSet<I, J> {
+    inner: I,
+    other: J,
+    // the smallest leaf of the other source not yet passed by the inner one
+    head: Option<Hash>,
+    last: Option<Hash>,
+    line: usize,
+    other_line: usize,
+}
+
+impl<I, J> DiffSet<I, J> {
+    pub fn new(inner: I, other: J) -> Self {
+        Self {
+            inner,
+            other,
+            head: None,
+            last: None,
+            line: 0,
+            other_line: 0,
+        }
+    }
+}
+
+impl<I, J> DiffSet<I, J>
+where
+    J: Iterator<Item = Result<Hash, MerkleError>>,
+{
+    ///
+    /// Advances the other source up to the leaf, returning whether the leaf is
+    /// present there.
+    fn contains(&mut self, leaf: &Hash) -> Result<bool, MerkleError> {
+        loop {
+            match self.head {
+                Some(head) if head >= *leaf => return Ok(head == *leaf),
+                _ => {}
+            }
+            let Some(next) = self.other.next() else {
+                // the other source is exhausted, keep the head to compare
+                // against the rest of the inner leaves
+                return Ok(self.head.as_ref() == Some(leaf));
+            };
+            let next = next?;
+            self.other_line += 1;
+            if self.head.is_some_and(|head| next < head) {
+                return Err(MerkleError::Unsorted {
+                    line: self.other_line,
+                });
+            }
+            self.head = Some(next);
+        }
+    }
+}
+
+impl<I, J> Iterator for DiffSet<I, J>
+where
+    I: Iterator<Item = Result<Hash, MerkleError>>,
+    J: Iterator<Item = Result<Hash, MerkleError>>,
+{
+    type Item = Result<Hash, MerkleError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let leaf = match self.inner.next()? {
+                Ok(leaf) => leaf,
+                error => return Some(error),
+            };
+            self.line += 1;
+            if self.last.is_some_and(|last| leaf < last) {
+                return Some(Err(MerkleError::Unsorted { line: self.line }));
+            }
+            self.last = Some(leaf);
+            match self.contains(&leaf) {
+                Ok(true) => continue,
+                Ok(false) => return Some(Ok(leaf)),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::fixture;
+
+    const LEAVES: &str = "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053\n\
+        915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723\n\
+        7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646\n";
+    use crate::calc::{
+        accumulator::MerkleAccumulator, depth_walk::DepthWalk, hash, root_from_source, Mode,
+    };
+
+    fn counted(name: &str, contents: &str) -> Result<usize, MerkleError> {
+        let mut reader = SourceReader::new(fixture("source", name, contents)).unwrap();
+        let expected = reader.read_header()?;
+        let mut leaves = UntilError::new(Counted::new(reader, expected));
+        let count = (&mut leaves).count();
+        match leaves.error() {
+            Some(error) => Err(error),
+            None => Ok(count),
+        }
+    }
+
+    ///
+    /// Reads all the leaves of the file, panicking on the first error.
+    fn read_all(file: String) -> Vec<Hash> {
+        SourceReader::new(file)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    const LEAF: &str = "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053\n";
+
+    #[test]
+    fn counted_header() {
+        let contents = format!("2\n{LEAF}{LEAF}");
+        assert!(matches!(counted("counted_exact.txt", &contents), Ok(2)));
+
+        let contents = format!("3\n{LEAF}{LEAF}");
+        assert!(matches!(
+            counted("counted_few.txt", &contents),
+            Err(MerkleError::LeafCountMismatch {
+                expected: 3,
+                got: 2
+            })
+        ));
+
+        let contents = format!("1\n{LEAF}{LEAF}{LEAF}");
+        assert!(matches!(
+            counted("counted_many.txt", &contents),
+            Err(MerkleError::LeafCountMismatch {
+                expected: 1,
+                got: 3
+            })
+        ));
+
+        let contents = format!("two\n{LEAF}{LEAF}");
+        assert!(matches!(
+            counted("counted_bad.txt", &contents),
+            Err(MerkleError::BadHeader { .. })
+        ));
+    }
+
+    #[test]
+    fn resume_from_offset() {
+        let leaves = [
+            "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053",
+            "915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723",
+            "7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646",
+            "2163a680ddcd3b7dcfb444d1e19395e59c63781c09e29c8d4b66bfd6460ca142",
+            "e41682195335361034578a501591d04cbcda00bf19dc32b4e80746a3a9c72e27",
+        ];
+        let contents: String = leaves.iter().map(|leaf| format!("{leaf}\n")).collect();
+        let file = fixture("source", "resume_from_offset.txt", &contents);
+        let full = DepthWalk::calculate(&mut read_all(file.clone()).into_iter().peekable(), &hash);
+
+        let mut accumulator = MerkleAccumulator::new();
+        accumulator.extend(leaves[..2].iter().map(|leaf| decode_hex(leaf, 1).unwrap()));
+        let mut reader = SourceReader::new(file.clone()).unwrap();
+        assert_eq!(2, reader.seek_offset(2 * 65).unwrap());
+        accumulator.extend(reader.map(Result::unwrap));
+        assert_eq!(Some(full), accumulator.root());
+
+        let mut reader = SourceReader::new(file).unwrap();
+        assert!(matches!(
+            reader.seek_offset(64),
+            Err(MerkleError::MisalignedOffset { offset: 64 })
+        ));
+    }
+
+    #[test]
+    fn crlf_seek() {
+        let leaves = ["00".repeat(32), "01".repeat(32), "02".repeat(32)];
+        let crlf: String = leaves.iter().map(|leaf| format!("{leaf}\r\n")).collect();
+        let file = fixture("source", "crlf_seek.txt", &crlf);
+        let mut reader = SourceReader::new(file.clone()).unwrap();
+        assert!(matches!(
+            reader.seek_leaf(1),
+            Err(MerkleError::CrlfLine { line: 1 })
+        ));
+        let mut reader = SourceReader::new(file.clone()).unwrap();
+        assert!(matches!(
+            reader.seek_offset(0),
+            Err(MerkleError::CrlfLine { line: 1 })
+        ));
+        // not seeked, the reader takes either line ending
+        assert_eq!(
+            3,
+            SourceReader::new(file).unwrap().map(Result::unwrap).count()
+        );
+
+        // the "\r\n" lines past the offset fail on reading
+        let mixed = format!("{}\n{}\n{}\r\n", leaves[0], leaves[1], leaves[2]);
+        let mut reader =
+            SourceReader::new(fixture("source", "crlf_seek_mixed.txt", &mixed)).unwrap();
+        reader.seek_leaf(1).unwrap();
+        assert_eq!([1u8; 32], reader.next().unwrap().unwrap());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::CrlfLine { line: 3 }))
+        ));
+
+        let lf: String = leaves.iter().map(|leaf| format!("{leaf}\n")).collect();
+        let mut reader = SourceReader::new(fixture("source", "crlf_seek_lf.txt", &lf)).unwrap();
+        reader.seek_leaf(1).unwrap();
+        assert_eq!(2, reader.map(Result::unwrap).count());
+    }
+
+    #[test]
+    fn seekable_leaves() {
+        let contents = format!("{LEAF}{LEAF}{}", LEAF.trim_end());
+        let mut reader =
+            SourceReader::new(fixture("source", "seekable_leaves.txt", &contents)).unwrap();
+        assert_eq!(3, reader.seekable_leaves().unwrap());
+        assert_eq!(3, reader.count());
+
+        let mut reader = SourceReader::from_reader(std::io::Cursor::new(contents));
+        assert!(matches!(reader.seekable_leaves(), Err(MerkleError::Io(_))));
+    }
+
+    #[test]
+    fn line_too_long() {
+        let contents = format!("{}\n{LEAF}", "1".repeat(100));
+        let reader = SourceReader::new(fixture("source", "line_too_long.txt", &contents)).unwrap();
+        assert!(matches!(
+            reader.with_max_line_bytes(64).read_header(),
+            Err(MerkleError::LineTooLong { line: 1 })
+        ));
+
+        let mut buf = Vec::new();
+        let mut reader = "short\nlonger line\n".as_bytes();
+        assert_eq!(6, read_line_bounded(&mut reader, &mut buf, 8, 1).unwrap());
+        assert!(matches!(
+            read_line_bounded(&mut reader, &mut buf, 8, 2),
+            Err(MerkleError::LineTooLong { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn record_reader() {
+        let records: Vec<u8> = (0..5u8).flat_map(|byte| [byte; 32]).collect();
+        let mut reader = RecordReader::with_batch_records(2, records.as_slice());
+        let leaves: Vec<Hash> = (&mut reader).map(Result::unwrap).collect();
+        assert_eq!((0..5u8).map(|byte| [byte; 32]).collect::<Vec<_>>(), leaves);
+        assert_eq!(5, reader.records_read());
+        assert_eq!(160, reader.bytes_read());
+
+        let mut reader = RecordReader::with_batch_records(2, &records[..100]);
+        assert_eq!(3, (&mut reader).take_while(Result::is_ok).count());
+        assert!(reader.next().is_none());
+        let mut reader = RecordReader::new(&records[..100]);
+        assert!(matches!(
+            reader.nth(3),
+            Some(Err(MerkleError::TruncatedRecord { record: 4, got: 4 }))
+        ));
+    }
+
+    #[test]
+    fn lenient_reader() {
+        let upper = LEAF.trim().to_uppercase();
+        let contents = format!("# comment\n{LEAF}\r\n  \n0x{upper}\r\n{}", LEAF.trim());
+        let leaves: Vec<Hash> = LenientReader::new(contents.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(3, leaves.len());
+        assert!(leaves.iter().all(|leaf| *leaf == leaves[0]));
+
+        let contents = format!("{LEAF}\n{}\n", &LEAF[..10]);
+        let mut reader = LenientReader::new(contents.as_bytes());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::BadLength { line: 3, .. }))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn lenient_warnings() {
+        let upper = LEAF.trim().to_uppercase();
+        let contents = format!("{LEAF}0x{}\r\n\n{upper}", LEAF.trim());
+        let mut warnings = Vec::new();
+        let count = LenientReader::new(contents.as_bytes())
+            .with_warnings(|warning| warnings.push(warning))
+            .map(Result::unwrap)
+            .count();
+        assert_eq!(3, count);
+        let warnings: Vec<String> = warnings.iter().map(Warning::to_string).collect();
+        assert_eq!(
+            vec![
+                "line 2: CRLF line ending",
+                "line 2: 0x prefix",
+                "line 3: blank line",
+                "line 4: missing final newline",
+                "line 4: uppercase hex",
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn json_array_reader() {
+        let leaf = LEAF.trim();
+        let read = |json: &str| {
+            JsonArrayReader::new(json.as_bytes())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| error.to_string())
+        };
+        assert_eq!(
+            2,
+            read(&format!(" [ \"{leaf}\",\n\"{leaf}\" ] \n"))
+                .unwrap()
+                .len()
+        );
+        assert!(read("[]").unwrap().is_empty());
+
+        let errors = [
+            ("{}", "invalid JSON: expected an array"),
+            (
+                &format!("[\"{leaf}\", [\"{leaf}\"]]"),
+                "invalid JSON: element 2: expected a string, got an array",
+            ),
+            (
+                "[null]",
+                "invalid JSON: element 1: expected a string, got null",
+            ),
+            ("[\"00\"]", "invalid JSON: element 1: invalid hash \"00\""),
+            (
+                &format!("[\"{leaf}\""),
+                "invalid JSON: element 1: expected ',' or ']'",
+            ),
+            (
+                "[] x",
+                "invalid JSON: unexpected characters after the array",
+            ),
+        ];
+        for (json, error) in errors {
+            assert_eq!(Err(error.to_string()), read(json), "{json}");
+        }
+    }
+
+    #[test]
+    fn hex_lines() {
+        let bad = LEAF.trim().replace('7', "g");
+        let lines: Vec<String> = vec![LEAF.to_string(), LEAF.to_string(), bad, LEAF.to_string()];
+        let hashes: Vec<_> = hex_lines_to_hashes(lines.into_iter()).collect();
+        assert_eq!(4, hashes.len());
+        assert!(hashes[..2].iter().all(Result::is_ok));
+        assert!(matches!(
+            hashes[2],
+            Err(MerkleError::BadHex { line: 3, .. })
+        ));
+        assert!(hashes[3].is_ok());
+
+        let lines = vec![LEAF; 3];
+        let mut leaves = UntilError::new(hex_lines_to_hashes(lines.into_iter()));
+        let mut source = (&mut leaves).peekable();
+        let root = DepthWalk::calculate(&mut source, &hash);
+        assert!(leaves.error().is_none());
+        let decoded = decode_hex(LEAF, 1).unwrap();
+        let mut source = vec![decoded; 3].into_iter().peekable();
+        assert_eq!(DepthWalk::calculate(&mut source, &hash), root);
+    }
+
+    #[test]
+    fn forbid_duplicates() {
+        let source = vec![[0u8; 32], [1u8; 32], [2u8; 32], [1u8; 32], [3u8; 32]];
+        let mut leaves = UntilError::new(ForbidDuplicates::new(source.into_iter().map(Ok)));
+        assert_eq!(3, (&mut leaves).count());
+        assert!(matches!(
+            leaves.error(),
+            Some(MerkleError::DuplicateLeaf { line: 4 })
+        ));
+    }
+
+    #[test]
+    fn diff_set() {
+        let [a, b, c, d] = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        let diff = |main: &[Hash], other: &[Hash]| {
+            DiffSet::new(main.iter().copied().map(Ok), other.iter().copied().map(Ok))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        assert_eq!(vec![a, c], diff(&[a, b, c, d], &[b, d]).unwrap());
+        assert_eq!(vec![a, c], diff(&[a, b, b, c], &[b, b, d]).unwrap());
+        assert_eq!(vec![b, d], diff(&[b, d], &[a, c]).unwrap());
+        assert_eq!(vec![a, b], diff(&[a, b], &[]).unwrap());
+        assert!(diff(&[a], &[a]).unwrap().is_empty());
+        assert!(matches!(
+            diff(&[a, c, b], &[d]),
+            Err(MerkleError::Unsorted { line: 3 })
+        ));
+        assert!(matches!(
+            diff(&[c], &[b, a]),
+            Err(MerkleError::Unsorted { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn crlf() {
+        let leaves = read_all(fixture("source", "lf.hex", LEAVES));
+        assert_eq!(3, leaves.len());
+        let crlf = LEAVES.replace('\n', "\r\n");
+        assert_eq!(leaves, read_all(fixture("source", "crlf.hex", &crlf)));
+    }
+
+    #[test]
+    fn missing_final_newline() {
+        let file = fixture(
+            "source",
+            "no_final_newline.hex",
+            &LEAVES[..LEAVES.len() - 1],
+        );
+        let leaves = read_all(file);
+        assert_eq!(3, leaves.len());
+        assert_eq!(0x7a, leaves[2][0]);
+    }
+
+    #[test]
+    fn short_final_line() {
+        let file = fixture(
+            "source",
+            "short_final_line.hex",
+            &LEAVES[..LEAVES.len() - 2],
+        );
+        let error = SourceReader::new(file)
+            .unwrap()
+            .nth(2)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!("line 3: expected 64 characters, got 63", error.to_string());
+    }
+
+    #[test]
+    fn bad_length() {
+        let lengths = |contents: &str, name: &str| {
+            SourceReader::new(fixture("source", name, contents))
+                .unwrap()
+                .with_keep_going(true)
+                .map(|leaf| match leaf {
+                    Ok(_) => None,
+                    Err(MerkleError::BadLength {
+                        line,
+                        got,
+                        want: 64,
+                    }) => Some((line, got)),
+                    Err(error) => panic!("unexpected error {error}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // a short line does not pull the characters of the next one in
+        let short = LEAVES.replacen("77d5", "77d", 1);
+        assert_eq!(
+            vec![Some((1, 63)), None, None],
+            lengths(&short, "short.hex")
+        );
+
+        let long = LEAVES.replacen('\n', "0\n", 2);
+        assert_eq!(
+            vec![Some((1, 65)), Some((2, 65)), None],
+            lengths(&long, "long.hex")
+        );
+
+        // the final newline ends the last line, the blank line after it does not
+        let trailing = format!("{LEAVES}\n");
+        assert_eq!(
+            vec![None, None, None, Some((4, 0))],
+            lengths(&trailing, "empty_trailing_line.hex")
+        );
+    }
+
+    #[test]
+    fn size_hint() {
+        let upper = |reader: &SourceReader| reader.size_hint().1;
+        let mut reader = SourceReader::new(fixture("source", "size_hint.hex", LEAVES)).unwrap();
+        assert_eq!(Some(4), upper(&reader));
+        reader.next().unwrap().unwrap();
+        assert_eq!(Some(3), upper(&reader));
+        assert_eq!(2, reader.by_ref().count());
+        assert_eq!(Some(0), upper(&reader));
+        reader.seek_leaf(1).unwrap();
+        assert_eq!(Some(3), upper(&reader));
+
+        // the bound holds for the longer lines and the missing final newline
+        let crlf = LEAVES.replace('\n', "\r\n");
+        for contents in [&crlf, &LEAVES[..LEAVES.len() - 1]] {
+            let reader =
+                SourceReader::new(fixture("source", "size_hint_lines.hex", contents)).unwrap();
+            assert!(upper(&reader).unwrap() >= 3);
+        }
+
+        let mut reader =
+            SourceReader::new(fixture("source", "size_hint_bad.hex", "abc\n")).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert_eq!(Some(0), upper(&reader));
+        let reader = SourceReader::new(fixture("source", "size_hint_bad.hex", "abc\n")).unwrap();
+        assert_eq!(None, upper(&reader.with_keep_going(true)));
+        assert_eq!(None, upper(&SourceReader::from_reader(LEAVES.as_bytes())));
+    }
+
+    #[test]
+    fn skip_comments() {
+        let lines: Vec<&str> = LEAVES.lines().collect();
+        let commented = format!(
+            "# exported leaves\n\n{}\n   # indented comment\n\r\n{}\n \t\n{}\n# end",
+            lines[0], lines[1], lines[2]
+        );
+        let clean = fixture("source", "clean.hex", LEAVES);
+        let file = fixture("source", "commented.hex", &commented);
+        let root = |reader: SourceReader| root_from_source(reader, Mode::DepthWalk).unwrap();
+        assert_eq!(
+            root(SourceReader::new(clean).unwrap()),
+            root(
+                SourceReader::new(file.clone())
+                    .unwrap()
+                    .with_skip_comments(true)
+            )
+        );
+
+        // the comments are rejected by default
+        assert!(matches!(
+            SourceReader::new(file).unwrap().next(),
+            Some(Err(MerkleError::BadLength { line: 1, .. }))
+        ));
+    }
+
+    #[test]
+    fn whitespace_separator() {
+        let expected = read_all(fixture("source", "whitespace_clean.hex", LEAVES));
+        let lines: Vec<&str> = LEAVES.lines().collect();
+        let reader = |contents: &str| {
+            SourceReader::from_reader(std::io::Cursor::new(contents.to_string()))
+                .with_separator(Separator::Whitespace)
+        };
+        for contents in [
+            LEAVES.to_string(),
+            LEAVES.replace('\n', "\r\n"),
+            format!("{} {}\t{}", lines[0], lines[1], lines[2]),
+            format!(
+                "\n\n  {}  \t \n{}\n\n\t{} \n\n",
+                lines[0], lines[1], lines[2]
+            ),
+        ] {
+            let leaves: Vec<Hash> = reader(&contents).collect::<Result<_, _>>().unwrap();
+            assert_eq!(expected, leaves);
+        }
+        assert_eq!(0, reader(" \t\n\r\n").count());
+
+        // the errors name the line the token is on
+        let contents = format!("{} {}\n\n{}0 {}\n", lines[0], lines[1], lines[2], lines[0]);
+        let leaves: Vec<_> = reader(&contents).with_keep_going(true).collect();
+        assert_eq!(4, leaves.len());
+        assert!(matches!(
+            leaves[2],
+            Err(MerkleError::BadLength {
+                line: 3,
+                got: 65,
+                want: 64
+            })
+        ));
+        assert_eq!(expected[0], *leaves[3].as_ref().unwrap());
+
+        // the comments run to the end of the line, the tokens are bounded
+        let contents = format!(
+            "# leaves {}\n{} # {}\n{} {}",
+            lines[0], lines[0], lines[1], lines[1], lines[2]
+        );
+        let leaves: Vec<Hash> = reader(&contents)
+            .with_skip_comments(true)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(expected, leaves);
+        assert!(matches!(
+            reader(&contents).next(),
+            Some(Err(MerkleError::BadLength {
+                line: 1,
+                got: 1,
+                ..
+            }))
+        ));
+        let contents = format!("{}\n{}", lines[0], "0".repeat(100));
+        let leaves: Vec<_> = reader(&contents)
+            .with_max_line_bytes(64)
+            .with_keep_going(true)
+            .collect();
+        assert!(matches!(
+            leaves[..],
+            [Ok(_), Err(MerkleError::LineTooLong { line: 2 })]
+        ));
+    }
+
+    #[test]
+    fn wide() {
+        use crate::calc::hash_with;
+        use sha2::Sha512;
+
+        let contents: String = (0..3u8)
+            .map(|byte| format!("{}\n", hex::<64>(byte)))
+            .collect();
+        let file = fixture("source", "wide.hex", &contents);
+        let leaves: Vec<[u8; 64]> = SourceReader::new(file.clone())
+            .unwrap()
+            .into_width::<64>()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(vec![[0u8; 64], [1u8; 64], [2u8; 64]], leaves);
+
+        let hash = hash_with::<Sha512, 64>;
+        let root = DepthWalk::calculate(&mut leaves.into_iter().peekable(), &hash);
+        let expected = hash(
+            &hash(&[0u8; 64], Some(&[1u8; 64])),
+            Some(&hash(&[2u8; 64], None)),
+        );
+        assert_eq!(expected, root);
+
+        // the 32 bytes hashes are too short for the wide reader, and vice versa
+        let mut reader = SourceReader::new(fixture("source", "narrow.hex", LEAVES))
+            .unwrap()
+            .into_width::<64>();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::BadLength {
+                line: 1,
+                got: 64,
+                want: 128
+            }))
+        ));
+        assert!(matches!(
+            SourceReader::new(file).unwrap().next(),
+            Some(Err(MerkleError::BadLength { want: 64, .. }))
+        ));
+    }
+
+    fn hex<const N: usize>(byte: u8) -> String {
+        format!("{byte:02x}").repeat(N)
+    }
+
+    #[test]
+    fn bad_line() {
+        let broken = "zz".repeat(32);
+        let contents = LEAVES.replacen(&LEAVES[65..129], &broken, 1);
+        let file = fixture("source", "bad_line.hex", &contents);
+        let mut reader = SourceReader::new(file.clone()).unwrap();
+        assert!(matches!(reader.next(), Some(Ok(_))));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::BadHex { line: 2, content })) if content == broken
+        ));
+        // the reader ends on the broken line
+        assert!(reader.next().is_none());
+        assert!(reader.next().is_none());
+
+        let mut reader = SourceReader::new(file).unwrap().with_keep_going(true);
+        assert!(matches!(reader.next(), Some(Ok(_))));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::BadHex { .. }))
+        ));
+        // unless asked to move on past it
+        assert!(matches!(reader.next(), Some(Ok(_))));
+        assert!(reader.next().is_none());
+
+        let contents = format!("{}\n", "A".repeat(44));
+        let file = fixture("source", "bad_line.b64", &contents);
+        let mut reader = SourceReader::with_encoding(Encoding::Base64, file).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::BadBase64 { line: 1, .. }))
+        ));
+    }
+
+    #[test]
+    fn base64_same_root() {
+        let hex = fixture("source", "base64_same_root.hex", LEAVES);
+        let contents: String = read_all(hex.clone())
+            .iter()
+            .map(|leaf| format!("{}\n", BASE64.encode(leaf)))
+            .collect();
+        let b64 = fixture("source", "base64_same_root.b64", &contents);
+        for mode in [Mode::DepthWalk, Mode::WidthWalk] {
+            let expected = root_from_source(SourceReader::new(hex.clone()).unwrap(), mode);
+            let reader = SourceReader::with_encoding(Encoding::Base64, b64.clone()).unwrap();
+            assert_eq!(expected.unwrap(), root_from_source(reader, mode).unwrap());
+        }
+    }
+
+    #[test]
+    fn all_bad_lines() {
+        let leaves: Vec<&str> = LEAVES.lines().collect();
+        let mut contents = format!("{}\nabc\n{}\n", leaves[0], "zz".repeat(32)).into_bytes();
+        contents.extend([0xffu8; 64]);
+        contents.extend(format!("\n{}\n", leaves[2]).bytes());
+        let file = fixture("source", "all_bad_lines.hex", "");
+        std::fs::write(&file, contents).unwrap();
+
+        let results: Vec<_> = SourceReader::new(file.clone())
+            .unwrap()
+            .with_keep_going(true)
+            .collect();
+        assert_eq!(5, results.len());
+        let errors: Vec<MerkleError> = results.into_iter().filter_map(Result::err).collect();
+        assert_eq!(
+            vec![Some(2), Some(3), Some(4)],
+            errors.iter().map(MerkleError::line).collect::<Vec<_>>()
+        );
+        assert!(matches!(errors[0], MerkleError::BadLength { got: 3, .. }));
+        assert!(matches!(errors[1], MerkleError::BadHex { .. }));
+        assert!(matches!(errors[2], MerkleError::BadUtf8 { .. }));
+
+        let leaves: Result<Vec<Hash>, _> = SourceReader::new(file).unwrap().collect();
+        assert!(matches!(
+            leaves,
+            Err(MerkleError::BadLength { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn fused_after_error() {
+        let leaves: Vec<&str> = LEAVES.lines().collect();
+        let contents = format!("{}\n{}0\n{}\n", leaves[0], leaves[1], leaves[2]);
+        let reader = || SourceReader::from_reader(std::io::Cursor::new(contents.clone()));
+        let results: Vec<_> = reader().collect();
+        assert_eq!(2, results.len());
+        assert!(matches!(
+            results[1],
+            Err(MerkleError::BadLength {
+                line: 2,
+                got: 65,
+                ..
+            })
+        ));
+        assert_eq!(3, reader().with_keep_going(true).count());
+
+        // the rest of the overlong line can not be told from the next line
+        let mut reader = reader().with_max_line_bytes(65).with_keep_going(true);
+        assert!(matches!(reader.next(), Some(Ok(_))));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MerkleError::LineTooLong { line: 2 }))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn from_reader() {
+        let expected = read_all(fixture("source", "from_reader.hex", LEAVES));
+        let reader = SourceReader::from_reader(std::io::Cursor::new(LEAVES.as_bytes().to_vec()));
+        assert_eq!(expected, reader.collect::<Result<Vec<_>, _>>().unwrap());
+
+        let mut reader = SourceReader::from_reader(LEAVES.as_bytes());
+        assert!(reader.seek_leaf(1).is_err());
+        assert_eq!(expected[0], reader.next().unwrap().unwrap());
+    }
+
+    #[test]
+    fn uppercase() {
+        let leaves = read_all(fixture("source", "lowercase.hex", LEAVES));
+        let upper = fixture("source", "uppercase.hex", LEAVES.to_uppercase());
+        assert_eq!(leaves, read_all(upper));
+        let mixed: String = LEAVES
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match i % 2 {
+                0 => format!("{}\n", line.to_uppercase()),
+                _ => format!("{line}\n"),
+            })
+            .collect();
+        assert_eq!(
+            leaves,
+            read_all(fixture("source", "mixed_case.hex", &mixed))
+        );
     }
 }
@@ -0,0 +1,28 @@
+//!
+//! Implements the fixture files of the tests, shared by the unit tests of the
+//! modules and the integration tests of the command line tool.
+//!
+//! Each suite of the tests writes its fixtures into its own temporary
+//! directory, so the suites running in parallel do not overwrite the fixtures
+//! of the same name.
+
+use std::path::PathBuf;
+
+///
+/// Returns the temporary directory of the fixtures of the suite, e.g.
+/// `source`, creating it if needed.
+pub fn fixture_dir(suite: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("merkle_root_{suite}_tests"));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+///
+/// Writes the fixture file into the temporary directory of the suite, along
+/// with the parent directories of the name, returning its path.
+pub fn fixture(suite: &str, name: &str, contents: impl AsRef<[u8]>) -> String {
+    let path = fixture_dir(suite).join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, contents).unwrap();
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,87 @@
+use merkle_root::calc::{hash_sha256d, Mode};
+use merkle_root::hex::HexHash;
+use merkle_root::Hash;
+
+///
+/// Calculates the Bitcoin merkle root of the displayed transaction ids, the
+/// ids and the root being byte-reversed as Bitcoin displays them.
+fn merkle_root(txids: &[&str], mode: Mode) -> String {
+    let mut leaves = txids
+        .iter()
+        .map(|txid| {
+            let mut leaf: Hash = txid.parse::<HexHash>().unwrap().into();
+            leaf.reverse();
+            leaf
+        })
+        .peekable();
+    let mut root = mode.calculate(&mut leaves, &hash_sha256d);
+    root.reverse();
+    HexHash(root).to_string()
+}
+
+fn check(txids: &[&str], root: &str) {
+    for mode in [
+        Mode::DepthWalk,
+        Mode::WidthWalk,
+        Mode::ParallelShards,
+        Mode::Auto,
+    ] {
+        assert_eq!(root, merkle_root(txids, mode), "{mode:?}");
+    }
+}
+
+#[test]
+fn genesis_block() {
+    let coinbase = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+    check(&[coinbase], coinbase);
+}
+
+#[test]
+fn block_170() {
+    // the first block spending a coin, from Satoshi to Hal Finney
+    check(
+        &[
+            "b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082",
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+        ],
+        "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff",
+    );
+}
+
+#[test]
+fn block_100000() {
+    let txids = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    let root = "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766";
+    check(&txids, root);
+
+    // the swapped branches give another root
+    let swapped = [txids[1], txids[0], txids[2], txids[3]];
+    assert_ne!(root, merkle_root(&swapped, Mode::DepthWalk));
+}
+
+#[test]
+fn odd_transaction_count() {
+    // the transactions of block 100000 cut to odd counts, the roots taken from
+    // an independent sha256d reference, which gives the root of the full block
+    let txids = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    let three = "fa435470825de273081dcc706b25514c936fa6dc80ab965ce6970d68ddd0b553";
+    check(&txids[..3], three);
+    check(
+        &[txids[0], txids[1], txids[2], txids[3], txids[0]],
+        "294b257084a14ef954334f28cffb6f7724e27b025bfc8111ed89a503184eb42f",
+    );
+
+    // the last transaction is hashed with itself, so repeating it keeps the
+    // root, as in CVE-2012-2459
+    check(&[txids[0], txids[1], txids[2], txids[2]], three);
+}
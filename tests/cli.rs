@@ -0,0 +1,952 @@
+//!
+//! Integration tests, running the merkle_root binary against fixture files.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use merkle_root::calc::hash_level_domain;
+use merkle_root::Hash;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use test_fixtures::{fixture, fixture_dir};
+
+#[path = "../src/test_fixtures.rs"]
+mod test_fixtures;
+
+const LEAVES: [&str; 3] = [
+    "77d519a56a3bb197bca02ed25f880a122487914556d587588e633c8368d13053",
+    "915961583d426ff5d6726ee59ff7e1ad234d8343f60c57ab023b21741fdba723",
+    "7a172559f818c9d9f750b20f9fb16ed89879df47c20e03ffeaa3026c1d297646",
+];
+
+fn hex_fixture(name: &str, leaves: &[&str]) -> String {
+    let contents: String = leaves.iter().map(|leaf| format!("{leaf}\n")).collect();
+    fixture("cli", name, &contents)
+}
+
+fn decode(hex: &str) -> Hash {
+    let mut buf = [0u8; 32];
+    base16ct::lower::decode(hex, &mut buf).unwrap();
+    buf
+}
+
+fn encode(hash: &Hash) -> String {
+    let mut buf = [0u8; 64];
+    base16ct::lower::encode_str(hash, &mut buf)
+        .unwrap()
+        .to_string()
+}
+
+fn merkle_root(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_merkle_root"))
+        .args(args)
+        .output()
+        .expect("Expected merkle_root binary to run")
+}
+
+#[test]
+fn equals_across_encodings() {
+    let hex = hex_fixture("equals.hex", &LEAVES);
+    let b64: String = LEAVES
+        .iter()
+        .map(|leaf| format!("{}\n", BASE64.encode(decode(leaf))))
+        .collect();
+    let b64 = fixture("cli", "equals.b64", &b64);
+
+    let output = merkle_root(&["equals", &hex, &b64, "--right-encoding", "base64"]);
+    assert_eq!(Some(0), output.status.code());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn equals_different_files() {
+    let left = hex_fixture("equals_left.hex", &LEAVES);
+    let right = hex_fixture("equals_right.hex", &LEAVES[..2]);
+
+    let output = merkle_root(&["equals", &left, &right]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn equals_missing_file() {
+    let present = hex_fixture("equals_present.hex", &LEAVES);
+    let missing = fixture_dir("cli").join("equals_missing.hex");
+    let missing = missing.to_string_lossy().into_owned();
+
+    for (left, right) in [(&missing, &present), (&present, &missing)] {
+        let output = merkle_root(&["equals", left, right]);
+        assert_eq!(Some(2), output.status.code());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("error: ") && !stderr.contains("panicked"));
+    }
+}
+
+#[test]
+fn intermediate_level_domain() {
+    let leaves = hex_fixture("intermediate_leaves.hex", &LEAVES);
+    let level1: Vec<String> = LEAVES
+        .chunks(2)
+        .map(|chunk| {
+            let decoded: Vec<Hash> = chunk.iter().map(|leaf| decode(leaf)).collect();
+            encode(&hash_level_domain(1, &decoded[0], decoded.get(1)))
+        })
+        .collect();
+    let level1: Vec<&str> = level1.iter().map(String::as_str).collect();
+    let level1 = hex_fixture("intermediate_level1.hex", &level1);
+
+    let full = merkle_root(&["-f", &leaves, "--level-domain"]);
+    let reduced = merkle_root(&[
+        "-f",
+        &level1,
+        "--level-domain",
+        "--intermediate",
+        "--start-level",
+        "1",
+    ]);
+    assert!(full.status.success());
+    assert_eq!(full.stdout, reduced.stdout);
+}
+
+#[test]
+fn hex_prefix_output() {
+    let leaves = hex_fixture("hex_prefix_output.hex", &LEAVES);
+
+    let plain = merkle_root(&["-f", &leaves]);
+    let plain = String::from_utf8(plain.stdout).unwrap();
+    assert!(!plain.starts_with("0x"));
+
+    let prefixed = merkle_root(&["-f", &leaves, "--hex-prefix-output"]);
+    let prefixed = String::from_utf8(prefixed.stdout).unwrap();
+    assert_eq!(format!("0x{plain}"), prefixed);
+}
+
+#[test]
+fn format() {
+    let leaves = hex_fixture("format.hex", &LEAVES);
+    let root = |format: &str| {
+        let output = merkle_root(&["-f", &leaves, "--format", format]);
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let lower = root("lower");
+    assert_eq!(
+        String::from_utf8(merkle_root(&["-f", &leaves]).stdout).unwrap(),
+        lower
+    );
+    assert_eq!(lower.to_uppercase(), root("upper"));
+    assert_eq!(format!("0x{lower}"), root("prefixed"));
+
+    let conflict = merkle_root(&["-f", &leaves, "--format", "upper", "--hex-prefix-output"]);
+    assert!(!conflict.status.success());
+}
+
+#[test]
+fn output_json() {
+    let leaves = hex_fixture("output_json.hex", &LEAVES);
+    let root = String::from_utf8(merkle_root(&["-f", &leaves]).stdout).unwrap();
+    let root = root.trim_end();
+
+    let output = merkle_root(&["-f", &leaves, "--output", "json"]);
+    assert!(output.status.success());
+    assert_eq!(
+        format!("{{\"root\":\"{root}\",\"leaves\":3,\"mode\":\"depth-walk\"}}\n"),
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let output = merkle_root(&["-f", &leaves, "--output", "json", "-m", "parallel-shards"]);
+    assert!(output.status.success());
+    assert_eq!(
+        format!("{{\"root\":\"{root}\",\"leaves\":3,\"mode\":\"parallel-shards\"}}\n"),
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn no_trailing_newline() {
+    let contents = LEAVES.join("\n");
+    let leaves = fixture("cli", "no_trailing_newline.hex", &contents);
+    let expected = merkle_root(&["-f", &hex_fixture("trailing_newline.hex", &LEAVES)]);
+    assert!(expected.status.success());
+    for mode in ["depth-walk", "width-walk", "parallel-shards"] {
+        for shards in ["1", "4"] {
+            let output = merkle_root(&["-f", &leaves, "-m", mode, "--shards", shards]);
+            assert!(output.status.success());
+            assert_eq!(expected.stdout, output.stdout);
+        }
+    }
+}
+
+#[test]
+fn parallel_shards_errors() {
+    let missing = fixture_dir("cli").join("parallel_shards_missing.hex");
+    let missing = missing.to_string_lossy().into_owned();
+    let crlf: String = LEAVES.iter().map(|leaf| format!("{leaf}\r\n")).collect();
+    let crlf = fixture("cli", "parallel_shards_crlf.hex", &crlf);
+    // the gzip magic, the compressed input can not be opened at the leaf index
+    let magic = [0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00];
+    let gz = fixture("cli", "parallel_shards.hex.gz", magic);
+
+    for file in [&missing, &crlf, &gz] {
+        for shards in ["1", "4"] {
+            let output = merkle_root(&["-f", file, "-m", "parallel-shards", "--shards", shards]);
+            assert_eq!(Some(2), output.status.code());
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            assert!(stderr.starts_with("error: ") && !stderr.contains("panicked"));
+        }
+    }
+    let output = merkle_root(&["-f", &crlf, "-m", "parallel-shards", "--shards", "4"]);
+    assert_eq!(
+        "error: line 1: seeking requires '\\n' line endings, got \"\\r\\n\"\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn expect() {
+    let leaves = hex_fixture("expect.hex", &LEAVES);
+    let root = String::from_utf8(merkle_root(&["-f", &leaves]).stdout).unwrap();
+    let root = root.trim_end();
+
+    for expected in [root.to_string(), root.to_uppercase(), format!("0x{root}")] {
+        let output = merkle_root(&["-f", &leaves, "--expect", &expected]);
+        assert_eq!(Some(0), output.status.code());
+    }
+    let output = merkle_root(&["-f", &leaves, "-m", "parallel-shards", "--expect", root]);
+    assert_eq!(Some(0), output.status.code());
+
+    let other = encode(&[0u8; 32]);
+    let output = merkle_root(&["-f", &leaves, "--expect", &other]);
+    assert_eq!(Some(1), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&other) && stderr.contains(root));
+
+    let output = merkle_root(&["-f", &leaves, "--expect", &root[2..]]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn auto() {
+    let leaves: Vec<String> = (0..4097u64)
+        .map(|index| encode(&merkle_root::calc::bind_index(index, &[0u8; 32])))
+        .collect();
+    let mode = |count: usize| {
+        let leaves: Vec<&str> = leaves[..count].iter().map(String::as_str).collect();
+        let file = hex_fixture(&format!("auto_{count}.hex"), &leaves);
+        let expected = merkle_root(&["-f", &file, "-m", "depth-walk"]);
+        let output = merkle_root(&["-f", &file, "-m", "auto", "--output", "json"]);
+        assert!(output.status.success());
+        let output = String::from_utf8(output.stdout).unwrap();
+        let root = String::from_utf8(expected.stdout).unwrap();
+        assert!(output.contains(root.trim_end()));
+        output
+    };
+    assert!(mode(4095).ends_with("\"mode\":\"depth-walk\"}\n"));
+    assert!(mode(4097).ends_with("\"mode\":\"width-walk\"}\n"));
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn threads() {
+    let leaves = hex_fixture("threads.hex", &LEAVES);
+    let expected = merkle_root(&["-f", &leaves]);
+    assert!(expected.status.success());
+
+    for threads in ["0", "1", "3"] {
+        let output = merkle_root(&["-f", &leaves, "-m", "width-walk", "--threads", threads]);
+        assert!(output.status.success());
+        assert_eq!(expected.stdout, output.stdout);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "parallel"))]
+fn threads_without_parallel() {
+    let leaves = hex_fixture("threads.hex", &LEAVES);
+    assert!(merkle_root(&["-f", &leaves, "-m", "width-walk"])
+        .status
+        .success());
+
+    let output = merkle_root(&["-f", &leaves, "-m", "width-walk", "--threads", "2"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--threads requires the parallel feature"));
+}
+
+#[test]
+fn hash_rfc6962() {
+    // the RFC 6962 leaf hashes of the first 3 entries of the Certificate
+    // Transparency reference test vectors, and the root of their tree
+    let leaves = hex_fixture(
+        "hash_rfc6962.hex",
+        &[
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "0298d122906dcfc10892cb53a73992fc5b9f493ea4c9badb27b791b4127a7fe7",
+        ],
+    );
+    for mode in ["depth-walk", "width-walk", "parallel-shards"] {
+        let output = merkle_root(&["-f", &leaves, "-m", mode, "--hash", "rfc6962"]);
+        assert!(output.status.success());
+        assert_eq!(
+            b"aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77\n",
+            output.stdout.as_slice()
+        );
+    }
+}
+
+#[test]
+fn forbid_duplicates() {
+    let leaves = hex_fixture(
+        "forbid_duplicates.hex",
+        &[LEAVES[0], LEAVES[1], LEAVES[2], LEAVES[1]],
+    );
+
+    let output = merkle_root(&["-f", &leaves]);
+    assert!(output.status.success());
+
+    let output = merkle_root(&["-f", &leaves, "--forbid-duplicates"]);
+    assert_eq!(Some(2), output.status.code());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!("error: duplicate leaf on line 4\n", stderr);
+}
+
+#[test]
+fn paranoid() {
+    let leaves = hex_fixture("paranoid.hex", &LEAVES);
+    let output = merkle_root(&["-f", &leaves, "--paranoid"]);
+    assert!(output.status.success());
+
+    let zero = "0".repeat(64);
+    let leaves = hex_fixture("paranoid_zero.hex", &[&zero]);
+    let output = merkle_root(&["-f", &leaves]);
+    assert!(output.status.success());
+    let output = merkle_root(&["-f", &leaves, "--paranoid"]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!("error: degenerate root\n", stderr);
+
+    // the accumulator is checked the same way, before the state is saved
+    let state = fixture_dir("cli").join("paranoid.state");
+    let _ = std::fs::remove_file(&state);
+    let state = state.to_string_lossy();
+    let output = merkle_root(&["-f", &leaves, "--paranoid", "--save-state", &state]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!("error: degenerate root\n", stderr);
+    assert!(!std::path::Path::new(state.as_ref()).exists());
+
+    let output = merkle_root(&["-f", &leaves, "--paranoid", "--load-state", &state]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn resume_from_offset() {
+    let prefix = hex_fixture("resume_prefix.hex", &LEAVES[..2]);
+    let full = hex_fixture("resume_full.hex", &LEAVES);
+    let state = fixture("cli", "resume.state", "");
+
+    let output = merkle_root(&["-f", &prefix, "--save-state", &state]);
+    assert!(output.status.success());
+
+    let expected = merkle_root(&["-f", &full]);
+    let resumed = merkle_root(&[
+        "-f",
+        &full,
+        "--load-state",
+        &state,
+        "--resume-from-offset",
+        "130",
+    ]);
+    assert!(resumed.status.success());
+    assert_eq!(expected.stdout, resumed.stdout);
+
+    let output = merkle_root(&[
+        "-f",
+        &full,
+        "--load-state",
+        &state,
+        "--resume-from-offset",
+        "64",
+    ]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!("error: offset 64 is not on a line boundary\n", stderr);
+
+    // the offset is counted in the base16 lines only
+    for option in [&["--binary"][..], &["--separator", "whitespace"]] {
+        let mut args = vec![
+            "-f",
+            &full,
+            "--load-state",
+            &state,
+            "--resume-from-offset",
+            "130",
+        ];
+        args.extend(option);
+        assert_eq!(Some(2), merkle_root(&args).status.code());
+    }
+
+    let crlf: String = LEAVES.iter().map(|leaf| format!("{leaf}\r\n")).collect();
+    let crlf = fixture("cli", "resume_crlf.hex", &crlf);
+    let output = merkle_root(&[
+        "-f",
+        &crlf,
+        "--load-state",
+        &state,
+        "--resume-from-offset",
+        "130",
+    ]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        "error: line 1: seeking requires '\\n' line endings, got \"\\r\\n\"\n",
+        stderr
+    );
+}
+
+#[test]
+fn load_state_out_of_range() {
+    let leaves = hex_fixture("load_state_range.hex", &LEAVES);
+    let state = fixture(
+        "cli",
+        "load_state_range.state",
+        format!("70 {}\n", LEAVES[0]),
+    );
+
+    let output = merkle_root(&["-f", &leaves, "--load-state", &state]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        "error: invalid state: peak height 70 is out of range\n",
+        stderr
+    );
+}
+
+#[test]
+fn verify_frontier() {
+    let prefix = hex_fixture("frontier_prefix.hex", &LEAVES[..2]);
+    let extended = hex_fixture("frontier_extended.hex", &LEAVES);
+    let tampered = hex_fixture("frontier_tampered.hex", &[LEAVES[1], LEAVES[0], LEAVES[2]]);
+    let frontier = fixture("cli", "frontier.state", "");
+
+    let output = merkle_root(&["-f", &prefix, "--save-state", &frontier]);
+    assert!(output.status.success());
+
+    let expected = merkle_root(&["-f", &extended]);
+    let output = merkle_root(&["-f", &extended, "--verify-frontier", &frontier]);
+    assert!(output.status.success());
+    assert_eq!(expected.stdout, output.stdout);
+
+    let output = merkle_root(&["-f", &tampered, "--verify-frontier", &frontier]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        "error: the first 2 leaves do not match the frontier\n",
+        stderr
+    );
+
+    let out_of_range = fixture("cli", "frontier_range.state", format!("70 {}\n", LEAVES[0]));
+    let output = merkle_root(&["-f", &extended, "--verify-frontier", &out_of_range]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error: ") && !stderr.contains("panicked"));
+}
+
+#[test]
+fn profile() {
+    let file = hex_fixture("profile.hex", &LEAVES);
+
+    for mode in ["depth-walk", "width-walk"] {
+        let output = merkle_root(&["-f", &file, "-m", mode, "--profile"]);
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let stages: Vec<&str> = stderr
+            .lines()
+            .map(|line| {
+                let (stage, time) = line.split_at(10);
+                let millis = time.trim().strip_suffix(" ms").unwrap();
+                assert!(millis.parse::<f64>().is_ok(), "{line:?}");
+                stage.trim()
+            })
+            .collect();
+        match mode {
+            "depth-walk" => assert_eq!(vec!["read", "hash", "total"], stages),
+            _ => assert_eq!(vec!["read", "hash", "level 1", "level 2", "total"], stages),
+        }
+    }
+}
+
+#[test]
+fn normalize() {
+    let messy = format!(
+        "# leaves\r\n0x{}\r\n\n{}\n  {}  \r\n# end",
+        LEAVES[0].to_uppercase(),
+        LEAVES[1],
+        LEAVES[2]
+    );
+    let messy = fixture("cli", "normalize_messy.hex", &messy);
+
+    let output = merkle_root(&["normalize", &messy]);
+    assert!(output.status.success());
+    let expected: String = LEAVES.iter().map(|leaf| format!("{leaf}\n")).collect();
+    assert_eq!(expected.as_bytes(), output.stdout);
+
+    let broken = fixture(
+        "cli",
+        "normalize_broken.hex",
+        format!("{}\n0x12\n", LEAVES[0]),
+    );
+    let output = merkle_root(&["normalize", &broken]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!("error: line 2: expected 64 characters, got 2\n", stderr);
+}
+
+#[test]
+fn bad_line() {
+    let broken = "g000000000000000000000000000000000000000000000000000000000000000";
+    let leaves = hex_fixture("bad_line.hex", &[LEAVES[0], broken, LEAVES[2]]);
+    for mode in ["depth-walk", "width-walk"] {
+        let output = merkle_root(&["-f", &leaves, "-m", mode]);
+        assert_eq!(Some(2), output.status.code());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(
+            format!("error: line 2: invalid base16 string \"{broken}\"\n"),
+            stderr
+        );
+    }
+}
+
+#[test]
+fn skip_comments() {
+    let clean = hex_fixture("skip_comments_clean.hex", &LEAVES);
+    let commented = format!(
+        "# leaves\n\n{}\n{}\n  # the last one\n{}\n",
+        LEAVES[0], LEAVES[1], LEAVES[2]
+    );
+    let commented = fixture("cli", "skip_comments.hex", &commented);
+
+    let expected = merkle_root(&["-f", &clean]);
+    let output = merkle_root(&["-f", &commented, "--skip-comments"]);
+    assert!(output.status.success());
+    assert_eq!(expected.stdout, output.stdout);
+
+    let output = merkle_root(&["-f", &commented]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn separator() {
+    let clean = hex_fixture("separator_clean.hex", &LEAVES);
+    let spaced = format!("{} {}\t{}\n", LEAVES[0], LEAVES[1], LEAVES[2]);
+    let spaced = fixture("cli", "separator_spaced.hex", &spaced);
+
+    let expected = merkle_root(&["-f", &clean]);
+    let output = merkle_root(&["-f", &spaced, "--separator", "whitespace"]);
+    assert!(output.status.success());
+    assert_eq!(expected.stdout, output.stdout);
+
+    let output = merkle_root(&["-f", &spaced]);
+    assert_eq!(Some(2), output.status.code());
+    let output = merkle_root(&[
+        "-f",
+        &spaced,
+        "--separator",
+        "whitespace",
+        "-m",
+        "parallel-shards",
+    ]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn warnings() {
+    let clean = hex_fixture("warnings_clean.hex", &LEAVES);
+    let messy = format!(
+        "{}\r\n{}\n{}\n",
+        LEAVES[0],
+        LEAVES[1].to_uppercase(),
+        LEAVES[2]
+    );
+    let messy = fixture("cli", "warnings_messy.hex", &messy);
+
+    let expected = merkle_root(&["-f", &clean]);
+    let output = merkle_root(&["-f", &messy, "--warnings"]);
+    assert!(output.status.success());
+    assert_eq!(expected.stdout, output.stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        "warning: line 1: CRLF line ending\nwarning: line 2: uppercase hex\n",
+        stderr
+    );
+
+    let output = merkle_root(&["-f", &clean, "--warnings"]);
+    assert_eq!(expected.stdout, output.stdout);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn binary() {
+    let hex = hex_fixture("binary.hex", &LEAVES);
+    let expected = merkle_root(&["-f", &hex]);
+    assert!(expected.status.success());
+
+    let records: Vec<u8> = LEAVES.iter().flat_map(|leaf| decode(leaf)).collect();
+    let binary = fixture("cli", "binary.bin", &records);
+    let output = merkle_root(&["-f", &binary, "--binary"]);
+    assert!(output.status.success());
+    assert_eq!(expected.stdout, output.stdout);
+
+    let binary = fixture("cli", "binary.bin", &records[..records.len() - 1]);
+    let output = merkle_root(&["-f", &binary, "--binary"]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("record 3"));
+}
+
+#[test]
+fn selftest() {
+    let output = merkle_root(&["selftest"]);
+    assert_eq!(Some(0), output.status.code());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().count() > 1);
+    assert!(stdout.lines().all(|line| line.starts_with("pass\t")));
+}
+
+#[test]
+fn json_input() {
+    let json = format!(
+        "[\n  \"{}\",\n  \"{}\",\n  \"{}\"\n]\n",
+        LEAVES[0], LEAVES[1], LEAVES[2]
+    );
+    let json = fixture("cli", "json_input.json", &json);
+
+    let output = merkle_root(&["-f", &json, "--json-input"]);
+    assert!(output.status.success());
+    assert_eq!(
+        "f635de0509854cee47184a166eba580b37f9c64ad7e1bfe9c74cd3bb17119cdb\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let nested = fixture(
+        "cli",
+        "json_input_nested.json",
+        format!("[[\"{}\"]]", LEAVES[0]),
+    );
+    let output = merkle_root(&["-f", &nested, "--json-input"]);
+    assert_eq!(Some(2), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        "error: invalid JSON: element 1: expected a string, got an array\n",
+        stderr
+    );
+}
+
+#[test]
+fn audit() {
+    let leaves = hex_fixture("audit_leaves.hex", &LEAVES);
+    let root = "f635de0509854cee47184a166eba580b37f9c64ad7e1bfe9c74cd3bb17119cdb";
+    let candidates = hex_fixture(
+        "audit_candidates.hex",
+        &[LEAVES[0], LEAVES[1], root, LEAVES[2]],
+    );
+
+    let output = merkle_root(&["audit", &leaves, &candidates]);
+    assert_eq!(Some(0), output.status.code());
+    assert_eq!(
+        format!("3\t{root}\n"),
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let candidates = hex_fixture("audit_no_match.hex", &LEAVES);
+    let output = merkle_root(&["audit", &leaves, &candidates]);
+    assert_eq!(Some(1), output.status.code());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn mmr() {
+    let file = hex_fixture("mmr.hex", &LEAVES);
+    let leaves: Vec<Hash> = LEAVES.iter().map(|leaf| decode(leaf)).collect();
+    let peak = merkle_root::calc::hash(&leaves[0], Some(&leaves[1]));
+    let root = merkle_root::calc::hash(&peak, Some(&leaves[2]));
+
+    let output = merkle_root(&["-f", &file, "--mmr"]);
+    assert!(output.status.success());
+    assert_eq!(
+        format!(
+            "peak\t1\t{}\npeak\t0\t{}\nroot\t{}\n",
+            encode(&peak),
+            LEAVES[2],
+            encode(&root)
+        ),
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn diff_set() {
+    let mut sorted = LEAVES;
+    sorted.sort();
+    let d = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    let [a, b, c] = sorted;
+    let main = hex_fixture("diff_set_main.hex", &[a, b, c, d]);
+    let other = hex_fixture("diff_set_other.hex", &[b, d]);
+    let expected = hex_fixture("diff_set_expected.hex", &[a, c]);
+
+    let output = merkle_root(&["-f", &main, "--diff-set", &other]);
+    assert!(output.status.success());
+    assert_eq!(merkle_root(&["-f", &expected]).stdout, output.stdout);
+
+    let output = merkle_root(&["-f", &other, "--diff-set", &main]);
+    assert_eq!(Some(2), output.status.code());
+    assert_eq!(
+        "error: source is empty\n",
+        String::from_utf8(output.stderr).unwrap()
+    );
+}
+
+#[test]
+fn tag_source() {
+    let first = hex_fixture("tag_source_first.hex", &LEAVES);
+    let second = hex_fixture("tag_source_second.hex", &LEAVES);
+
+    let untagged = merkle_root(&["-f", &first]);
+    let first = merkle_root(&["-f", &first, "--tag-source"]);
+    let second = merkle_root(&["-f", &second, "--tag-source"]);
+    assert!(first.status.success() && second.status.success());
+    assert_ne!(untagged.stdout, first.stdout);
+    assert_ne!(first.stdout, second.stdout);
+
+    let tag = merkle_root::calc::source_tag("tag_source_first.hex", 3 * 65);
+    let mut tagged = vec![encode(&tag)];
+    tagged.extend(LEAVES.iter().map(|leaf| leaf.to_string()));
+    let tagged: Vec<&str> = tagged.iter().map(String::as_str).collect();
+    let expected = merkle_root(&["-f", &hex_fixture("tag_source_expected.hex", &tagged)]);
+    assert_eq!(expected.stdout, first.stdout);
+}
+
+#[test]
+fn dir() {
+    let dir = fixture_dir("cli").join("dir");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("a.txt"), "a").unwrap();
+    std::fs::write(dir.join("sub").join("b.txt"), "b").unwrap();
+    std::fs::write(dir.join("sub").join("c.txt"), "c").unwrap();
+
+    let output = merkle_root(&["--dir", &dir.to_string_lossy()]);
+    assert!(output.status.success());
+    assert_eq!(
+        "6d1aadec68a0d9271bb85fa86d345d455c9b349a5f5693f235ecc5851dc01203\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let output = merkle_root(&["--dir", &dir.to_string_lossy(), "--paranoid"]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn files() {
+    let dir = fixture_dir("cli").join("files");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    // created out of order, read sorted by name
+    for name in ["c.txt", "a.txt", "b.txt", "sub/d.txt"] {
+        std::fs::write(dir.join(name), name).unwrap();
+    }
+    let leaves: Vec<String> = ["a.txt", "b.txt", "c.txt"]
+        .iter()
+        .map(|name| encode(&Sha256::digest(name).into()))
+        .collect();
+    let leaves: Vec<&str> = leaves.iter().map(String::as_str).collect();
+    let expected = merkle_root(&["-f", &hex_fixture("files.hex", &leaves)]);
+
+    for mode in ["depth-walk", "width-walk", "parallel-shards"] {
+        let output = merkle_root(&["--files", &dir.to_string_lossy(), "-m", mode]);
+        assert!(output.status.success());
+        assert_eq!(expected.stdout, output.stdout);
+    }
+}
+
+#[test]
+fn stdin() {
+    let file = hex_fixture("stdin.hex", &LEAVES);
+    let expected = merkle_root(&["-f", &file]).stdout;
+    let contents = std::fs::read(&file).unwrap();
+    for args in [&[][..], &["-f", "-"][..]] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_merkle_root"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(&contents).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(expected, output.stdout);
+    }
+}
+
+#[test]
+fn odd_policy() {
+    let file = hex_fixture("odd_policy.hex", &LEAVES);
+    let leaves: Vec<Hash> = LEAVES.iter().map(|leaf| decode(leaf)).collect();
+    let ab = merkle_root::calc::hash(&leaves[0], Some(&leaves[1]));
+    let root = merkle_root::calc::hash(&ab, Some(&leaves[2]));
+
+    let output = merkle_root(&["-f", &file, "--odd-policy", "promote"]);
+    assert!(output.status.success());
+    assert_eq!(
+        format!("{}\n", encode(&root)),
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn strict_odd_policy() {
+    let file = hex_fixture("strict.hex", &LEAVES);
+    for mode in ["depth-walk", "width-walk", "parallel-shards"] {
+        let output = merkle_root(&["-f", &file, "-m", mode, "--odd-policy", "strict"]);
+        assert_eq!(Some(2), output.status.code());
+        assert!(output.stdout.is_empty());
+        assert_eq!(
+            "error: level 0 has the odd number of nodes 3\n",
+            String::from_utf8(output.stderr).unwrap()
+        );
+    }
+
+    let even = hex_fixture("strict_even.hex", &LEAVES[..2]);
+    let leaves: Vec<Hash> = LEAVES.iter().map(|leaf| decode(leaf)).collect();
+    let root = merkle_root::calc::hash(&leaves[0], Some(&leaves[1]));
+    let output = merkle_root(&["-f", &even, "--odd-policy", "strict"]);
+    assert!(output.status.success());
+    assert_eq!(
+        format!("{}\n", encode(&root)),
+        String::from_utf8(output.stdout).unwrap()
+    );
+
+    let output = merkle_root(&["-f", &file, "--odd-policy", "strict", "--window", "2"]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn dump_levels() {
+    let file = hex_fixture("dump_levels.hex", &LEAVES);
+    let dump = fixture_dir("cli").join("dump_levels.txt");
+    let dump = dump.to_string_lossy().into_owned();
+    let output = merkle_root(&["-f", &file, "--dump-levels", &dump]);
+    assert!(output.status.success());
+    let root = String::from_utf8(output.stdout).unwrap();
+
+    let leaves: Vec<Hash> = LEAVES.iter().map(|leaf| decode(leaf)).collect();
+    let ab = merkle_root::calc::hash(&leaves[0], Some(&leaves[1]));
+    let cc = merkle_root::calc::hash(&leaves[2], None);
+    let expected = format!(
+        "# level 0: 3 nodes\n{}\n{}\n{}\n# level 1: 2 nodes\n{}\n{}\n# level 2: root\n{}",
+        LEAVES[0],
+        LEAVES[1],
+        LEAVES[2],
+        encode(&ab),
+        encode(&cc),
+        root
+    );
+    assert_eq!(expected, std::fs::read_to_string(&dump).unwrap());
+}
+
+#[test]
+fn buffer_size() {
+    let file = hex_fixture("buffer_size.hex", &LEAVES);
+    let expected = merkle_root(&["-f", &file]);
+    assert!(expected.status.success());
+    for size in ["1", "64", "1048576"] {
+        let output = merkle_root(&["-f", &file, "--buffer-size", size]);
+        assert!(output.status.success());
+        assert_eq!(expected.stdout, output.stdout);
+    }
+
+    let output = merkle_root(&["-f", &file, "--buffer-size", "0"]);
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn gzip_not_enabled() {
+    // the truncated gzip stream, refused before decoding without the feature
+    let magic = [0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00];
+    let gz = fixture("cli", "gzip_not_enabled.hex.gz", magic);
+
+    for args in [vec!["-f", &gz], vec!["-f", &gz, "--compression", "gzip"]] {
+        let output = merkle_root(&args);
+        assert_eq!(Some(2), output.status.code());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        match cfg!(feature = "compression") {
+            true => assert!(stderr.starts_with("error: ")),
+            false => assert_eq!("error: compression support is not enabled\n", stderr),
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn gzip() {
+    let plain = hex_fixture("gzip.hex", &LEAVES);
+    let contents: String = LEAVES.iter().map(|leaf| format!("{leaf}\n")).collect();
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(contents.as_bytes()).unwrap();
+    let gz = fixture("cli", "gzip.hex.gz", gzip.finish().unwrap());
+
+    let expected = merkle_root(&["-f", &plain]);
+    assert!(expected.status.success());
+    for args in [vec!["-f", &gz], vec!["-f", &gz, "--compression", "gzip"]] {
+        let output = merkle_root(&args);
+        assert!(output.status.success());
+        assert_eq!(expected.stdout, output.stdout);
+    }
+}
+
+#[test]
+fn stats() {
+    let file = hex_fixture("stats.hex", &LEAVES);
+    for mode in ["depth-walk", "width-walk", "parallel-shards"] {
+        let output = merkle_root(&["-f", &file, "-m", mode, "--stats"]);
+        assert!(output.status.success());
+        assert_eq!(
+            1,
+            output.stdout.iter().filter(|&&byte| byte == b'\n').count()
+        );
+        assert_eq!(
+            "leaves             3\nheight             2\n",
+            String::from_utf8(output.stderr).unwrap()
+        );
+    }
+
+    let single = hex_fixture("stats_single.hex", &LEAVES[..1]);
+    let output = merkle_root(&["-f", &single, "--stats"]);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .ends_with("height             0\n"));
+}
+
+#[test]
+fn salt() {
+    let file = hex_fixture("salt.hex", &LEAVES);
+    let leaves: Vec<Hash> = LEAVES.iter().map(|leaf| decode(leaf)).collect();
+    let salted = merkle_root::calc::hash_with_salt(b"tree-a");
+    let ab = salted(&leaves[0], Some(&leaves[1]));
+    let root = salted(&ab, Some(&salted(&leaves[2], None)));
+
+    for mode in ["depth-walk", "width-walk", "parallel-shards"] {
+        let output = merkle_root(&["-f", &file, "-m", mode, "--salt", "tree-a"]);
+        assert!(output.status.success());
+        assert_eq!(
+            format!("{}\n", encode(&root)),
+            String::from_utf8(output.stdout).unwrap()
+        );
+    }
+
+    let output = merkle_root(&["-f", &file, "--salt", "tree-a", "--length-prefixed"]);
+    assert_eq!(Some(2), output.status.code());
+}